@@ -0,0 +1,696 @@
+// ローカルの Cargo で Rust を実行するバックエンド (eval / rrepl の "// deps:" 用)
+//
+// Piston の Rust は素の rustc なので外部クレートが使えない。
+// Cargo.toml 付きのリクエストは一時ディレクトリにプロジェクトを作り、ここでビルド・実行する。
+// レジストリとビルド結果は共有のキャッシュに置き、起動時に許可済みクレートを取ってきてビルドしておく。
+// リクエストのビルドはネットワークを使わず (--offline --locked)、取ってきたクレートだけで解決する。
+// そのため頼まれたバージョンが取ってきたもの (WARM_LOCK に記録) と合わなければ、ビルドの前に断る。
+// ビルドも実行も runner/sandbox.rs のサンドボックス (SANDBOX_*) の中で行い、環境変数は空にする
+// （Bot のトークンなどがビルドスクリプトや proc-macro から見えないように）。
+// キャッシュのうちレジストリ (home) は読み取り専用、ビルド結果 (target) だけ書き込めるようにする。
+//
+// 設定は環境変数で行う（既定では無効）
+// - CARGO_RUNNER: 1 / true で有効
+// - CARGO_RUNNER_CACHE: キャッシュの置き場所 (既定: data/cargo_cache)
+// - CARGO_RUNNER_CRATES: 使えるクレート (カンマ区切り, 既定: DEFAULT_CRATES)
+// - CARGO_RUNNER_BUILD_TIMEOUT_SECS: ビルドのタイムアウト秒数 (既定: 120)
+// - CARGO_RUNNER_RUN_TIMEOUT_SECS: 実行のタイムアウト秒数 (既定: 10)
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::json;
+use tokio::{
+    process::Command,
+    sync::{Mutex, OnceCell},
+};
+
+use crate::runner::sandbox::{self, File, Isolation, Limits, Stage, run_command, write_files};
+
+const DEFAULT_CACHE_PATH: &str = "data/cargo_cache";
+const DEFAULT_BUILD_TIMEOUT_SECS: u64 = 120;
+const DEFAULT_RUN_TIMEOUT_SECS: u64 = 10;
+// ビルドした実行ファイルのコピー先
+const BINARY: &str = "main.bin";
+const MANIFEST: &str = "Cargo.toml";
+// Cargo.toml の [package] で使える項目
+const PACKAGE_KEYS: &[&str] = &["name", "version", "edition"];
+// 依存クレートの指定で使える項目（path や git は不可）
+const DEPENDENCY_KEYS: &[&str] = &["version", "features", "default-features"];
+// 起動時に取ってきたクレートのバージョン（キャッシュに置く Cargo.lock）
+const WARM_LOCK: &str = "warm.lock";
+// 事前にビルドしておくクレートと、その指定。ここにないクレートは最新版 ("*") を取ってくる
+const DEFAULT_CRATES: &[(&str, &str)] = &[
+    ("serde", r#"{ version = "1", features = ["derive"] }"#),
+    ("serde_json", r#""1""#),
+    ("rand", r#""0.8""#),
+    ("regex", r#""1""#),
+    ("itertools", r#""0.13""#),
+    ("anyhow", r#""1""#),
+    ("thiserror", r#""1""#),
+    ("once_cell", r#""1""#),
+    ("num", r#""0.4""#),
+    ("chrono", r#""0.4""#),
+];
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub enabled: bool,
+    pub cache_path: PathBuf,
+    pub crates: Vec<String>,
+    pub build_timeout: Duration,
+    pub run_timeout: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cache_path: PathBuf::from(DEFAULT_CACHE_PATH),
+            crates: DEFAULT_CRATES.iter().map(|(c, _)| c.to_string()).collect(),
+            build_timeout: Duration::from_secs(DEFAULT_BUILD_TIMEOUT_SECS),
+            run_timeout: Duration::from_secs(DEFAULT_RUN_TIMEOUT_SECS),
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// 環境変数の読み方を差し替えられるようにしたもの（テスト用）
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let default = Self::default();
+        let secs = |key: &str, default: Duration| {
+            lookup(key)
+                .and_then(|s| s.trim().parse::<u64>().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(default)
+        };
+        Self {
+            enabled: lookup("CARGO_RUNNER")
+                .is_some_and(|s| matches!(s.trim().to_lowercase().as_str(), "1" | "true" | "yes")),
+            cache_path: lookup("CARGO_RUNNER_CACHE")
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .map_or(default.cache_path, PathBuf::from),
+            crates: lookup("CARGO_RUNNER_CRATES")
+                .map(|s| {
+                    s.split(',')
+                        .map(|c| c.trim().to_string())
+                        .filter(|c| !c.is_empty())
+                        .collect()
+                })
+                .unwrap_or(default.crates),
+            build_timeout: secs("CARGO_RUNNER_BUILD_TIMEOUT_SECS", default.build_timeout),
+            run_timeout: secs("CARGO_RUNNER_RUN_TIMEOUT_SECS", default.run_timeout),
+        }
+    }
+}
+
+/// Piston の /execute と同じ形のリクエスト（使う項目だけ）
+#[derive(Debug, Deserialize)]
+struct Request {
+    #[serde(default)]
+    files: Vec<File>,
+    #[serde(default)]
+    stdin: Option<String>,
+    #[serde(default)]
+    args: Vec<String>,
+    // ミリ秒
+    #[serde(default)]
+    run_timeout: Option<u64>,
+    // 色付きの出力にする (Piston にない項目)
+    #[serde(default)]
+    color: bool,
+}
+
+#[derive(Debug)]
+pub struct CargoRunner {
+    config: Config,
+    // ビルドした実行ファイルを動かすサンドボックス
+    sandbox: sandbox::Config,
+    // 共有の target ディレクトリでは同時に1つだけビルドする（成果物の名前が同じため）
+    build_lock: Mutex<()>,
+    // Rust のツールチェーンの場所 (rustc --print sysroot)。初回だけ調べる
+    sysroot: OnceCell<Option<PathBuf>>,
+}
+
+impl CargoRunner {
+    pub fn new(config: Config, sandbox: sandbox::Config) -> Self {
+        Self {
+            config,
+            sandbox,
+            build_lock: Mutex::new(()),
+            sysroot: OnceCell::new(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Piston の execute と同じく、リクエストを受けて応答を返す
+    pub async fn execute<Req: Serialize, Resp: DeserializeOwned>(
+        &self,
+        req: &Req,
+    ) -> Result<Resp, String> {
+        if !self.enabled() {
+            return Err(
+                "// deps: を使うにはローカル実行 (CARGO_RUNNER=1) を有効にしてください".to_string(),
+            );
+        }
+        let req: Request = serde_json::to_value(req)
+            .and_then(serde_json::from_value)
+            .map_err(|e| format!("リクエストが不正です: {e}"))?;
+        let manifest = req
+            .files
+            .iter()
+            .find(|f| f.name == MANIFEST)
+            .ok_or("Cargo.toml がありません")?;
+        let deps = self.check_manifest(&manifest.content)?;
+        self.check_files(&req.files)?;
+        self.check_versions(&deps)?;
+
+        let isolation = self.sandbox.isolation.resolve()?;
+
+        let dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+        write_files(dir.path(), &req.files).await?;
+
+        let (compile, built) = self.build(isolation, dir.path(), req.color).await?;
+        let run = if built {
+            let limits = Limits {
+                timeout: req
+                    .run_timeout
+                    .map(Duration::from_millis)
+                    .map_or(self.config.run_timeout, |t| t.min(self.config.run_timeout)),
+                ..self.sandbox.run
+            };
+            let mut argv = vec![format!("./{BINARY}")];
+            argv.extend(req.args.iter().cloned());
+            let env = if req.color { sandbox::COLOR_ENV } else { &[] };
+            let cmd = sandbox::jail(isolation, dir.path(), &[], &[], env, limits, &argv);
+            Some(run_command(cmd, req.stdin.as_deref(), limits.timeout).await?)
+        } else {
+            None
+        };
+        let resp = json!({
+            "language": "rust",
+            "version": "cargo",
+            "compile": compile,
+            "run": run,
+        });
+        serde_json::from_value(resp).map_err(|e| e.to_string())
+    }
+
+    /// プロジェクトのファイルを確かめる。Cargo.toml と src/ の下だけ受け取る
+    /// （build.rs や .cargo/config.toml でビルドの手順を変えられないように）
+    fn check_files(&self, files: &[File]) -> Result<(), String> {
+        match files
+            .iter()
+            .find(|f| f.name != MANIFEST && !f.name.starts_with("src/"))
+        {
+            Some(file) => Err(format!("使えないファイル名です: {}", file.name)),
+            None => Ok(()),
+        }
+    }
+
+    /// Cargo.toml を読んで、許可されていない項目・クレートを拒否する。
+    /// 使えるのは [package] の基本的な項目と、[dependencies] の許可済みクレートの
+    /// version / features / default-features だけ。依存クレートの (名前, バージョンの指定) を返す
+    fn check_manifest(&self, manifest: &str) -> Result<Vec<(String, String)>, String> {
+        let manifest: toml::Table = manifest
+            .parse()
+            .map_err(|e| format!("Cargo.toml が読めません: {e}"))?;
+        let unknown = |key: &str| format!("Cargo.toml の {key} は使えません");
+        let mut denied = Vec::new();
+        let mut deps = Vec::new();
+        for (key, value) in &manifest {
+            let table = value.as_table().ok_or_else(|| unknown(key))?;
+            match key.as_str() {
+                "package" => {
+                    if let Some(key) = table.keys().find(|k| !PACKAGE_KEYS.contains(&k.as_str())) {
+                        return Err(unknown(&format!("package.{key}")));
+                    }
+                }
+                "dependencies" => {
+                    for (name, spec) in table {
+                        let version = match spec {
+                            toml::Value::String(v) => Some(v.as_str()),
+                            toml::Value::Table(spec)
+                                if spec.keys().all(|k| DEPENDENCY_KEYS.contains(&k.as_str())) =>
+                            {
+                                match spec.get("version") {
+                                    None => Some("*"),
+                                    Some(v) => v.as_str(),
+                                }
+                            }
+                            _ => None,
+                        };
+                        let Some(version) = version else {
+                            return Err(unknown(&format!("dependencies.{name}")));
+                        };
+                        deps.push((name.clone(), version.to_string()));
+                        if !self.config.crates.iter().any(|c| c == name) {
+                            denied.push(name.as_str());
+                        }
+                    }
+                }
+                _ => return Err(unknown(key)),
+            }
+        }
+        if denied.is_empty() {
+            Ok(deps)
+        } else {
+            Err(format!(
+                "使えないクレートです: {}（使えるもの: {}）",
+                denied.join(", "),
+                self.config.crates.join(", ")
+            ))
+        }
+    }
+
+    /// 頼まれたバージョンが起動時に取ってきたものにあるか確かめる
+    /// （ビルドはオフラインなので、無いとわかりにくいエラーになる）
+    fn check_versions(&self, deps: &[(String, String)]) -> Result<(), String> {
+        if deps.is_empty() {
+            return Ok(());
+        }
+        let cached = self.warmed_versions().ok_or(
+            "クレートのキャッシュがまだありません（起動時の準備が終わるまでお待ちください）",
+        )?;
+        for (name, version) in deps {
+            let req = semver::VersionReq::parse(version)
+                .map_err(|_| format!("{name} のバージョン指定が読めません: {version}"))?;
+            let versions = cached.get(name).map(Vec::as_slice).unwrap_or_default();
+            if !versions.iter().any(|v| req.matches(v)) {
+                let available: Vec<String> = versions.iter().map(|v| v.to_string()).collect();
+                return Err(format!(
+                    "{name} = \"{version}\" は使えません（使えるバージョン: {}）",
+                    if available.is_empty() {
+                        "なし".to_string()
+                    } else {
+                        available.join(", ")
+                    }
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// 起動時に取ってきたクレートのバージョン（名前 → バージョン）。まだ無ければ None
+    fn warmed_versions(&self) -> Option<HashMap<String, Vec<semver::Version>>> {
+        let lock = std::fs::read_to_string(self.cache().join(WARM_LOCK)).ok()?;
+        let lock: toml::Table = lock.parse().ok()?;
+        let mut versions: HashMap<String, Vec<semver::Version>> = HashMap::new();
+        for package in lock.get("package")?.as_array()? {
+            let name = package.get("name").and_then(toml::Value::as_str);
+            let version = package
+                .get("version")
+                .and_then(toml::Value::as_str)
+                .and_then(|v| semver::Version::parse(v).ok());
+            if let (Some(name), Some(version)) = (name, version) {
+                versions.entry(name.to_string()).or_default().push(version);
+            }
+        }
+        Some(versions)
+    }
+
+    /// 起動時に取ってくるプロジェクトの [dependencies]。設定されたクレートを全部使う
+    fn warmup_deps(&self) -> String {
+        self.config
+            .crates
+            .iter()
+            .map(|name| {
+                let spec = DEFAULT_CRATES
+                    .iter()
+                    .find(|(c, _)| c == name)
+                    .map_or(r#""*""#, |(_, spec)| spec);
+                format!("{name} = {spec}\n")
+            })
+            .collect()
+    }
+
+    /// キャッシュの場所。プロジェクトのディレクトリで cargo を動かすので絶対パスにする
+    fn cache(&self) -> PathBuf {
+        std::path::absolute(&self.config.cache_path).unwrap_or(self.config.cache_path.clone())
+    }
+
+    async fn sysroot(&self) -> Result<&Path, String> {
+        self.sysroot
+            .get_or_init(|| async {
+                sandbox::probe("rustc", &["--print", "sysroot"])
+                    .await
+                    .map(PathBuf::from)
+            })
+            .await
+            .as_deref()
+            .ok_or_else(|| "Rust のツールチェーンが見つかりません".to_string())
+    }
+
+    /// ツールチェーンの cargo をサンドボックスの中で、キャッシュの設定だけの環境変数で動かすコマンド
+    fn cargo(
+        &self,
+        isolation: Isolation,
+        sysroot: &Path,
+        project: &Path,
+        color: bool,
+        args: &[&str],
+    ) -> Command {
+        let (home, target) = (self.cache().join("home"), self.cache().join("target"));
+        let path = |p: &Path| p.to_string_lossy().into_owned();
+        let env = [
+            ("RUSTC", path(&sysroot.join("bin/rustc"))),
+            ("CARGO_HOME", path(&home)),
+            ("CARGO_TARGET_DIR", path(&target)),
+            (
+                "CARGO_TERM_COLOR",
+                if color { "always" } else { "never" }.to_string(),
+            ),
+        ];
+        let env: Vec<(&str, &str)> = env.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        let limits = Limits {
+            timeout: self.config.build_timeout,
+            ..self.sandbox.compile
+        };
+        let mut argv = vec![path(&sysroot.join("bin/cargo"))];
+        argv.extend(args.iter().map(|a| a.to_string()));
+        sandbox::jail(
+            isolation,
+            project,
+            &[sysroot.to_path_buf(), home],
+            &[target],
+            &env,
+            limits,
+            &argv,
+        )
+    }
+
+    /// 温めたキャッシュのクレートだけでビルドして、成功したら実行ファイルをプロジェクトに BINARY としてコピーする
+    async fn build(
+        &self,
+        isolation: Isolation,
+        project: &Path,
+        color: bool,
+    ) -> Result<(Stage, bool), String> {
+        let _lock = self.build_lock.lock().await;
+        let sysroot = self.sysroot().await?;
+        let timeout = self.config.build_timeout;
+        tokio::fs::create_dir_all(self.cache().join("target"))
+            .await
+            .map_err(|e| e.to_string())?;
+        // Cargo.lock はキャッシュにあるバージョンだけで作る
+        let cmd = self.cargo(
+            isolation,
+            sysroot,
+            project,
+            color,
+            &["generate-lockfile", "--offline", "--quiet"],
+        );
+        let stage = run_command(cmd, None, timeout).await?;
+        if !stage.succeeded() {
+            return Ok((stage, false));
+        }
+        let cmd = self.cargo(
+            isolation,
+            sysroot,
+            project,
+            color,
+            &["build", "--release", "--offline", "--locked", "--quiet"],
+        );
+        let stage = run_command(cmd, None, timeout).await?;
+        if !stage.succeeded() {
+            return Ok((stage, false));
+        }
+        let built = self.cache().join("target/release/main");
+        tokio::fs::copy(&built, project.join(BINARY))
+            .await
+            .map_err(|e| format!("実行ファイルが見つかりません: {e}"))?;
+        Ok((stage, true))
+    }
+
+    /// 許可済みクレートを取ってくる。ここだけネットワークを使う（ビルドはしないのでコードは動かない）
+    async fn fetch(&self, project: &Path) -> Result<(), String> {
+        let sysroot = self.sysroot().await?;
+        let mut cmd = Command::new(sysroot.join("bin/cargo"));
+        cmd.args(["fetch", "--quiet"])
+            .current_dir(project)
+            .env("CARGO_HOME", self.cache().join("home"));
+        let stage = run_command(cmd, None, self.config.build_timeout).await?;
+        if stage.succeeded() {
+            Ok(())
+        } else {
+            Err(stage.stderr)
+        }
+    }
+
+    /// 許可済みクレートを全部使うプロジェクトを取ってきてビルドして、キャッシュを温めておく
+    pub fn spawn_warmup(&'static self) {
+        if !self.enabled() {
+            return;
+        }
+        tokio::spawn(async move {
+            let deps = self.warmup_deps();
+            let result = async {
+                let dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+                let manifest = format!(
+                    "[package]\nname = \"main\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\n{deps}"
+                );
+                std::fs::write(dir.path().join("Cargo.toml"), manifest).map_err(|e| e.to_string())?;
+                std::fs::create_dir_all(dir.path().join("src")).map_err(|e| e.to_string())?;
+                std::fs::write(dir.path().join("src/main.rs"), "fn main() {}")
+                    .map_err(|e| e.to_string())?;
+                self.fetch(dir.path()).await?;
+                // 取ってきたバージョンを覚えておき、リクエストの指定と照らし合わせる
+                std::fs::create_dir_all(self.cache()).map_err(|e| e.to_string())?;
+                std::fs::copy(dir.path().join("Cargo.lock"), self.cache().join(WARM_LOCK))
+                    .map_err(|e| e.to_string())?;
+                let isolation = self.sandbox.isolation.resolve()?;
+                let (stage, _) = self.build(isolation, dir.path(), false).await?;
+                match stage.code {
+                    Some(0) => Ok(()),
+                    _ => Err(stage.stderr),
+                }
+            }
+            .await;
+            match result {
+                Ok(()) => println!("クレートのキャッシュを準備しました"),
+                Err(e) => println!("クレートのキャッシュの準備に失敗: {e}"),
+            }
+        });
+    }
+}
+
+static CLIENT: Lazy<CargoRunner> =
+    Lazy::new(|| CargoRunner::new(Config::from_env(), sandbox::Config::from_env()));
+
+/// 環境変数の設定で作った共有のランナー
+pub fn client() -> &'static CargoRunner {
+    &CLIENT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn unjailed() -> sandbox::Config {
+        sandbox::Config {
+            isolation: Isolation::None,
+            ..sandbox::Config::default()
+        }
+    }
+
+    #[test]
+    fn config_from_lookup() {
+        let c = Config::from_lookup(|_| None);
+        assert!(!c.enabled);
+        assert_eq!(c.crates.len(), DEFAULT_CRATES.len());
+
+        let c = Config::from_lookup(|key| match key {
+            "CARGO_RUNNER" => Some("true".to_string()),
+            "CARGO_RUNNER_CRATES" => Some("rand, serde ,".to_string()),
+            "CARGO_RUNNER_RUN_TIMEOUT_SECS" => Some("3".to_string()),
+            _ => None,
+        });
+        assert!(c.enabled);
+        assert_eq!(c.crates, ["rand", "serde"]);
+        assert_eq!(c.run_timeout, Duration::from_secs(3));
+    }
+
+    #[tokio::test]
+    async fn rejects_before_building() {
+        let req = |manifest: &str, name: &str| json!({"files": [{"name": name, "content": ""}, {"name": "Cargo.toml", "content": manifest}]});
+        let off = CargoRunner::new(Config::default(), unjailed());
+        assert!(
+            off.execute::<_, Value>(&req("", "src/main.rs"))
+                .await
+                .is_err()
+        );
+
+        let on = CargoRunner::new(
+            Config {
+                enabled: true,
+                crates: vec!["rand".to_string()],
+                ..Config::default()
+            },
+            unjailed(),
+        );
+        let err = on
+            .execute::<_, Value>(&req(
+                "[dependencies]\nrand = \"0.8\"\ntokio = \"1\"\n",
+                "src/main.rs",
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(err, "使えないクレートです: tokio（使えるもの: rand）");
+        let err = on
+            .execute::<_, Value>(&req("[dependencies]\nrand = \"0.8\"\n", "../main.rs"))
+            .await
+            .unwrap_err();
+        assert_eq!(err, "使えないファイル名です: ../main.rs");
+        let err = on
+            .execute::<_, Value>(&req("[dependencies]\nrand = \"0.8\"\n", "build.rs"))
+            .await
+            .unwrap_err();
+        assert_eq!(err, "使えないファイル名です: build.rs");
+
+        // [dependencies] 以外の書き方や、許可済みクレートの path / git も拒否する
+        for (manifest, expected) in [
+            (
+                "[dependencies.tokio]\nversion = \"1\"\n",
+                "使えないクレートです: tokio（使えるもの: rand）",
+            ),
+            (
+                "[build-dependencies]\nrand = \"0.8\"\n",
+                "Cargo.toml の build-dependencies は使えません",
+            ),
+            (
+                "[target.'cfg(unix)'.dependencies]\ntokio = \"1\"\n",
+                "Cargo.toml の target は使えません",
+            ),
+            (
+                "[patch.crates-io]\nrand = { path = \"/\" }\n",
+                "Cargo.toml の patch は使えません",
+            ),
+            (
+                "[package]\nname = \"main\"\nbuild = \"/tmp/x.rs\"\n",
+                "Cargo.toml の package.build は使えません",
+            ),
+            (
+                "[dependencies]\nrand = { git = \"https://example.com/rand\" }\n",
+                "Cargo.toml の dependencies.rand は使えません",
+            ),
+            (
+                "[dependencies]\nrand = { version = \"0.8\", path = \"/\" }\n",
+                "Cargo.toml の dependencies.rand は使えません",
+            ),
+        ] {
+            let err = on
+                .execute::<_, Value>(&req(manifest, "src/main.rs"))
+                .await
+                .unwrap_err();
+            assert_eq!(err, expected, "{manifest}");
+        }
+    }
+
+    #[tokio::test]
+    async fn checks_versions_against_cache() {
+        let cache = tempfile::tempdir().unwrap();
+        let runner = CargoRunner::new(
+            Config {
+                enabled: true,
+                crates: vec!["rand".to_string()],
+                cache_path: cache.path().to_path_buf(),
+                ..Config::default()
+            },
+            unjailed(),
+        );
+        let check = |manifest: &str| {
+            let deps = runner.check_manifest(manifest)?;
+            runner.check_versions(&deps)
+        };
+        // 依存が無ければキャッシュが無くてもよい
+        assert_eq!(check("[dependencies]\n"), Ok(()));
+        assert_eq!(
+            check("[dependencies]\nrand = \"0.8\"\n").unwrap_err(),
+            "クレートのキャッシュがまだありません（起動時の準備が終わるまでお待ちください）"
+        );
+
+        std::fs::write(
+            cache.path().join(WARM_LOCK),
+            "version = 4\n\n[[package]]\nname = \"rand\"\nversion = \"0.8.5\"\n",
+        )
+        .unwrap();
+        assert_eq!(check("[dependencies]\nrand = \"0.8\"\n"), Ok(()));
+        assert_eq!(
+            check("[dependencies]\nrand = { features = [\"small_rng\"] }\n"),
+            Ok(())
+        );
+        assert_eq!(
+            check("[dependencies]\nrand = \"0.9\"\n").unwrap_err(),
+            "rand = \"0.9\" は使えません（使えるバージョン: 0.8.5）"
+        );
+        assert_eq!(
+            check("[dependencies]\nrand = { version = \"latest\" }\n").unwrap_err(),
+            "rand のバージョン指定が読めません: latest"
+        );
+    }
+
+    #[test]
+    fn warms_every_configured_crate() {
+        let runner = CargoRunner::new(
+            Config {
+                crates: vec!["rand".to_string(), "bitflags".to_string()],
+                ..Config::default()
+            },
+            unjailed(),
+        );
+        let rand = DEFAULT_CRATES.iter().find(|(c, _)| *c == "rand").unwrap().1;
+        assert_eq!(
+            runner.warmup_deps(),
+            format!("rand = {rand}\nbitflags = \"*\"\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn builds_and_runs_project() {
+        let cache = tempfile::tempdir().unwrap();
+        let runner = CargoRunner::new(
+            Config {
+                enabled: true,
+                cache_path: cache.path().to_path_buf(),
+                ..Config::default()
+            },
+            unjailed(),
+        );
+        let manifest = "[package]\nname = \"main\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\n";
+        let req = |main: &str| {
+            json!({
+                "files": [
+                    {"name": "src/main.rs", "content": main},
+                    {"name": "Cargo.toml", "content": manifest},
+                ],
+                "stdin": "hi",
+                "args": ["x"],
+            })
+        };
+        let res: Value = runner
+            .execute(&req("fn main() { let mut s = String::new(); std::io::stdin().read_line(&mut s).unwrap(); print!(\"{s}{}{}\", std::env::args().nth(1).unwrap(), option_env!(\"HOME\").unwrap_or(\"\")); }"))
+            .await
+            .unwrap();
+        assert_eq!(res["compile"]["code"], 0);
+        // ビルド中も Bot の環境変数 (HOME など) は見えない
+        assert_eq!(res["run"]["stdout"], "hix");
+
+        let res: Value = runner.execute(&req("fn main() { oops }")).await.unwrap();
+        assert_ne!(res["compile"]["code"], 0);
+        assert!(res["run"].is_null());
+    }
+}
@@ -1,32 +1,165 @@
 // コマンド用モジュール: 各コマンドのハンドラと共通項目を公開
 
+pub mod ansi;
+pub mod args;
+pub mod reply;
+
 pub mod get;
 pub mod gpt;
+pub mod gpt_config;
 pub mod help;
 pub mod hukidashi;
 pub mod ping;
 pub mod post;
+pub mod summarize;
 pub mod tex;
 
 pub mod eval;
 pub mod rust_repl_cmd;
+pub mod rust_tools;
 
 // プレフィックスはここで設定（後で環境変数などで変更可能）
 pub const PREFIX: &str = "!";
 
-use serenity::builder::CreateCommand;
+use serenity::{
+    async_trait,
+    builder::{
+        CreateCommand, CreateCommandOption, CreateInteractionResponse,
+        CreateInteractionResponseMessage,
+    },
+    model::{
+        application::{CommandInteraction, ComponentInteraction, ModalInteraction},
+        channel::Message,
+    },
+    prelude::Context,
+};
+
+/// 全コマンド共通のインターフェース。
+/// プレフィックス版・スラッシュ版のディスパッチとスラッシュコマンド登録はすべてここを経由する。
+#[async_trait]
+pub trait Command: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    /// スラッシュコマンドのオプション定義
+    fn options(&self) -> Vec<CreateCommandOption> {
+        Vec::new()
+    }
+
+    /// プレフィックス版で位置引数にせず、key:value / --name でのみ指定できるオプション名
+    /// （値の形からは他の位置引数と区別できないもの）
+    fn named_only(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// スラッシュコマンドのメタデータ（通常は上書き不要）
+    fn register(&self) -> CreateCommand {
+        CreateCommand::new(self.name())
+            .description(self.description())
+            .set_options(self.options())
+    }
+
+    /// プレフィックス版: !<name> ...
+    /// 未対応のコマンドは何もしない
+    async fn run(&self, _ctx: &Context, _msg: &Message) -> serenity::Result<()> {
+        Ok(())
+    }
+
+    /// スラッシュ版: /<name> ...
+    async fn slash_execute(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> serenity::Result<()> {
+        respond(ctx, command, "未対応のコマンドです").await
+    }
+
+    /// スラッシュコマンドの入力補完（`set_autocomplete(true)` のオプションがある場合のみ呼ばれる）
+    async fn autocomplete(
+        &self,
+        _ctx: &Context,
+        _command: &CommandInteraction,
+    ) -> serenity::Result<()> {
+        Ok(())
+    }
+
+    /// モーダルの送信（custom_id が "<name>:..." のもの）
+    async fn modal_submit(
+        &self,
+        _ctx: &Context,
+        _modal: &ModalInteraction,
+    ) -> serenity::Result<()> {
+        Ok(())
+    }
+
+    /// ボタンなどのコンポーネント操作（custom_id が "<name>:..." のもの）
+    async fn component(
+        &self,
+        _ctx: &Context,
+        _component: &ComponentInteraction,
+    ) -> serenity::Result<()> {
+        Ok(())
+    }
+}
+
+// コマンドの登録はここだけ（プレフィックス・スラッシュ両方に反映される）
+pub static COMMANDS: &[&dyn Command] = &[
+    &ping::Ping,
+    &help::Help,
+    &tex::Tex,
+    &rust_repl_cmd::RustRepl,
+    &rust_tools::RustTools,
+    &get::Get,
+    &post::Post,
+    &gpt::Gpt,
+    &gpt_config::GptConfig,
+    &summarize::Summarize,
+    &eval::Eval,
+    &hukidashi::Hukidashi,
+];
+
+/// 名前からコマンドを検索
+pub fn find(name: &str) -> Option<&'static dyn Command> {
+    COMMANDS.iter().copied().find(|c| c.name() == name)
+}
+
+/// "!<command> ..." のコマンド名。プレフィックスで始まらなければ None
+pub fn command_name(content: &str) -> Option<&str> {
+    content
+        .trim()
+        .strip_prefix(PREFIX)?
+        .split_whitespace()
+        .next()
+}
+
+/// モーダル・ボタンなどの custom_id。先頭のコマンド名でディスパッチする
+pub fn custom_id(cmd: &dyn Command, rest: &str) -> String {
+    format!("{}:{rest}", cmd.name())
+}
+
+/// custom_id を (コマンド名, 残り) に分ける
+pub fn split_custom_id(custom_id: &str) -> (&str, &str) {
+    custom_id.split_once(':').unwrap_or((custom_id, ""))
+}
 
 // スラッシュコマンド定義を集約（起動時に自動登録するため）
 pub fn slash_commands() -> Vec<CreateCommand> {
-    vec![
-        ping::slash_register(),
-        help::slash_register(),
-        tex::slash_register(),
-        rust_repl_cmd::slash_register(),
-        get::slash_register(),
-        post::slash_register(),
-        gpt::slash_register(),
-        eval::slash_register(),
-        hukidashi::slash_register(),
-    ]
+    COMMANDS.iter().map(|c| c.register()).collect()
+}
+
+/// スラッシュコマンドへテキストで即時応答する
+pub async fn respond(
+    ctx: &Context,
+    command: &CommandInteraction,
+    content: impl Into<String>,
+) -> serenity::Result<()> {
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new().content(content),
+            ),
+        )
+        .await
 }
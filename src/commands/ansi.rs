@@ -0,0 +1,191 @@
+// プログラムの出力に含まれる ANSI エスケープシーケンスを Discord 向けにする
+//
+// Discord の ```ansi ブロックが表示できる SGR は一部だけ:
+// 0 (リセット), 1 (太字), 4 (下線), 30-37 (文字色), 40-47 (背景色)。
+// 明るい色 (90-97) や 256色・24bit 色は近い基本色に寄せ、それ以外の属性や
+// カーソル移動などのシーケンスは取り除く。
+
+const ESC: char = '\x1b';
+
+/// 表示できる属性
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Style {
+    bold: bool,
+    underline: bool,
+    // 0-7 (黒, 赤, 緑, 黄, 青, マゼンタ, シアン, 白)
+    fg: Option<u8>,
+    bg: Option<u8>,
+}
+
+impl Style {
+    /// この属性にする SGR。前の属性は 0 でリセットしてから付け直す
+    fn sgr(&self) -> String {
+        let mut codes = vec!["0".to_string()];
+        if self.bold {
+            codes.push("1".to_string());
+        }
+        if self.underline {
+            codes.push("4".to_string());
+        }
+        if let Some(fg) = self.fg {
+            codes.push((30 + fg).to_string());
+        }
+        if let Some(bg) = self.bg {
+            codes.push((40 + bg).to_string());
+        }
+        format!("{ESC}[{}m", codes.join(";"))
+    }
+
+    fn apply(&mut self, params: &str) {
+        let mut codes = params
+            .split([';', ':'])
+            .map(|p| p.parse::<u16>().unwrap_or(0));
+        while let Some(code) = codes.next() {
+            match code {
+                0 => *self = Self::default(),
+                1 => self.bold = true,
+                22 => self.bold = false,
+                4 => self.underline = true,
+                24 => self.underline = false,
+                30..=37 => self.fg = Some((code - 30) as u8),
+                90..=97 => self.fg = Some((code - 90) as u8),
+                39 => self.fg = None,
+                40..=47 => self.bg = Some((code - 40) as u8),
+                100..=107 => self.bg = Some((code - 100) as u8),
+                49 => self.bg = None,
+                38 | 48 => {
+                    let color = match codes.next() {
+                        Some(5) => codes.next().map(from_256),
+                        Some(2) => {
+                            let mut rgb = || codes.next().unwrap_or(0);
+                            Some(nearest(rgb(), rgb(), rgb()))
+                        }
+                        _ => None,
+                    };
+                    if code == 38 {
+                        self.fg = color;
+                    } else {
+                        self.bg = color;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// 24bit 色を近い基本色にする
+fn nearest(r: u16, g: u16, b: u16) -> u8 {
+    u8::from(r >= 128) | (u8::from(g >= 128) << 1) | (u8::from(b >= 128) << 2)
+}
+
+/// 256色を近い基本色にする
+fn from_256(n: u16) -> u8 {
+    match n {
+        0..=7 => n as u8,
+        8..=15 => (n - 8) as u8,
+        // 6x6x6 の色立方体
+        16..=231 => {
+            let n = n - 16;
+            let level = |v: u16| v * 51;
+            nearest(level(n / 36), level(n / 6 % 6), level(n % 6))
+        }
+        // グレー
+        _ if n < 244 => 0,
+        _ => 7,
+    }
+}
+
+/// Discord の ```ansi で表示できるシーケンスだけにする
+pub fn to_discord(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let (mut current, mut shown) = (Style::default(), Style::default());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != ESC {
+            // 属性の変化は次に文字を出すときにまとめて書く
+            if current != shown {
+                out.push_str(&current.sgr());
+                shown = current;
+            }
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            // CSI: ESC [ パラメーター 終端文字
+            Some('[') => {
+                let mut params = String::new();
+                for c in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        if c == 'm' {
+                            current.apply(&params);
+                        }
+                        break;
+                    }
+                    params.push(c);
+                }
+            }
+            // OSC (リンクやタイトル): BEL か ESC \ まで
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == ESC && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            // その他の2文字のシーケンス
+            _ => {}
+        }
+    }
+    if shown != Style::default() {
+        out.push_str(&format!("{ESC}[0m"));
+    }
+    out
+}
+
+/// エスケープシーケンスを全部取り除く
+pub fn strip(s: &str) -> String {
+    to_discord(s)
+        .split(ESC)
+        .enumerate()
+        .map(|(i, part)| match i {
+            0 => part,
+            // 残っているのは "[...m" だけ
+            _ => part.split_once('m').map_or("", |(_, rest)| rest),
+        })
+        .collect()
+}
+
+/// 色が付いているか
+pub fn is_colored(s: &str) -> bool {
+    s.contains(ESC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_to_discord_subset() {
+        // rustc のエラー: 太字の明るい赤 → 太字の赤
+        let rustc =
+            "\x1b[0m\x1b[1m\x1b[38;5;9merror[E0425]\x1b[0m\x1b[0m\x1b[1m: cannot find\x1b[0m\n";
+        assert_eq!(
+            to_discord(rustc),
+            "\x1b[0;1;31merror[E0425]\x1b[0;1m: cannot find\x1b[0m\n"
+        );
+        assert_eq!(strip(rustc), "error[E0425]: cannot find\n");
+
+        // 24bit 色・背景・属性の解除
+        assert_eq!(
+            to_discord("\x1b[38;2;0;200;0;44mok\x1b[39;22mx"),
+            "\x1b[0;32;44mok\x1b[0;44mx\x1b[0m"
+        );
+        // 表示できないもの (カーソル移動, 斜体, OSC のリンク) は消える
+        assert_eq!(
+            to_discord("\x1b[2K\x1b[3ma\x1b]8;;http://x\x1b\\b\x1b]8;;\x07"),
+            "ab"
+        );
+        assert!(!is_colored(&to_discord("plain\n")));
+    }
+}
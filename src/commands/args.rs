@@ -0,0 +1,875 @@
+// プレフィックス/スラッシュ共通の引数パーサー
+//
+// スラッシュコマンド登録に使う `CreateCommandOption` をそのままスキーマとして使い、
+// `!cmd ...` のテキストからも `/cmd` と同じ形の `Args` を組み立てる。
+//
+// プレフィックス版で使える書き方:
+// - 位置引数: スキーマ順に割り当て。最後の引数は残り全部を受け取る
+//   (コマンドが named_only に挙げたオプションは位置引数にならない)
+// - "quoted string" / 'quoted string'
+// - --flag value / --flag (Boolean のみ値省略可)
+// - key:value
+// - ```lang\ncode``` (code オプションへ優先的に割り当て)
+//   コードブロックが無いときは、code に入る最初の語から後ろは全部コードとして読む
+//   (`let args: Vec<String>` の args: をオプションと取り違えないように)
+// - {"json": "object"} / [1, 2] (括弧が閉じるまで1トークン)
+
+use std::collections::HashMap;
+
+use serenity::{
+    builder::CreateCommandOption,
+    model::{
+        application::{
+            CommandInteraction, CommandOption, CommandOptionType, ResolvedOption, ResolvedValue,
+        },
+        channel::{Attachment, Message},
+    },
+};
+
+use super::Command;
+
+/// コードブロックを優先的に受け取るオプション名
+pub const CODE: &str = "code";
+
+/// テキスト引数のスキーマ。スラッシュ版のオプション定義に、プレフィックス版だけの指定を足したもの
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    options: Vec<CommandOption>,
+    // 値の形からは位置引数と区別できないため、key:value / --name でのみ指定できるオプション名
+    named_only: Vec<String>,
+}
+
+impl Schema {
+    /// `names` のオプションを key:value / --name でのみ指定できるようにする
+    pub fn named_only(mut self, names: &[&str]) -> Self {
+        self.named_only = names.iter().map(|n| n.to_string()).collect();
+        self
+    }
+
+    fn is_named_only(&self, opt: &CommandOption) -> bool {
+        self.named_only.contains(&opt.name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ArgValue {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    // スラッシュコマンドでのみ指定できる
+    Attachment(Box<Attachment>),
+}
+
+/// パース済みの引数
+#[derive(Debug, Clone, Default)]
+pub struct Args {
+    values: HashMap<String, ArgValue>,
+    // オプション名 → コードブロックの言語タグ
+    fence_langs: HashMap<String, String>,
+    // スラッシュ版のサブコマンド名
+    subcommand: Option<String>,
+}
+
+/// コマンドごとの型付き引数
+pub trait FromArgs: Sized {
+    fn from_args(args: &Args) -> Result<Self, String>;
+}
+
+/// `!name ...` から型付き引数を取り出す
+pub fn from_message<T: FromArgs>(cmd: &dyn Command, msg: &Message) -> Result<T, String> {
+    T::from_args(&Args::from_message(cmd, msg)?)
+}
+
+/// `/name ...` から型付き引数を取り出す
+pub fn from_interaction<T: FromArgs>(command: &CommandInteraction) -> Result<T, String> {
+    T::from_args(&Args::from_interaction(command))
+}
+
+impl Args {
+    pub fn from_message(cmd: &dyn Command, msg: &Message) -> Result<Self, String> {
+        Self::from_content(cmd, &msg.content)
+    }
+
+    /// "!name ..." 形式の文字列をパースする
+    pub fn from_content(cmd: &dyn Command, content: &str) -> Result<Self, String> {
+        let (sub, input, schema) =
+            select_subcommand(strip_command(cmd, content), command_schema(cmd))?;
+        let mut args = Self::parse(input, &schema)?;
+        args.subcommand = sub;
+        Ok(args)
+    }
+
+    /// 必須チェックを後回しにしてパースする（返信先などで値を補う場合）。
+    /// 必須項目は `FromArgs` 側の `required_str` で検査する
+    pub fn from_message_partial(cmd: &dyn Command, msg: &Message) -> Result<Self, String> {
+        Self::from_content_partial(cmd, &msg.content)
+    }
+
+    pub fn from_content_partial(cmd: &dyn Command, content: &str) -> Result<Self, String> {
+        let (sub, input, schema) =
+            select_subcommand(strip_command(cmd, content), command_schema(cmd))?;
+        let mut args = Self::parse_partial(input, &schema)?;
+        args.subcommand = sub;
+        Ok(args)
+    }
+
+    pub fn from_interaction(command: &CommandInteraction) -> Self {
+        let mut args = Self::default();
+        args.set_resolved(command.data.options());
+        args
+    }
+
+    /// サブコマンドはその名前を覚えて、中のオプションを同じ階層に並べる
+    fn set_resolved(&mut self, options: Vec<ResolvedOption<'_>>) {
+        for opt in options {
+            let value = match opt.value {
+                ResolvedValue::SubCommand(inner) => {
+                    self.subcommand = Some(opt.name.to_string());
+                    self.set_resolved(inner);
+                    continue;
+                }
+                ResolvedValue::String(s) => ArgValue::String(s.to_string()),
+                ResolvedValue::Integer(n) => ArgValue::Integer(n),
+                ResolvedValue::Boolean(b) => ArgValue::Boolean(b),
+                ResolvedValue::Attachment(a) => ArgValue::Attachment(Box::new(a.clone())),
+                _ => continue,
+            };
+            self.values.insert(opt.name.to_string(), value);
+        }
+    }
+
+    /// テキスト引数をスキーマに従ってパースする
+    pub fn parse(input: &str, schema: &Schema) -> Result<Self, String> {
+        let args = Self::parse_partial(input, schema)?;
+        if let Some(missing) = schema
+            .options
+            .iter()
+            .find(|o| o.required && !args.values.contains_key(&o.name))
+        {
+            return Err(format!("{} が必要です", missing.name));
+        }
+        Ok(args)
+    }
+
+    fn parse_partial(input: &str, schema: &Schema) -> Result<Self, String> {
+        let tokens = tokenize(input);
+        let mut args = Self::default();
+        let mut positional: Vec<&Token> = Vec::new();
+        // コードブロックが無ければ、code の位置引数が始まったところから後ろは全部コード
+        let code = find(schema, CODE).filter(|_| !tokens.iter().any(Token::is_fence));
+        let mut in_code = false;
+
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+            i += 1;
+            if token.is_fence() || in_code {
+                positional.push(token);
+                continue;
+            }
+
+            // --flag [value]
+            if let Some(opt) = token
+                .raw
+                .strip_prefix("--")
+                .and_then(|name| find(schema, name))
+            {
+                if opt.kind == CommandOptionType::Boolean {
+                    let value = tokens.get(i).and_then(|t| parse_bool(&t.value()));
+                    if value.is_some() {
+                        i += 1;
+                    }
+                    args.set(opt, ArgValue::Boolean(value.unwrap_or(true)));
+                } else {
+                    let Some(next) = tokens.get(i) else {
+                        return Err(format!("--{} の値がありません", opt.name));
+                    };
+                    i += 1;
+                    args.set_token(opt, next)?;
+                }
+                continue;
+            }
+
+            // key:value
+            if let Some((opt, rest)) = token
+                .raw
+                .split_once(':')
+                .and_then(|(key, rest)| find(schema, key).map(|opt| (opt, rest)))
+            {
+                if rest.is_empty() {
+                    let Some(next) = tokens.get(i) else {
+                        return Err(format!("{}: の値がありません", opt.name));
+                    };
+                    i += 1;
+                    args.set_token(opt, next)?;
+                } else {
+                    args.set_str(opt, &unquote(rest), None)?;
+                }
+                continue;
+            }
+
+            // code より前の位置引数が埋まっていれば、これがコードの始まり
+            if let Some(code) = code
+                && !args.values.contains_key(&code.name)
+                && positional.len() >= args.slots(schema).len().saturating_sub(1)
+            {
+                in_code = true;
+            }
+            positional.push(token);
+        }
+
+        // コードブロックは code オプションへ
+        if let Some(code) = find(schema, CODE).filter(|o| !args.values.contains_key(&o.name))
+            && let Some(pos) = positional.iter().position(|t| t.is_fence())
+        {
+            let fence = positional.remove(pos);
+            args.set_token(code, fence)?;
+        }
+
+        let slots = args.slots(schema);
+        let mut rest = positional.as_slice();
+        for (n, opt) in slots.iter().enumerate() {
+            if rest.is_empty() {
+                break;
+            }
+            if n + 1 == slots.len() {
+                args.set_str(opt, &join_raw(input, rest), None)?;
+                rest = &[];
+            } else {
+                args.set_token(opt, rest[0])?;
+                rest = &rest[1..];
+            }
+        }
+        Ok(args)
+    }
+
+    /// まだ値の無い、位置引数を受け取るオプション。code は末尾に回して残り全部を受け取らせる
+    fn slots<'a>(&self, schema: &'a Schema) -> Vec<&'a CommandOption> {
+        let mut slots: Vec<&CommandOption> = schema
+            .options
+            .iter()
+            .filter(|o| !self.values.contains_key(&o.name))
+            .filter(|o| o.kind == CommandOptionType::String)
+            .filter(|o| !schema.is_named_only(o))
+            .collect();
+        if let Some(pos) = slots.iter().position(|o| o.name == CODE) {
+            let code = slots.remove(pos);
+            slots.push(code);
+        }
+        slots
+    }
+
+    /// 未指定なら文字列値を補う
+    pub fn set_default(&mut self, name: &str, value: &str) {
+        self.values
+            .entry(name.to_string())
+            .or_insert_with(|| ArgValue::String(value.to_string()));
+    }
+
+    pub fn str(&self, name: &str) -> Option<&str> {
+        match self.values.get(name) {
+            Some(ArgValue::String(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn int(&self, name: &str) -> Option<i64> {
+        match self.values.get(name) {
+            Some(ArgValue::Integer(n)) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn attachment(&self, name: &str) -> Option<&Attachment> {
+        match self.values.get(name) {
+            Some(ArgValue::Attachment(a)) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn bool(&self, name: &str) -> Option<bool> {
+        match self.values.get(name) {
+            Some(ArgValue::Boolean(b)) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// 必須の文字列引数（空文字も未指定扱い）
+    pub fn required_str(&self, name: &str) -> Result<String, String> {
+        self.str(name)
+            .filter(|s| !s.trim().is_empty())
+            .map(str::to_string)
+            .ok_or_else(|| format!("{name} が必要です"))
+    }
+
+    /// スラッシュ版で選ばれたサブコマンド
+    pub fn subcommand(&self) -> Option<&str> {
+        self.subcommand.as_deref()
+    }
+
+    /// コードブロックで渡された場合の言語タグ
+    pub fn fence_lang(&self, name: &str) -> Option<&str> {
+        self.fence_langs
+            .get(name)
+            .map(String::as_str)
+            .filter(|l| !l.is_empty())
+    }
+
+    fn set(&mut self, opt: &CommandOption, value: ArgValue) {
+        self.values.insert(opt.name.clone(), value);
+    }
+
+    fn set_token(&mut self, opt: &CommandOption, token: &Token) -> Result<(), String> {
+        match &token.kind {
+            TokenKind::Fence { lang, body } => self.set_str(opt, body, Some(lang)),
+            TokenKind::Word => self.set_str(opt, &token.value(), None),
+        }
+    }
+
+    /// スキーマの型に合わせて変換して格納
+    fn set_str(&mut self, opt: &CommandOption, s: &str, lang: Option<&str>) -> Result<(), String> {
+        let value = match opt.kind {
+            CommandOptionType::Boolean => ArgValue::Boolean(
+                parse_bool(s)
+                    .ok_or_else(|| format!("{} は true/false で指定してください", opt.name))?,
+            ),
+            CommandOptionType::String => ArgValue::String(s.to_string()),
+            CommandOptionType::Integer => ArgValue::Integer(parse_int(opt, s)?),
+            _ => return Err(format!("{} はテキストでは指定できません", opt.name)),
+        };
+        if let Some(lang) = lang {
+            self.fence_langs.insert(opt.name.clone(), lang.to_string());
+        }
+        self.set(opt, value);
+        Ok(())
+    }
+}
+
+/// "!name" を外した引数部分
+fn strip_command<'a>(cmd: &dyn Command, content: &'a str) -> &'a str {
+    content
+        .trim()
+        .strip_prefix(super::PREFIX)
+        .map(str::trim_start)
+        .and_then(|s| s.strip_prefix(cmd.name()))
+        .unwrap_or("")
+}
+
+/// サブコマンドを持つコマンドなら、先頭の単語でサブコマンドを選び、その中のオプションをスキーマにする
+fn select_subcommand(
+    input: &str,
+    schema: Schema,
+) -> Result<(Option<String>, &str, Schema), String> {
+    let is_sub = |o: &CommandOption| o.kind == CommandOptionType::SubCommand;
+    if !schema.options.iter().any(is_sub) {
+        return Ok((None, input, schema));
+    }
+    let Schema {
+        options: schema,
+        named_only,
+    } = schema;
+    let input = input.trim_start();
+    let (name, rest) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+    let names: Vec<String> = schema
+        .iter()
+        .filter(|o| is_sub(o))
+        .map(|o| o.name.clone())
+        .collect();
+    match schema.into_iter().find(|o| is_sub(o) && o.name == name) {
+        Some(sub) => Ok((
+            Some(sub.name),
+            rest,
+            Schema {
+                options: sub.options,
+                named_only,
+            },
+        )),
+        None => Err(format!(
+            "サブコマンドを指定してください: {}",
+            names.join(", ")
+        )),
+    }
+}
+
+/// `CreateCommandOption` はビルダーなので、serde 経由でフィールドを読める形に戻す
+pub fn schema(options: &[CreateCommandOption]) -> Schema {
+    Schema {
+        options: options
+            .iter()
+            .filter_map(|o| serde_json::to_value(o).ok())
+            .filter_map(|v| serde_json::from_value(v).ok())
+            .collect(),
+        named_only: Vec::new(),
+    }
+}
+
+/// コマンドのオプション定義と named_only から作ったスキーマ
+pub fn command_schema(cmd: &dyn Command) -> Schema {
+    schema(&cmd.options()).named_only(cmd.named_only())
+}
+
+fn find<'a>(schema: &'a Schema, name: &str) -> Option<&'a CommandOption> {
+    schema.options.iter().find(|o| o.name == name)
+}
+
+fn parse_bool(s: &str) -> Option<bool> {
+    match s.trim().to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// 整数に変換し、スキーマの最小値・最大値も確認する
+fn parse_int(opt: &CommandOption, s: &str) -> Result<i64, String> {
+    let n: i64 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("{} は整数で指定してください", opt.name))?;
+    let bound = |v: &Option<serde_json::Number>| v.as_ref().and_then(|v| v.as_i64());
+    if let Some(min) = bound(&opt.min_value)
+        && n < min
+    {
+        return Err(format!("{} は {min} 以上で指定してください", opt.name));
+    }
+    if let Some(max) = bound(&opt.max_value)
+        && n > max
+    {
+        return Err(format!("{} は {max} 以下で指定してください", opt.name));
+    }
+    Ok(n)
+}
+
+/// 引用符を解釈して空白で区切る（コマンドライン引数用）
+pub fn split_words(input: &str) -> Vec<String> {
+    tokenize(input).iter().map(Token::value).collect()
+}
+
+#[derive(Debug)]
+struct Token<'a> {
+    raw: &'a str,
+    // 入力中の位置（連続する位置引数を元の空白のまま連結するため）
+    start: usize,
+    end: usize,
+    kind: TokenKind,
+}
+
+#[derive(Debug)]
+enum TokenKind {
+    Word,
+    Fence { lang: String, body: String },
+}
+
+impl Token<'_> {
+    fn is_fence(&self) -> bool {
+        matches!(self.kind, TokenKind::Fence { .. })
+    }
+
+    fn value(&self) -> String {
+        match &self.kind {
+            TokenKind::Fence { body, .. } => body.clone(),
+            TokenKind::Word => unquote(self.raw),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while let Some(c) = input[i..].chars().next() {
+        if c.is_whitespace() {
+            i += c.len_utf8();
+            continue;
+        }
+        let start = i;
+        let end = if let Some(after) = input[i..].strip_prefix("```") {
+            let inner_start = i + 3;
+            let (inner_end, end) = match after.find("```") {
+                Some(p) => (inner_start + p, inner_start + p + 3),
+                None => (input.len(), input.len()),
+            };
+            let (lang, body) = split_fence(&input[inner_start..inner_end]);
+            tokens.push(Token {
+                raw: &input[start..end],
+                start,
+                end,
+                kind: TokenKind::Fence { lang, body },
+            });
+            i = end;
+            continue;
+        } else {
+            // 閉じていない引用符は普通の文字として読み直す
+            scan_word(input, start, false).unwrap_or_else(|| {
+                scan_word(input, start, true).expect("引用符を無視すれば必ず読める")
+            })
+        };
+        tokens.push(Token {
+            raw: &input[start..end],
+            start,
+            end,
+            kind: TokenKind::Word,
+        });
+        i = end;
+    }
+    tokens
+}
+
+/// 空白で区切られた1語の終端を返す。
+/// 引用符の中と、閉じていない {} / [] の中の空白では区切らない。
+fn scan_word(input: &str, start: usize, literal_quotes: bool) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut prev: Option<char> = None;
+    for (off, ch) in input[start..].char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if ch == '\\' {
+                escaped = true;
+            } else if ch == q {
+                quote = None;
+            }
+            prev = Some(ch);
+            continue;
+        }
+        match ch {
+            // it's などのアポストロフィは引用符として扱わない
+            '"' | '\'' if !literal_quotes && !prev.is_some_and(char::is_alphanumeric) => {
+                quote = Some(ch)
+            }
+            '{' | '[' => depth += 1,
+            '}' | ']' => depth = depth.saturating_sub(1),
+            c if c.is_whitespace() && depth == 0 => return Some(start + off),
+            _ => {}
+        }
+        prev = Some(ch);
+    }
+    quote.is_none().then_some(input.len())
+}
+
+/// 全体が引用符で囲まれていれば外す
+fn unquote(raw: &str) -> String {
+    let Some(q) = raw.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+        return raw.to_string();
+    };
+    let inner = &raw[1..];
+    let mut out = String::new();
+    let mut chars = inner.char_indices();
+    while let Some((off, ch)) = chars.next() {
+        if ch == '\\' {
+            match chars.next() {
+                Some((_, next)) if next == q || next == '\\' => out.push(next),
+                Some((_, next)) => {
+                    out.push(ch);
+                    out.push(next);
+                }
+                None => out.push(ch),
+            }
+        } else if ch == q {
+            // 閉じ引用符が末尾でなければ引用ではない
+            return if off + ch.len_utf8() == inner.len() {
+                out
+            } else {
+                raw.to_string()
+            };
+        } else {
+            out.push(ch);
+        }
+    }
+    raw.to_string()
+}
+
+/// ```lang\ncode``` を (lang, code) に分ける
+fn split_fence(inner: &str) -> (String, String) {
+    let (lang, body) = match inner.split_once('\n') {
+        Some((first, body)) if is_lang_tag(first.trim()) => (first.trim(), body),
+        _ => ("", inner),
+    };
+    (
+        lang.to_string(),
+        body.trim_start_matches(['\n', '\r']).trim_end().to_string(),
+    )
+}
+
+fn is_lang_tag(s: &str) -> bool {
+    s.chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '+' | '#' | '-' | '.' | '_'))
+}
+
+/// ファイル名の行が直前にあるコードブロックを取り出す。
+/// 戻り値は (取り出した残りの文字列, [(ファイル名, 中身)])
+///
+/// ```text
+/// main.py
+/// ```py
+/// import util
+/// ```
+/// ```
+pub fn take_named_fences(input: &str) -> (String, Vec<(String, String)>) {
+    let mut rest = String::new();
+    let mut files = Vec::new();
+    let mut s = input;
+    while let Some(open) = s.find("```") {
+        let Some(close) = s[open + 3..].find("```") else {
+            break;
+        };
+        let end = open + 3 + close + 3;
+        let before = s[..open].trim_end_matches([' ', '\t']);
+        let name_line = before
+            .strip_suffix('\n')
+            .map(|b| b.trim_end_matches('\r'))
+            .map(|b| (b.rfind('\n').map_or(0, |i| i + 1), b));
+        match name_line.and_then(|(start, b)| Some((start, file_name(&b[start..])?))) {
+            Some((start, name)) => {
+                let (_, body) = split_fence(&s[open + 3..end - 3]);
+                files.push((name, body));
+                rest.push_str(&s[..start]);
+            }
+            None => rest.push_str(&s[..end]),
+        }
+        s = &s[end..];
+    }
+    rest.push_str(s);
+    (rest, files)
+}
+
+/// "main.py" / "`main.py`" / "**main.py**:" のような行ならファイル名を返す
+fn file_name(line: &str) -> Option<String> {
+    let name = line
+        .trim()
+        .trim_end_matches(':')
+        .trim_matches(|c| c == '*' || c == '`');
+    let (stem, ext) = name.rsplit_once('.')?;
+    let valid = !stem.is_empty()
+        && !ext.is_empty()
+        && ext.chars().all(|c| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '/'));
+    valid.then(|| name.to_string())
+}
+
+/// 位置引数を元の入力の区切りのまま連結する
+fn join_raw(input: &str, tokens: &[&Token]) -> String {
+    let mut out = String::new();
+    for (n, token) in tokens.iter().enumerate() {
+        if n > 0 {
+            let prev = tokens[n - 1];
+            let gap = &input[prev.end..token.start];
+            // 間にフラグなどが挟まっていれば空白1つで繋ぐ
+            if gap.trim().is_empty() {
+                out.push_str(gap);
+            } else {
+                out.push(' ');
+            }
+        }
+        out.push_str(token.raw);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts() -> Schema {
+        schema(&[
+            CreateCommandOption::new(CommandOptionType::String, "url", "url").required(true),
+            CreateCommandOption::new(CommandOptionType::String, "payload", "payload"),
+            CreateCommandOption::new(CommandOptionType::String, "headers", "headers"),
+            CreateCommandOption::new(CommandOptionType::Boolean, "hide", "hide"),
+        ])
+    }
+
+    #[test]
+    fn schema_roundtrip() {
+        let s = opts().options;
+        assert_eq!(s.len(), 4);
+        assert_eq!(s[0].name, "url");
+        assert!(s[0].required);
+        assert_eq!(s[3].kind, CommandOptionType::Boolean);
+    }
+
+    #[test]
+    fn positional_and_flags() {
+        let a = Args::parse(
+            r#"https://example.com {"a": "b c"} --headers {"x": "y"} --hide"#,
+            &opts(),
+        )
+        .unwrap();
+        assert_eq!(a.str("url"), Some("https://example.com"));
+        assert_eq!(a.str("payload"), Some(r#"{"a": "b c"}"#));
+        assert_eq!(a.str("headers"), Some(r#"{"x": "y"}"#));
+        assert_eq!(a.bool("hide"), Some(true));
+    }
+
+    #[test]
+    fn key_value_and_quotes() {
+        let a = Args::parse(r#"url:"https://example.com" hide:false 'it''s'"#, &opts()).unwrap();
+        assert_eq!(a.str("url"), Some("https://example.com"));
+        assert_eq!(a.bool("hide"), Some(false));
+        assert_eq!(a.str("payload"), Some("'it''s'"));
+    }
+
+    #[test]
+    fn last_positional_takes_rest() {
+        let s = schema(&[CreateCommandOption::new(
+            CommandOptionType::String,
+            "query",
+            "q",
+        )]);
+        let a = Args::parse("what's  the \"answer\"?", &s).unwrap();
+        assert_eq!(a.str("query"), Some("what's  the \"answer\"?"));
+    }
+
+    #[test]
+    fn fence_goes_to_code() {
+        let s = schema(&[
+            CreateCommandOption::new(CommandOptionType::String, "code", "code").required(true),
+            CreateCommandOption::new(CommandOptionType::String, "lang", "lang"),
+        ]);
+        let a = Args::parse("python\n```py\nif x:\n    print(1)\n```", &s).unwrap();
+        assert_eq!(a.str("lang"), Some("python"));
+        assert_eq!(a.str("code"), Some("if x:\n    print(1)"));
+        assert_eq!(a.fence_lang("code"), Some("py"));
+
+        let a = Args::parse("rust println!(\"{}\", 1 + 2);", &s).unwrap();
+        assert_eq!(a.str("lang"), Some("rust"));
+        assert_eq!(a.str("code"), Some("println!(\"{}\", 1 + 2);"));
+    }
+
+    #[test]
+    fn partial_then_default() {
+        let s = schema(&[
+            CreateCommandOption::new(CommandOptionType::String, "code", "code").required(true),
+            CreateCommandOption::new(CommandOptionType::String, "lang", "lang").required(true),
+        ]);
+        assert!(Args::parse("```py\nprint(1)\n```", &s).is_err());
+        let mut a = Args::parse_partial("```py\nprint(1)\n```", &s).unwrap();
+        let lang = a.fence_lang("code").unwrap().to_string();
+        a.set_default("lang", &lang);
+        a.set_default("code", "ignored");
+        assert_eq!(a.str("lang"), Some("py"));
+        assert_eq!(a.str("code"), Some("print(1)"));
+    }
+
+    #[test]
+    fn named_only_is_not_positional() {
+        let options = [
+            CreateCommandOption::new(CommandOptionType::String, "code", "code").required(true),
+            CreateCommandOption::new(CommandOptionType::String, "lang", "lang").required(true),
+            CreateCommandOption::new(CommandOptionType::String, "version", "version"),
+        ];
+        let s = schema(&options).named_only(&["version"]);
+        let a = Args::parse("python print(1)", &s).unwrap();
+        assert_eq!(a.str("lang"), Some("python"));
+        assert_eq!(a.str("version"), None);
+        assert_eq!(a.str("code"), Some("print(1)"));
+
+        let a = Args::parse("python version:3.10 print(1)", &s).unwrap();
+        assert_eq!(a.str("version"), Some("3.10"));
+        assert_eq!(a.str("code"), Some("print(1)"));
+
+        // named_only に挙げなければ普通の位置引数
+        let a = Args::parse("python 3.10 print(1)", &schema(&options)).unwrap();
+        assert_eq!(a.str("version"), Some("3.10"));
+    }
+
+    #[test]
+    fn options_stop_at_unfenced_code() {
+        let s = schema(&[
+            CreateCommandOption::new(CommandOptionType::String, "lang", "lang").required(true),
+            CreateCommandOption::new(CommandOptionType::String, "code", "code").required(true),
+            CreateCommandOption::new(CommandOptionType::String, "args", "args"),
+            CreateCommandOption::new(CommandOptionType::Boolean, "hide", "hide"),
+        ])
+        .named_only(&["args"]);
+        let a = Args::parse(
+            "rust --hide let args: Vec<String> = std::env::args().collect(); --hide",
+            &s,
+        )
+        .unwrap();
+        assert_eq!(a.str("lang"), Some("rust"));
+        assert_eq!(a.bool("hide"), Some(true));
+        assert_eq!(a.str("args"), None);
+        assert_eq!(
+            a.str("code"),
+            Some("let args: Vec<String> = std::env::args().collect(); --hide")
+        );
+
+        // コードブロックなら後ろのオプションも読む
+        let a = Args::parse("rust ```\nlet args: u8 = 1;\n``` args:x", &s).unwrap();
+        assert_eq!(a.str("code"), Some("let args: u8 = 1;"));
+        assert_eq!(a.str("args"), Some("x"));
+    }
+
+    #[test]
+    fn subcommands() {
+        let s = schema(&[
+            CreateCommandOption::new(CommandOptionType::SubCommand, "fmt", "fmt").add_sub_option(
+                CreateCommandOption::new(CommandOptionType::String, "code", "code").required(true),
+            ),
+            CreateCommandOption::new(CommandOptionType::SubCommand, "asm", "asm"),
+        ]);
+        let (sub, rest, inner) =
+            select_subcommand("fmt\n```rust\nfn a(){}\n```", s.clone()).unwrap();
+        assert_eq!(sub.as_deref(), Some("fmt"));
+        let a = Args::parse(rest, &inner).unwrap();
+        assert_eq!(a.str("code"), Some("fn a(){}"));
+        assert_eq!(
+            select_subcommand("run x", s).unwrap_err(),
+            "サブコマンドを指定してください: fmt, asm"
+        );
+        // サブコマンドの無いコマンドはそのまま
+        let (sub, rest, _) = select_subcommand("a b", Schema::default()).unwrap();
+        assert_eq!((sub, rest), (None, "a b"));
+    }
+
+    #[test]
+    fn integers_and_words() {
+        let s =
+            schema(&[
+                CreateCommandOption::new(CommandOptionType::Integer, "timeout", "timeout")
+                    .min_int_value(1)
+                    .max_int_value(10),
+            ]);
+        assert_eq!(
+            Args::parse("timeout:3", &s).unwrap().int("timeout"),
+            Some(3)
+        );
+        assert_eq!(
+            Args::parse("--timeout 11", &s).unwrap_err(),
+            "timeout は 10 以下で指定してください"
+        );
+        assert!(Args::parse("timeout:x", &s).is_err());
+
+        assert_eq!(split_words(r#"a "b c" 'd'"#), ["a", "b c", "d"]);
+    }
+
+    #[test]
+    fn named_fences() {
+        let input = "!eval python\nmain.py\n```py\nimport util\n```\n**util.py**:\n```\ndef f(): pass\n```\n```rs\nunnamed\n```";
+        let (rest, files) = take_named_fences(input);
+        assert_eq!(rest, "!eval python\n\n\n```rs\nunnamed\n```");
+        assert_eq!(
+            files,
+            [
+                ("main.py".to_string(), "import util".to_string()),
+                ("util.py".to_string(), "def f(): pass".to_string()),
+            ]
+        );
+
+        // 言語名だけの行や文中のコードブロックはファイル扱いしない
+        let input = "!eval python\n```py\nprint(1)\n```";
+        assert_eq!(take_named_fences(input), (input.to_string(), Vec::new()));
+        assert!(take_named_fences("see main.py ```x```").1.is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Args::parse("", &opts()).unwrap_err(), "url が必要です");
+        assert!(Args::parse("u hide:maybe", &opts()).is_err());
+        assert!(Args::parse("u --headers", &opts()).is_err());
+    }
+}
@@ -1,180 +1,834 @@
-use reqwest;
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use serenity::{
-    all::CommandDataOptionValue,
-    builder::{CreateCommand, CreateCommandOption},
-    model::application::CommandOptionType,
+    async_trait,
+    builder::{
+        CreateActionRow, CreateAttachment, CreateAutocompleteResponse, CreateButton,
+        CreateCommandOption, CreateInputText, CreateInteractionResponse,
+        CreateInteractionResponseMessage, CreateModal, EditInteractionResponse,
+    },
+    model::{
+        application::{
+            ActionRowComponent, ButtonStyle, CommandInteraction, CommandOptionType,
+            ComponentInteraction, InputTextStyle, ModalInteraction, ModalInteractionData,
+        },
+        channel::{Attachment, Message},
+        id::{MessageId, UserId},
+    },
     prelude::Context,
 };
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use super::{
+    ansi,
+    args::{self, Args, FromArgs},
+    reply,
+};
+use crate::cargo_runner;
+use crate::piston::{Lang, Languages};
+use crate::runner::{self, Runner};
+
+pub mod codegen;
+pub mod deps;
 
 pub const NAME: &str = "eval";
 pub const DESCRIPTION: &str = "REPL";
 
-pub fn slash_register() -> CreateCommand {
-    CreateCommand::new(NAME)
-        .description(DESCRIPTION)
-        .add_option(
-            CreateCommandOption::new(CommandOptionType::String, "code", DESCRIPTION).required(true),
-        )
-        .add_option(
+pub struct Eval;
+
+#[async_trait]
+impl super::Command for Eval {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn description(&self) -> &'static str {
+        DESCRIPTION
+    }
+
+    fn named_only(&self) -> &'static [&'static str] {
+        &["version", "stdin", "args"]
+    }
+
+    fn options(&self) -> Vec<CreateCommandOption> {
+        vec![
             CreateCommandOption::new(CommandOptionType::String, "lang", "language")
                 .required(true)
-                .add_string_choice("Rust", "rust")
-                .add_string_choice("Python", "python")
-                .add_string_choice("C", "c")
-                .add_string_choice("C++", "c++")
-                .add_string_choice("Java", "java")
-                .add_string_choice("JavaScript", "javascript")
-                .add_string_choice("TypeScript", "typescript")
-                .add_string_choice("Go", "go")
-                .add_string_choice("Ruby", "ruby")
-                .add_string_choice("bash", "bash")
-                .add_string_choice("haskell", "haskell")
-                .add_string_choice("lisp", "lisp")
-                .add_string_choice("ocaml", "ocaml")
-                .add_string_choice("prolog", "prolog")
-                .add_string_choice("zig", "zig")
-                .add_string_choice("swift", "swift")
-                .add_string_choice("scala", "scala")
-                .add_string_choice("nim", "nim"),
-        )
-        .add_option(CreateCommandOption::new(
-            CommandOptionType::Boolean,
-            "no-wrap-main",
-            "without wrapping with main(){}",
-        ))
-        .add_option(CreateCommandOption::new(
-            CommandOptionType::Boolean,
-            "hide",
-            "only show runner",
-        ))
-}
-
-pub async fn slash_execute(
-    ctx: &Context,
-    command: &serenity::model::application::CommandInteraction,
-) -> serenity::Result<()> {
-    let hide = command
-        .data
-        .options
-        .iter()
-        .find(|opt| opt.name == "hide")
-        .and_then(|opt| opt.value.as_bool())
-        .unwrap_or(false);
+                .set_autocomplete(true),
+            // 省略すると入力用のモーダルを開く（file を添付した場合を除く）
+            CreateCommandOption::new(CommandOptionType::String, "code", DESCRIPTION),
+            CreateCommandOption::new(
+                CommandOptionType::String,
+                "version",
+                "language version (default: latest)",
+            )
+            .set_autocomplete(true),
+            CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "no-wrap-main",
+                "without wrapping with main(){}",
+            ),
+            CreateCommandOption::new(CommandOptionType::Boolean, "hide", "only show runner"),
+            CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "color",
+                "colored compiler diagnostics and output (ANSI, not on Piston)",
+            ),
+            CreateCommandOption::new(CommandOptionType::String, "stdin", "standard input"),
+            CreateCommandOption::new(
+                CommandOptionType::Attachment,
+                "stdin-file",
+                "standard input from a text file",
+            ),
+            CreateCommandOption::new(
+                CommandOptionType::String,
+                "args",
+                "command-line arguments (space separated, quotes allowed)",
+            ),
+            CreateCommandOption::new(
+                CommandOptionType::Integer,
+                "compile-timeout",
+                "compile timeout (ms)",
+            )
+            .min_int_value(1),
+            CreateCommandOption::new(
+                CommandOptionType::Integer,
+                "run-timeout",
+                "run timeout (ms)",
+            )
+            .min_int_value(1),
+            CreateCommandOption::new(
+                CommandOptionType::Integer,
+                "compile-memory",
+                "compile memory limit (MB)",
+            )
+            .min_int_value(1),
+            CreateCommandOption::new(
+                CommandOptionType::Integer,
+                "run-memory",
+                "run memory limit (MB)",
+            )
+            .min_int_value(1),
+            CreateCommandOption::new(
+                CommandOptionType::Attachment,
+                "file",
+                "source file (entry point if code is omitted)",
+            ),
+            CreateCommandOption::new(CommandOptionType::Attachment, "file2", "source file"),
+            CreateCommandOption::new(CommandOptionType::Attachment, "file3", "source file"),
+        ]
+    }
+
+    async fn run(&self, ctx: &Context, msg: &Message) -> serenity::Result<()> {
+        run(ctx, msg).await
+    }
 
-    if hide {
+    async fn slash_execute(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> serenity::Result<()> {
+        slash_execute(ctx, command).await
+    }
+
+    async fn modal_submit(&self, ctx: &Context, modal: &ModalInteraction) -> serenity::Result<()> {
+        modal_submit(ctx, modal).await
+    }
+
+    async fn component(
+        &self,
+        ctx: &Context,
+        component: &ComponentInteraction,
+    ) -> serenity::Result<()> {
+        self::component(ctx, component).await
+    }
+
+    async fn autocomplete(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> serenity::Result<()> {
+        let Some(focused) = command.data.autocomplete() else {
+            return Ok(());
+        };
+        // 言語リストが取れなければ候補なし（入力はそのまま送れる）
+        let choices = match runner::client().languages().await {
+            Ok(langs) => match focused.name {
+                "lang" => lang_choices(&langs, focused.value),
+                "version" => {
+                    let args = Args::from_interaction(command);
+                    version_choices(&langs, args.str("lang").unwrap_or(""), focused.value)
+                }
+                _ => Vec::new(),
+            },
+            Err(_) => Vec::new(),
+        };
+        let response = choices
+            .into_iter()
+            .fold(CreateAutocompleteResponse::new(), |r, (name, value)| {
+                r.add_string_choice(name, value)
+            });
+        command
+            .create_response(&ctx.http, CreateInteractionResponse::Autocomplete(response))
+            .await
+    }
+}
+
+// Discord が受け付ける補完候補の最大数
+const MAX_CHOICES: usize = 25;
+
+/// 言語の補完候補 (表示名, 値)。表示名にはエイリアスを添える
+fn lang_choices(langs: &Languages, query: &str) -> Vec<(String, String)> {
+    langs
+        .search(query)
+        .into_iter()
+        .take(MAX_CHOICES)
+        .map(|l| {
+            let name = if l.aliases.is_empty() {
+                l.language.clone()
+            } else {
+                format!("{} ({})", l.language, l.aliases.join(", "))
+            };
+            // 表示名は100文字まで
+            (name.chars().take(100).collect(), l.language.clone())
+        })
+        .collect()
+}
+
+/// バージョンの補完候補 (新しい順)
+fn version_choices(langs: &Languages, lang: &str, query: &str) -> Vec<(String, String)> {
+    let query = query.trim();
+    langs
+        .versions(lang)
+        .into_iter()
+        .filter(|v| v.starts_with(query))
+        .take(MAX_CHOICES)
+        .enumerate()
+        .map(|(n, v)| {
+            let name = if n == 0 {
+                format!("{v} (latest)")
+            } else {
+                v.to_string()
+            };
+            (name, v.to_string())
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct EvalArgs {
+    code: Option<String>,
+    lang: String,
+    version: Option<String>,
+    without_main: bool,
+    hide: bool,
+    stdin_file: Option<Attachment>,
+    files: Vec<Attachment>,
+    opts: RunOptions,
+}
+
+impl FromArgs for EvalArgs {
+    fn from_args(args: &Args) -> Result<Self, String> {
+        let mb = |name: &str| args.int(name).map(|n| n * 1024 * 1024);
+        Ok(Self {
+            code: args.str("code").map(str::to_string),
+            lang: args.required_str("lang")?,
+            version: args.str("version").map(str::to_string),
+            without_main: args.bool("no-wrap-main").unwrap_or(false),
+            hide: args.bool("hide").unwrap_or(false),
+            stdin_file: args.attachment("stdin-file").cloned(),
+            files: FILE_OPTIONS
+                .iter()
+                .filter_map(|name| args.attachment(name).cloned())
+                .collect(),
+            opts: RunOptions {
+                stdin: args.str("stdin").map(str::to_string),
+                args: args.str("args").map(args::split_words).unwrap_or_default(),
+                compile_timeout: args.int("compile-timeout"),
+                run_timeout: args.int("run-timeout"),
+                compile_memory_limit: mb("compile-memory"),
+                run_memory_limit: mb("run-memory"),
+                color: args.bool("color").unwrap_or(false),
+            },
+        })
+    }
+}
+
+impl EvalArgs {
+    /// 添付ファイルを読み込んで実行内容にする
+    async fn into_job(self) -> Result<Job, String> {
+        let mut opts = self.opts;
+        if opts.stdin.is_none()
+            && let Some(file) = &self.stdin_file
+        {
+            opts.stdin = Some(read_attachment(file).await?);
+        }
+        let mut files = Vec::new();
+        for file in &self.files {
+            files.push(FileContent {
+                name: file.filename.clone(),
+                content: read_attachment(file).await?,
+            });
+        }
+        Ok(Job {
+            lang: self.lang,
+            version: self.version,
+            code: self.code.filter(|c| !c.trim().is_empty()),
+            files,
+            without_main: self.without_main,
+            opts,
+        })
+    }
+}
+
+/// 実行内容
+#[derive(Debug, Clone, Default)]
+struct Job {
+    lang: String,
+    version: Option<String>,
+    // 直接書かれたコード。必要なら main などで包み、エントリーポイントにする
+    code: Option<String>,
+    // 添付ファイルなど、そのまま送るファイル
+    files: Vec<FileContent>,
+    without_main: bool,
+    opts: RunOptions,
+}
+
+// スラッシュ版でファイルを添付するオプション
+const FILE_OPTIONS: &[&str] = &["file", "file2", "file3"];
+
+pub async fn slash_execute(ctx: &Context, command: &CommandInteraction) -> serenity::Result<()> {
+    let args = match args::from_interaction::<EvalArgs>(command) {
+        // コードもファイルも無ければモーダルで入力してもらう
+        Ok(a) if a.code.is_none() && a.files.is_empty() => {
+            return open_modal(ctx, command, a).await;
+        }
+        Ok(a) => a,
+        Err(e) => return super::respond(ctx, command, e).await,
+    };
+
+    if args.hide {
         command.defer_ephemeral(&ctx.http).await?;
     } else {
         command.defer(&ctx.http).await?;
     }
 
-    // required(true)のためunwrap
-    let code_opt = command
-        .data
-        .options
-        .iter()
-        .find(|opt| opt.name == "code")
-        .unwrap();
-    let lang_opt = command
-        .data
-        .options
-        .iter()
-        .find(|opt| opt.name == "lang")
-        .unwrap();
+    let (result, job) = run_args(args).await;
+    let message = command
+        .edit_response(&ctx, result_response(result, job.is_some()))
+        .await?;
+    if let Some(job) = job {
+        remember(message.id, command.user.id, job);
+    }
 
-    let without_main = command
-        .data
-        .options
-        .iter()
-        .find(|opt| opt.name == "no-wrap-main")
-        .and_then(|opt| opt.value.as_bool())
-        .unwrap_or(false);
+    Ok(())
+}
 
-    let code = if let CommandDataOptionValue::String(code_val) = code_opt.value.clone() {
-        code_val
+/// 実行結果と、実行できた場合はその内容を返す
+async fn run_args(args: EvalArgs) -> (EvalResult, Option<Job>) {
+    match args.into_job().await {
+        Ok(job) => (execute_or_error(&job).await, Some(job)),
+        Err(e) => (EvalResult::text(e), None),
+    }
+}
+
+async fn execute_or_error(job: &Job) -> EvalResult {
+    execute(runner::client(), job)
+        .await
+        .unwrap_or_else(EvalResult::text)
+}
+
+/// 結果の編集内容。実行できた結果にはボタンを付ける
+fn result_response(result: EvalResult, with_buttons: bool) -> EditInteractionResponse {
+    // 再実行のときは前回の添付を外す
+    let builder = result.attachments().into_iter().fold(
+        EditInteractionResponse::new()
+            .content(result.content)
+            .clear_attachments(),
+        |b, file| b.new_attachment(file),
+    );
+    if with_buttons {
+        builder.components(buttons())
     } else {
-        command
-            .edit_response(
-                &ctx,
-                serenity::builder::EditInteractionResponse::new().content("コードが不正です。"),
+        builder
+    }
+}
+
+/// 結果の下に付けるボタン（再実行・編集して再実行・削除）
+fn buttons() -> Vec<CreateActionRow> {
+    vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(super::custom_id(&Eval, "rerun"))
+            .label("再実行")
+            .emoji('🔁')
+            .style(ButtonStyle::Primary),
+        CreateButton::new(super::custom_id(&Eval, "edit"))
+            .label("編集して再実行")
+            .emoji('✏')
+            .style(ButtonStyle::Secondary),
+        CreateButton::new(super::custom_id(&Eval, "delete"))
+            .label("削除")
+            .emoji('🗑')
+            .style(ButtonStyle::Danger),
+    ])]
+}
+
+/// 結果メッセージごとの実行内容（ボタンから再実行するため）
+struct Stored {
+    at: Instant,
+    owner: UserId,
+    job: Job,
+}
+
+static RESULTS: Lazy<Mutex<HashMap<MessageId, Stored>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+// これより古い結果のボタンは効かなくなる
+const RESULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const MAX_RESULTS: usize = 1000;
+
+fn remember(message: MessageId, owner: UserId, job: Job) {
+    let mut results = RESULTS.lock().unwrap();
+    results.retain(|_, s| s.at.elapsed() < RESULT_TTL);
+    if results.len() >= MAX_RESULTS
+        && let Some(oldest) = results.iter().min_by_key(|(_, s)| s.at).map(|(id, _)| *id)
+    {
+        results.remove(&oldest);
+    }
+    results.insert(
+        message,
+        Stored {
+            at: Instant::now(),
+            owner,
+            job,
+        },
+    );
+}
+
+fn recall(message: MessageId) -> Option<(UserId, Job)> {
+    RESULTS
+        .lock()
+        .unwrap()
+        .get(&message)
+        .map(|s| (s.owner, s.job.clone()))
+}
+
+/// 結果の下のボタン
+pub async fn component(ctx: &Context, component: &ComponentInteraction) -> serenity::Result<()> {
+    let (_, action) = super::split_custom_id(&component.data.custom_id);
+    let reply = |content: &str| {
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(content)
+                .ephemeral(true),
+        )
+    };
+    let Some((owner, job)) = recall(component.message.id) else {
+        return component
+            .create_response(
+                &ctx.http,
+                reply("この結果は古いため操作できません。もう一度 /eval を実行してください。"),
             )
-            .await?;
-        return Ok(());
+            .await;
     };
-    let lang = if let CommandDataOptionValue::String(lang_val) = lang_opt.value.clone() {
-        lang_val
+    let is_owner = component.user.id == owner;
+
+    match action {
+        "rerun" => {
+            component
+                .create_response(&ctx.http, CreateInteractionResponse::Acknowledge)
+                .await?;
+            let result = execute_or_error(&job).await;
+            component
+                .edit_response(&ctx.http, result_response(result, true))
+                .await?;
+        }
+        "edit" if is_owner => {
+            let modal = code_modal(
+                super::custom_id(&Eval, &format!("edit:{}", component.message.id)),
+                "編集して再実行",
+                &job.lang,
+                job.code.as_deref(),
+            );
+            component
+                .create_response(&ctx.http, CreateInteractionResponse::Modal(modal))
+                .await?;
+        }
+        "delete" if is_owner => {
+            component
+                .create_response(&ctx.http, CreateInteractionResponse::Acknowledge)
+                .await?;
+            component.delete_response(&ctx.http).await?;
+            RESULTS.lock().unwrap().remove(&component.message.id);
+        }
+        "edit" | "delete" => {
+            component
+                .create_response(&ctx.http, reply("実行した人だけが操作できます"))
+                .await?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// モーダル送信待ちの引数（スラッシュコマンドの interaction id → 引数）
+static PENDING: Lazy<Mutex<HashMap<u64, (Instant, EvalArgs)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+// 送信されずに閉じられたモーダルの引数を捨てるまでの時間
+const PENDING_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// 言語とコードを入力するモーダル。
+/// Discord のモーダルはテキスト入力しか置けないため、言語も入力欄にする
+fn code_modal(custom_id: String, title: &str, lang: &str, code: Option<&str>) -> CreateModal {
+    let lang = CreateInputText::new(InputTextStyle::Short, "言語", "lang")
+        .value(lang)
+        .max_length(50);
+    let code_input = CreateInputText::new(InputTextStyle::Paragraph, "コード", "code")
+        .placeholder("print(\"Hello, world!\")")
+        .max_length(4000);
+    let code_input = match code {
+        Some(code) => code_input.value(code),
+        None => code_input,
+    };
+    CreateModal::new(custom_id, title).components(vec![
+        CreateActionRow::InputText(lang),
+        CreateActionRow::InputText(code_input),
+    ])
+}
+
+/// スラッシュ版の code は改行を含められないので、複数行のテキスト入力で受け取る。
+/// 言語はコマンドの値を初期値にする
+async fn open_modal(
+    ctx: &Context,
+    command: &CommandInteraction,
+    args: EvalArgs,
+) -> serenity::Result<()> {
+    let modal = code_modal(
+        super::custom_id(&Eval, &format!("new:{}", command.id)),
+        "コードを実行",
+        &args.lang,
+        None,
+    );
+    {
+        let mut pending = PENDING.lock().unwrap();
+        pending.retain(|_, (at, _)| at.elapsed() < PENDING_TTL);
+        pending.insert(command.id.get(), (Instant::now(), args));
+    }
+    command
+        .create_response(&ctx.http, CreateInteractionResponse::Modal(modal))
+        .await
+}
+
+/// モーダルの送信。新規実行 ("new:<id>") と結果の編集 ("edit:<message id>") がある
+pub async fn modal_submit(ctx: &Context, modal: &ModalInteraction) -> serenity::Result<()> {
+    let (_, rest) = super::split_custom_id(&modal.data.custom_id);
+    if let Some(message) = rest.strip_prefix("edit:") {
+        return edit_submit(ctx, modal, message).await;
+    }
+
+    let args = modal_args(&modal.data);
+    if args.hide {
+        modal.defer_ephemeral(&ctx.http).await?;
     } else {
-        command
-            .edit_response(
-                &ctx,
-                serenity::builder::EditInteractionResponse::new().content("言語が不正です。"),
+        modal.defer(&ctx.http).await?;
+    }
+    let (result, job) = if args.lang.is_empty() {
+        (EvalResult::text("lang が必要です"), None)
+    } else {
+        run_args(args).await
+    };
+    let message = modal
+        .edit_response(&ctx.http, result_response(result, job.is_some()))
+        .await?;
+    if let Some(job) = job {
+        remember(message.id, modal.user.id, job);
+    }
+    Ok(())
+}
+
+/// 編集したコードで再実行し、元の結果メッセージを書き換える
+async fn edit_submit(
+    ctx: &Context,
+    modal: &ModalInteraction,
+    message: &str,
+) -> serenity::Result<()> {
+    let stored = message
+        .parse::<u64>()
+        .ok()
+        .map(MessageId::new)
+        .and_then(|id| Some((id, recall(id)?)));
+    let Some((id, (owner, mut job))) = stored.filter(|(_, (owner, _))| *owner == modal.user.id)
+    else {
+        return modal
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content("この結果は編集できません")
+                        .ephemeral(true),
+                ),
             )
-            .await?;
-        return Ok(());
+            .await;
     };
+    let inputs = modal_inputs(&modal.data);
+    if let Some(lang) = inputs.get("lang").filter(|l| !l.is_empty()) {
+        job.lang = lang.to_string();
+    }
+    job.code = inputs
+        .get("code")
+        .filter(|c| !c.trim().is_empty())
+        .map(|c| c.to_string());
 
-    let langs = match Languages::get_from_api().await {
-        Ok(l) => l,
-        Err(_) => {
-            command
-                .edit_response(
-                    &ctx,
-                    serenity::builder::EditInteractionResponse::new()
-                        .content("言語リストの取得に失敗しました。"),
-                )
-                .await?;
+    modal
+        .create_response(&ctx.http, CreateInteractionResponse::Acknowledge)
+        .await?;
+    let result = execute_or_error(&job).await;
+    modal
+        .edit_response(&ctx.http, result_response(result, true))
+        .await?;
+    remember(id, owner, job);
+    Ok(())
+}
+
+/// モーダルのテキスト入力 (custom_id → 値)
+fn modal_inputs(data: &ModalInteractionData) -> HashMap<&str, &str> {
+    let mut inputs = HashMap::new();
+    for row in &data.components {
+        for component in &row.components {
+            if let ActionRowComponent::InputText(input) = component {
+                let value = input.value.as_deref().unwrap_or("");
+                inputs.insert(input.custom_id.as_str(), value.trim_end());
+            }
+        }
+    }
+    inputs
+}
+
+/// 送信待ちの引数にモーダルの入力（言語・コード）を反映する
+fn modal_args(data: &ModalInteractionData) -> EvalArgs {
+    let inputs = modal_inputs(data);
+    let (_, rest) = super::split_custom_id(&data.custom_id);
+    let id = rest.strip_prefix("new:").unwrap_or(rest);
+    // 再起動などで引数が消えていても、言語とコードだけで実行する
+    let mut args = id
+        .parse::<u64>()
+        .ok()
+        .and_then(|id| PENDING.lock().unwrap().remove(&id))
+        .map(|(_, args)| args)
+        .unwrap_or_default();
+    if let Some(lang) = inputs
+        .get("lang")
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+    {
+        args.lang = lang.to_string();
+    }
+    args.code = inputs.get("code").map(|c| c.to_string());
+    args
+}
+
+// プレフィックス: !eval <lang> ```code```
+// ```python のように言語タグ付きのコードブロックなら <lang> は省略可
+// バージョンは version:3.10 のように指定（省略時は最新）
+// stdin:"..." / args:"..." のほか、.txt ファイルを添付すると標準入力になる
+// 複数ファイルは、ソースファイルを添付するか、ファイル名の行に続けてコードブロックを書く
+pub async fn run(ctx: &Context, msg: &Message) -> serenity::Result<()> {
+    let (content, named) = args::take_named_fences(&msg.content);
+    let (stdin_files, sources): (Vec<&Attachment>, Vec<&Attachment>) =
+        msg.attachments.iter().partition(|a| is_text_file(a));
+    let parsed = Args::from_content_partial(&Eval, &content).and_then(|mut args| {
+        if let Some(lang) = args.fence_lang("code").map(str::to_string) {
+            args.set_default("lang", &lang);
+        }
+        // 言語が無ければ最初のファイルの拡張子から
+        let first = named
+            .first()
+            .map(|(name, _)| name.as_str())
+            .or_else(|| sources.first().map(|a| a.filename.as_str()));
+        if let Some(lang) = first.and_then(extension_to_lang) {
+            args.set_default("lang", lang);
+        }
+        EvalArgs::from_args(&args)
+    });
+    let usage = "使い方: !eval <言語> [version:<バージョン>] [stdin:<入力>] [args:<引数>] [--no-wrap-main] ```<コード>```";
+    let mut args = match parsed {
+        Ok(a) if a.code.is_none() && named.is_empty() && sources.is_empty() => {
+            reply::say(ctx, msg, format!("code が必要です\n{usage}")).await?;
             return Ok(());
         }
-    };
-    let lang = match langs.get(&lang) {
-        Some(l) => l,
-        None => {
-            let c = format!("not supported lang: {}", &lang);
-            command
-                .edit_response(
-                    &ctx,
-                    serenity::builder::EditInteractionResponse::new().content(c),
-                )
-                .await?;
+        Ok(a) => a,
+        Err(e) => {
+            reply::say(ctx, msg, format!("{e}\n{usage}")).await?;
             return Ok(());
         }
     };
+    args.stdin_file = stdin_files.first().map(|a| (*a).clone());
+    args.files = sources.into_iter().cloned().collect();
 
-    let req_info = ReqJson::new(lang, code.clone(), without_main);
-    let ccc = req_info.get_generated_code();
-    println!("{req_info:?}");
-    let res = match run_with_api(req_info).await {
-        Ok(r) => r,
-        Err(_) => {
-            command
-                .edit_response(
-                    &ctx,
-                    serenity::builder::EditInteractionResponse::new()
-                        .content("実行に失敗しました。"),
-                )
-                .await?;
+    let job = match args.into_job().await {
+        Ok(mut job) => {
+            let named = named
+                .into_iter()
+                .map(|(name, content)| FileContent { name, content });
+            job.files.splice(0..0, named);
+            job
+        }
+        Err(e) => {
+            reply::say(ctx, msg, e).await?;
             return Ok(());
         }
     };
+    let result = execute_or_error(&job).await;
+    let files = result.attachments();
+    // 元のメッセージが編集されたら、前回の結果を書き換える
+    let message = reply::send_files(ctx, msg, result.content, buttons(), files).await?;
+    remember(message.id, msg.author.id, job);
+    Ok(())
+}
 
-    let response_content = format!("```{}\n{ccc}\n```\n{res}", lang.language);
-    let builder = serenity::builder::EditInteractionResponse::new().content(response_content);
+// 添付ファイルとして受け付ける最大サイズ
+const MAX_ATTACHMENT_BYTES: u32 = 1024 * 1024;
 
-    command.edit_response(&ctx, builder).await?;
+/// 標準入力として扱う添付ファイル
+fn is_text_file(file: &Attachment) -> bool {
+    file.filename.ends_with(".txt")
+        || file
+            .content_type
+            .as_deref()
+            .is_some_and(|t| t.starts_with("text/plain"))
+}
 
-    Ok(())
+/// 添付ファイルをテキストとして読み込む
+async fn read_attachment(file: &Attachment) -> Result<String, String> {
+    if file.size > MAX_ATTACHMENT_BYTES {
+        return Err(format!(
+            "{} が大きすぎます（{}KB まで）",
+            file.filename,
+            MAX_ATTACHMENT_BYTES / 1024
+        ));
+    }
+    let bytes = file
+        .download()
+        .await
+        .map_err(|_| format!("{} の取得に失敗しました。", file.filename))?;
+    String::from_utf8(bytes)
+        .map_err(|_| format!("{} は UTF-8 のテキストにしてください", file.filename))
 }
 
-async fn run_with_api(req_info: ReqJson) -> Result<String, reqwest::Error> {
-    let client = reqwest::Client::new();
-    let res = client
-        .post("https://emkc.org/api/v2/piston/execute")
-        .json(&req_info)
-        .send()
-        .await?;
-    let res = res.json::<Resp>().await?;
-    Ok(format!("{res}"))
+// Discord のメッセージは 2000 文字まで
+const MAX_MESSAGE_SIZE: usize = 2000;
+// 長すぎる結果で本文に残す量 (行数, 文字数)。残りはファイルで添付する
+const SOURCE_BUDGET: (usize, usize) = (15, 600);
+const OUTPUT_BUDGET: (usize, usize) = (20, 400);
+
+/// 返信の本文と、添付するファイル (名前, 中身)
+#[derive(Debug, Default, PartialEq)]
+struct EvalResult {
+    content: String,
+    files: Vec<(String, String)>,
+}
+
+impl EvalResult {
+    fn text(content: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            files: Vec::new(),
+        }
+    }
+
+    fn attachments(&self) -> Vec<CreateAttachment> {
+        self.files
+            .iter()
+            .map(|(name, body)| CreateAttachment::bytes(body.as_bytes().to_vec(), name.clone()))
+            .collect()
+    }
+}
+
+/// 先頭の `max_lines` 行・`max_chars` 文字までを残す。(残した部分, 省略した行数)
+fn clip(s: &str, max_lines: usize, max_chars: usize) -> (String, usize) {
+    let total = s.lines().count();
+    let (mut kept, mut chars, mut shown) = (String::new(), 0, 0);
+    for line in s.lines().take(max_lines) {
+        let len = line.chars().count() + 1;
+        if chars + len > max_chars {
+            // 1行目から長すぎるときは途中まで出す（その行も省略した行に数える）
+            if shown == 0 {
+                kept = line.chars().take(max_chars).collect();
+                kept.push('\n');
+            }
+            break;
+        }
+        kept.push_str(line);
+        kept.push('\n');
+        chars += len;
+        shown += 1;
+    }
+    (kept, total - shown)
+}
+
+/// 言語の解決 → コード生成 → 実行 の共通処理。
+/// 成功時・失敗時ともにそのまま返信できる内容を返す。
+/// 長すぎるときはソースと出力をそれぞれ省略し、全文をファイルで添付する
+async fn execute(runner: &dyn Runner, job: &Job) -> Result<EvalResult, String> {
+    let langs = runner.languages().await?;
+    let found = langs
+        .get(&job.lang)
+        .ok_or_else(|| format!("not supported lang: {}", job.lang))?;
+    let lang = match &job.version {
+        Some(version) => langs.find(&job.lang, Some(version)).ok_or_else(|| {
+            format!(
+                "{} {version} はありません（利用可能: {}）",
+                found.language,
+                langs.versions(&job.lang).join(", ")
+            )
+        })?,
+        None => found,
+    };
+
+    let req_info = ReqJson::new(lang, job)?;
+    let ccc = req_info.get_generated_code();
+    println!("{req_info:?}");
+    let mut info = job.opts.summary();
+    if req_info.files.len() > 1 {
+        let names: Vec<&str> = req_info.files.iter().map(|f| f.name.as_str()).collect();
+        info.insert(0, format!("files: {}", names.join(", ")));
+    }
+    let res: Resp = if req_info.uses_cargo() {
+        // 依存クレートがあるときはローカルの Cargo で実行する
+        cargo_runner::client().execute(&req_info).await?
+    } else {
+        runner::check_color(runner, req_info.opts.color)?;
+        runner::execute(runner, &req_info).await?
+    };
+
+    let info: String = info.iter().map(|l| format!("{l}\n")).collect();
+    let full = format!("```{}\n{ccc}\n```\n{info}{res}", lang.language);
+    if full.chars().count() <= MAX_MESSAGE_SIZE {
+        return Ok(EvalResult::text(full));
+    }
+
+    let mut files = Vec::new();
+    let (head, omitted) = clip(&ccc, SOURCE_BUDGET.0, SOURCE_BUDGET.1);
+    let source = if omitted > 0 {
+        // Cargo プロジェクトの src/main.rs は main.rs として添付する
+        let name = req_info.files[0].name.rsplit('/').next().unwrap_or("main");
+        files.push((name.to_string(), ccc.clone()));
+        format!(
+            "```{}\n{head}```\n… あと {omitted} 行 ({name})\n",
+            lang.language
+        )
+    } else {
+        format!("```{}\n{ccc}\n```\n", lang.language)
+    };
+    let body = res.render(Some(OUTPUT_BUDGET), &mut files);
+    // 実行条件が長すぎる場合などに備えて、最後に上限で切る
+    let content = format!("{source}{info}{body}")
+        .chars()
+        .take(MAX_MESSAGE_SIZE)
+        .collect();
+    Ok(EvalResult { content, files })
 }
 
 /// レスポンスのデシアライズ用のstruct
@@ -183,52 +837,235 @@ async fn run_with_api(req_info: ReqJson) -> Result<String, reqwest::Error> {
 struct Resp {
     language: String,
     version: String,
-    run: Run,
+    // コンパイルしない言語では無い
+    #[serde(default)]
+    compile: Option<Stage>,
+    // コンパイルに失敗すると実行されず無い
+    #[serde(default)]
+    run: Option<Stage>,
 }
 
-/// 結果表示用。このままmsgに流してる
-impl Display for Resp {
+/// 結果表示用。省略せずに全部出す
+impl std::fmt::Display for Resp {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "lang: {}\nversion: {}\nresult:\n```\n{}```",
-            self.language, self.version, self.run.output
-        )
+        f.write_str(&self.render(None, &mut Vec::new()))
+    }
+}
+
+/// コンパイル出力・stdout・stderr・終了ステータスを分けて表示する
+impl Resp {
+    /// `budget` (行数, 文字数) があれば、それを超える出力は省略して全文を `files` に入れる
+    fn render(&self, budget: Option<(usize, usize)>, files: &mut Vec<(String, String)>) -> String {
+        let mut out = format!("lang: {}\nversion: {}\n", self.language, self.version);
+        let mut section = |out: &mut String, body: &str, name: &str| match budget
+            .map(|(lines, chars)| clip(body, lines, chars))
+        {
+            Some((head, omitted)) if omitted > 0 => {
+                out.push_str(&code_block(&head));
+                let _ = writeln!(out, "… あと {omitted} 行 ({name})");
+                files.push((name.to_string(), ansi::strip(body)));
+            }
+            _ => out.push_str(&code_block(body)),
+        };
+        if let Some(compile) = &self.compile
+            && (!compile.succeeded() || !compile.output.trim().is_empty())
+        {
+            let _ = writeln!(out, "compile: {}", compile.status());
+            section(&mut out, &compile.output, "compile.txt");
+        }
+        let Some(run) = &self.run else {
+            out.push_str("run: コンパイルに失敗したため実行されませんでした");
+            return out;
+        };
+        if run.stdout.is_empty() && run.stderr.is_empty() {
+            out.push_str("(出力なし)\n");
+        }
+        if !run.stdout.is_empty() {
+            out.push_str("stdout:\n");
+            section(&mut out, &run.stdout, "stdout.txt");
+        }
+        if !run.stderr.is_empty() {
+            out.push_str("stderr:\n");
+            section(&mut out, &run.stderr, "stderr.txt");
+        }
+        out.push_str(&run.status());
+        out
     }
 }
 
+/// コンパイル・実行それぞれの結果
 #[derive(Debug, Serialize, Deserialize)]
-struct Run {
+struct Stage {
     stdout: String,
     stderr: String,
-    code: i32,
+    // シグナルで終了した場合は null
+    code: Option<i32>,
+    signal: Option<String>,
     output: String,
 }
 
+impl Stage {
+    fn succeeded(&self) -> bool {
+        self.code == Some(0) && self.signal.is_none()
+    }
+
+    /// 終了ステータス。正常終了以外は目立たせる
+    fn status(&self) -> String {
+        let status = match (&self.signal, self.code) {
+            (Some(signal), _) => format!("signal {signal}"),
+            (None, Some(code)) => format!("exit code {code}"),
+            (None, None) => "exit code なし".to_string(),
+        };
+        if self.succeeded() {
+            status
+        } else {
+            format!("⚠️ {status}")
+        }
+    }
+}
+
+/// 出力をコードブロックに入れる（中の ``` でブロックが閉じないようにする）。
+/// 色が付いていれば Discord で表示できる色だけにして ```ansi にする
+fn code_block(s: &str) -> String {
+    let s = ansi::to_discord(s).replace("```", "`\u{200b}``");
+    let lang = if ansi::is_colored(&s) { "ansi" } else { "" };
+    let newline = if s.ends_with('\n') { "" } else { "\n" };
+    format!("```{lang}\n{s}{newline}```\n")
+}
+
 /// apiリクエスト用
 #[derive(Debug, Serialize, Deserialize)]
 struct ReqJson {
     language: String,
     version: String,
     files: Vec<FileContent>,
+    #[serde(flatten)]
+    opts: RunOptions,
+    // "// deps:" から作った Cargo プロジェクトか（ローカルの Cargo で実行する）
+    #[serde(skip)]
+    cargo: bool,
+}
+
+/// 標準入力・コマンドライン引数・制限値（未指定の項目は送らず Piston の既定値に任せる）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct RunOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stdin: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    args: Vec<String>,
+    // ミリ秒
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compile_timeout: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run_timeout: Option<i64>,
+    // バイト
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compile_memory_limit: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run_memory_limit: Option<i64>,
+    // 色付きの出力を頼む（Piston にない項目なので、使うときだけ送る）
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    color: bool,
+}
+
+impl RunOptions {
+    /// 結果に添える実行条件（指定されたものだけ）
+    fn summary(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(stdin) = &self.stdin {
+            lines.push(format!("stdin: {} bytes", stdin.len()));
+        }
+        if !self.args.is_empty() {
+            lines.push(format!("args: {:?}", self.args));
+        }
+        let limits: Vec<String> = [
+            ("compile timeout", self.compile_timeout, "ms", 1),
+            ("run timeout", self.run_timeout, "ms", 1),
+            (
+                "compile memory",
+                self.compile_memory_limit,
+                "MB",
+                1024 * 1024,
+            ),
+            ("run memory", self.run_memory_limit, "MB", 1024 * 1024),
+        ]
+        .into_iter()
+        .filter_map(|(name, value, unit, scale)| Some(format!("{name} {}{unit}", value? / scale)))
+        .collect();
+        if !limits.is_empty() {
+            lines.push(format!("limits: {}", limits.join(", ")));
+        }
+        lines
+    }
 }
 
 impl ReqJson {
     /// lang引数はLang struct。Lang structはversion情報を含む
-    fn new(lang: &Lang, code: String, without_main: bool) -> Self {
-        Self {
+    /// Piston は先頭のファイルを実行するので、エントリーポイントを先頭に置く
+    fn new(lang: &Lang, job: &Job) -> Result<Self, String> {
+        let mut files = job.files.clone();
+        // Cargo.toml は "// deps:" からだけ作る（ビルドの設定を渡されないように）
+        if lang.language == "rust"
+            && let Some(file) = files.iter().find(|f| deps::is_reserved(&f.name))
+        {
+            return Err(format!(
+                "{} は使えません。依存クレートは // deps: で指定してください",
+                file.name
+            ));
+        }
+        // "// deps:" 付きの Rust は Cargo プロジェクトにする。添付のファイルは src/ に置く
+        let project = match &job.code {
+            Some(code) if lang.language == "rust" => deps::cargo_project(code, job.without_main)?,
+            _ => None,
+        };
+        let cargo = project.is_some();
+        if let Some(project) = project {
+            for file in &mut files {
+                file.name = format!("src/{}", file.name);
+                if file.name == deps::MAIN {
+                    return Err("main.rs はコードと同じファイル名なので使えません".to_string());
+                }
+            }
+            let project = project
+                .into_iter()
+                .map(|(name, content)| FileContent { name, content });
+            files.splice(0..0, project);
+        } else if let Some(code) = &job.code {
+            let main = FileContent::new(lang, code, job.without_main);
+            if files.iter().any(|f| f.name == main.name) {
+                return Err(format!(
+                    "{} はコードと同じファイル名なので使えません",
+                    main.name
+                ));
+            }
+            files.insert(0, main);
+        } else if let Some(entry) = entry_point(&files) {
+            let entry = files.remove(entry);
+            files.insert(0, entry);
+        }
+        if files.is_empty() {
+            return Err("code が必要です".to_string());
+        }
+        Ok(Self {
             language: lang.language.clone(),
             version: lang.version.clone(),
-            files: vec![FileContent::new(lang, code, without_main)],
-        }
+            files,
+            opts: job.opts.clone(),
+            cargo,
+        })
     }
 
     fn get_generated_code(&self) -> String {
         self.files[0].content.clone()
     }
+
+    /// "// deps:" から作った Cargo プロジェクト（ローカルの Cargo で実行する）か
+    fn uses_cargo(&self) -> bool {
+        self.cargo
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct FileContent {
     name: String,
     content: String,
@@ -238,144 +1075,470 @@ impl FileContent {
     fn new<T: AsRef<str>>(lang: &Lang, code: T, without_main: bool) -> Self {
         let code = code.as_ref().to_string();
         Self {
-            name: format!("main.{}", lang_to_extension(lang)),
-            content: code_generator(code, lang, without_main),
+            name: format!("main.{}", lang_to_extension(&lang.language)),
+            content: codegen::code_generator(code, &lang.language, without_main),
         }
     }
 }
 
-/// 言語名から拡張子を生成
-fn lang_to_extension(lang: &Lang) -> String {
-    let lang = lang.language.clone();
-    // 言語 → 拡張子 のテーブル
-    let table: HashMap<&str, &str> = [
-        ("rust", "rs"),
-        ("python", "py"),
-        ("c++", "cpp"),
-        ("c", "c"),
-        ("java", "java"),
-        ("javascript", "js"),
-        ("typescript", "ts"),
-        ("go", "go"),
-        ("ruby", "rb"),
-        ("html", "html"),
-        ("css", "css"),
-        ("bash", "bash"),
-        ("haskell", "hs"),
-        ("lisp", "lisp"),
-        ("ocaml", "ml"),
-        ("prolog", "pl"),
-        ("zig", "zig"),
-        ("swift", "swift"),
-        ("scala", "sc"),
-        ("nim", "nim"),
-    ]
-    .iter()
-    .cloned()
-    .collect();
-
-    table
-        .get(lang.to_lowercase().as_str())
-        .unwrap_or(&"rs")
-        .to_string()
-}
-
-/// main() {}が必要かどうか
-fn reqire_main(lang: &Lang) -> bool {
-    matches!(
-        lang.language.to_lowercase().as_str(),
-        "rust" | "c++" | "c" | "go" | "java" | "zig"
-    )
-}
-
-/// `reqire_main()`によってmain(){}などを追加して実行可能なコードを生成
-fn code_generator<T: AsRef<str>>(code: T, lang: &Lang, without_main: bool) -> String {
-    let code = code.as_ref().to_string();
-    if reqire_main(lang) && !without_main {
-        let lang_name = lang.language.clone();
-        match lang_name.as_str() {
-            "rust" => format!("fn main() {{{code}}}"),
-            "c" => format!(
-                "#include <stdio.h>\n#include <stdlib.h>\n#include <string.h>\n#include <math.h>\nint main() {{{code}}}"
-            ),
-            "c++" => format!(
-                "#include <iostream>\n#include <string>\n#include <vector>\n#include <array>\nint main() {{{code}}}"
+/// エントリーポイントらしいファイルの位置。
+/// main / index という名前のファイル、次に main 関数などを含むファイルを選ぶ
+fn entry_point(files: &[FileContent]) -> Option<usize> {
+    const MARKERS: &[&str] = &[
+        "fn main(",
+        "int main(",
+        "func main(",
+        "static void main(",
+        "__name__ == \"__main__\"",
+        "pub fn main(",
+    ];
+    let stem = |f: &FileContent| {
+        let name = f.name.rsplit('/').next().unwrap_or(&f.name);
+        name.split('.').next().unwrap_or("").to_lowercase()
+    };
+    files
+        .iter()
+        .position(|f| matches!(stem(f).as_str(), "main" | "index"))
+        .or_else(|| {
+            files
+                .iter()
+                .position(|f| MARKERS.iter().any(|m| f.content.contains(m)))
+        })
+}
+
+// 言語 → 拡張子 のテーブル
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("rust", "rs"),
+    ("python", "py"),
+    ("c++", "cpp"),
+    ("c", "c"),
+    ("java", "java"),
+    ("javascript", "js"),
+    ("typescript", "ts"),
+    ("go", "go"),
+    ("ruby", "rb"),
+    ("html", "html"),
+    ("css", "css"),
+    ("bash", "bash"),
+    ("haskell", "hs"),
+    ("lisp", "lisp"),
+    ("ocaml", "ml"),
+    ("prolog", "pl"),
+    ("zig", "zig"),
+    ("swift", "swift"),
+    ("scala", "sc"),
+    ("nim", "nim"),
+];
+
+/// 言語名から拡張子を生成（rrepl と共通）
+pub fn lang_to_extension(lang: &str) -> &'static str {
+    let lang = lang.to_lowercase();
+    EXTENSIONS
+        .iter()
+        .find(|(name, _)| *name == lang)
+        .map_or("rs", |(_, ext)| ext)
+}
+
+/// ファイル名の拡張子から言語名を推測。表に無ければ拡張子をそのまま（エイリアスとして引く）
+fn extension_to_lang(file_name: &str) -> Option<&str> {
+    let (_, ext) = file_name.rsplit_once('.')?;
+    EXTENSIONS
+        .iter()
+        .find(|(_, e)| e.eq_ignore_ascii_case(ext))
+        .map(|(name, _)| *name)
+        .or(Some(ext))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        FileContent, Job, MAX_MESSAGE_SIZE, ReqJson, Resp, RunOptions, clip, execute, lang_choices,
+        version_choices,
+    };
+    use crate::piston::{Lang, Languages};
+    use crate::piston::{Piston, fake};
+    use crate::test_support::fake_http::FakeHttp;
+    use serde_json::json;
+
+    fn job(lang: &str, version: Option<&str>, code: &str) -> Job {
+        Job {
+            lang: lang.to_string(),
+            version: version.map(str::to_string),
+            code: Some(code.to_string()),
+            ..Job::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn execute_with_fake_piston() {
+        let fake = FakeHttp::start(
+            json!([
+                {"language": "python", "version": "3.10.0", "aliases": ["py", "py3"]},
+                {"language": "rust", "version": "1.68.2", "aliases": ["rs"]},
+            ]),
+            |req| fake::output(req, "3\n"),
+        );
+        let piston = Piston::new(fake::config(&fake));
+
+        let res = execute(&piston, &job("rs", None, "println!(\"{}\", 1 + 2);"))
+            .await
+            .unwrap();
+        assert!(
+            res.content
+                .starts_with("```rust\nfn main() {println!(\"{}\", 1 + 2);}\n```")
+        );
+        assert!(res.content.contains("version: 1.68.2"));
+        assert!(res.content.ends_with("stdout:\n```\n3\n```\nexit code 0"));
+        assert_eq!(fake.requests()[1].body["files"][0]["name"], "main.rs");
+
+        let err = execute(&piston, &job("cobol", None, "")).await.unwrap_err();
+        assert_eq!(err, "not supported lang: cobol");
+    }
+
+    #[tokio::test]
+    async fn execute_with_version() {
+        let fake = FakeHttp::start(
+            json!([
+                {"language": "python", "version": "3.10.0", "aliases": ["py"]},
+                {"language": "python", "version": "3.12.0", "aliases": ["py"]},
+            ]),
+            |req| fake::output(req, ""),
+        );
+        let piston = Piston::new(fake::config(&fake));
+
+        execute(&piston, &job("py", None, "print(1)"))
+            .await
+            .unwrap();
+        execute(&piston, &job("py", Some("3.10"), "print(1)"))
+            .await
+            .unwrap();
+        let reqs = fake.requests();
+        assert_eq!(reqs[1].body["version"], "3.12.0");
+        assert_eq!(reqs[2].body["version"], "3.10.0");
+
+        let err = execute(&piston, &job("py", Some("2.7"), ""))
+            .await
+            .unwrap_err();
+        assert_eq!(err, "python 2.7 はありません（利用可能: 3.12.0, 3.10.0）");
+    }
+
+    #[tokio::test]
+    async fn execute_with_stdin_and_limits() {
+        let fake = FakeHttp::start(
+            json!([{"language": "python", "version": "3.10.0", "aliases": ["py"]}]),
+            |req| fake::output(req, req["stdin"].as_str().unwrap_or("")),
+        );
+        let piston = Piston::new(fake::config(&fake));
+        let opts = RunOptions {
+            stdin: Some("hello".to_string()),
+            args: vec!["a".to_string(), "b c".to_string()],
+            run_timeout: Some(3000),
+            run_memory_limit: Some(128 * 1024 * 1024),
+            ..RunOptions::default()
+        };
+
+        let res = execute(
+            &piston,
+            &Job {
+                opts,
+                ..job("py", None, "print(input())")
+            },
+        )
+        .await
+        .unwrap();
+        assert!(res.content.contains(
+            "stdin: 5 bytes\nargs: [\"a\", \"b c\"]\nlimits: run timeout 3000ms, run memory 128MB\n"
+        ));
+        assert!(res.content.contains("hello"));
+
+        let body = &fake.requests()[1].body;
+        assert_eq!(body["stdin"], "hello");
+        assert_eq!(body["args"], json!(["a", "b c"]));
+        assert_eq!(body["run_timeout"], 3000);
+        assert_eq!(body["run_memory_limit"], 128 * 1024 * 1024);
+        // 未指定の項目は送らない
+        assert!(body.get("compile_timeout").is_none());
+
+        execute(&piston, &job("py", None, "")).await.unwrap();
+        let body = &fake.requests()[2].body;
+        assert!(body.get("stdin").is_none());
+        assert!(body.get("args").is_none());
+    }
+
+    #[tokio::test]
+    async fn execute_multiple_files() {
+        let fake = FakeHttp::start(
+            json!([{"language": "python", "version": "3.10.0", "aliases": ["py"]}]),
+            |req| fake::output(req, ""),
+        );
+        let piston = Piston::new(fake::config(&fake));
+        let file = |name: &str, content: &str| FileContent {
+            name: name.to_string(),
+            content: content.to_string(),
+        };
+
+        // コードが無ければエントリーポイントらしいファイルを先頭に
+        let res = execute(
+            &piston,
+            &Job {
+                lang: "py".to_string(),
+                files: vec![
+                    file("util.py", "def f(): return 1"),
+                    file(
+                        "app.py",
+                        "import util\nif __name__ == \"__main__\": print(util.f())",
+                    ),
+                ],
+                ..Job::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert!(res.content.contains("files: app.py, util.py\n"));
+        let body = &fake.requests()[1].body;
+        assert_eq!(body["files"][0]["name"], "app.py");
+        assert_eq!(body["files"][1]["name"], "util.py");
+
+        // コードがあればそれが main.py として先頭
+        execute(
+            &piston,
+            &Job {
+                files: vec![file("util.py", "def f(): return 1")],
+                ..job("py", None, "import util")
+            },
+        )
+        .await
+        .unwrap();
+        let body = &fake.requests()[2].body;
+        assert_eq!(body["files"][0]["name"], "main.py");
+        assert_eq!(body["files"][1]["name"], "util.py");
+
+        let err = execute(
+            &piston,
+            &Job {
+                files: vec![file("main.py", "")],
+                ..job("py", None, "print(1)")
+            },
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err, "main.py はコードと同じファイル名なので使えません");
+    }
+
+    #[tokio::test]
+    async fn long_results_are_clipped_and_attached() {
+        let stdout: String = (1..=5000).map(|n| format!("{n}\n")).collect();
+        let fake = FakeHttp::start(
+            json!([{"language": "python", "version": "3.10.0", "aliases": ["py"]}]),
+            move |req| match req["files"][0]["content"].as_str() {
+                Some("print(1)") => fake::output(req, "1\n"),
+                _ => fake::output(req, &stdout),
+            },
+        );
+        let piston = Piston::new(fake::config(&fake));
+        let code: String = (1..=40).map(|n| format!("x{n} = {n}\n")).collect();
+
+        let res = execute(&piston, &job("py", None, &code)).await.unwrap();
+        assert!(res.content.chars().count() <= MAX_MESSAGE_SIZE);
+        assert!(
+            res.content
+                .contains("x15 = 15\n```\n… あと 25 行 (main.py)\n")
+        );
+        assert!(res.content.contains("stdout:\n```\n1\n2\n"));
+        assert!(
+            res.content
+                .contains("20\n```\n… あと 4980 行 (stdout.txt)\nexit code 0")
+        );
+        let names: Vec<&str> = res.files.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, ["main.py", "stdout.txt"]);
+        assert_eq!(res.files[0].1, code);
+        assert!(res.files[1].1.ends_with("4999\n5000\n"));
+
+        // 収まる結果はそのまま
+        let res = execute(&piston, &job("py", None, "print(1)"))
+            .await
+            .unwrap();
+        assert!(res.files.is_empty());
+    }
+
+    #[test]
+    fn clips_by_lines_and_chars() {
+        assert_eq!(clip("a\nb\nc", 2, 100), ("a\nb\n".to_string(), 1));
+        assert_eq!(clip("a\nb", 5, 100), ("a\nb\n".to_string(), 0));
+        assert_eq!(clip("aaaa\nbb", 5, 6), ("aaaa\n".to_string(), 1));
+        // 1行目から長すぎるときは途中まで
+        assert_eq!(clip("abcdef", 5, 3), ("abc\n".to_string(), 1));
+    }
+
+    #[test]
+    fn modal_fills_pending_args() {
+        use super::{EvalArgs, PENDING, modal_args};
+        use std::time::Instant;
+
+        PENDING.lock().unwrap().insert(
+            42,
+            (
+                Instant::now(),
+                EvalArgs {
+                    lang: "python".to_string(),
+                    hide: true,
+                    opts: RunOptions {
+                        stdin: Some("1 2".to_string()),
+                        ..RunOptions::default()
+                    },
+                    ..EvalArgs::default()
+                },
             ),
-            "go" => format!("package main\nimport \"fmt\"\nfunc main(){{{code}}}"),
-            "java" => {
-                format!("public class Main {{public static void main(String[] args) {{{code}}}}}")
-            }
-            // for zig
-            _ => format!("const std = @import(\"std\");\npub fn main() void {{{code}}}"),
+        );
+        let data = |id: &str| {
+            serde_json::from_value(json!({
+                "custom_id": id,
+                "components": [
+                    {"type": 1, "components": [{"type": 4, "custom_id": "lang", "value": "py"}]},
+                    {"type": 1, "components": [{"type": 4, "custom_id": "code", "value": "if True:\n    print(1)"}]},
+                ],
+            }))
+            .unwrap()
+        };
+
+        let args = modal_args(&data("eval:new:42"));
+        assert_eq!(args.lang, "py");
+        assert_eq!(args.code.as_deref(), Some("if True:\n    print(1)"));
+        assert!(args.hide);
+        assert_eq!(args.opts.stdin.as_deref(), Some("1 2"));
+        assert!(PENDING.lock().unwrap().is_empty());
+
+        // 送信待ちが無くても言語とコードで実行できる
+        let args = modal_args(&data("eval:new:43"));
+        assert_eq!(args.lang, "py");
+        assert!(!args.hide);
+    }
+
+    #[test]
+    fn remembers_results_per_message() {
+        use super::{MAX_RESULTS, RESULTS, recall, remember};
+        use serenity::model::id::{MessageId, UserId};
+
+        let owner = UserId::new(1);
+        for n in 1..=MAX_RESULTS as u64 + 1 {
+            remember(
+                MessageId::new(n),
+                owner,
+                job("py", None, &format!("print({n})")),
+            );
         }
-    } else {
-        println!("{code}");
-        code
+        assert_eq!(RESULTS.lock().unwrap().len(), MAX_RESULTS);
+        // 上限を超えたら最も古いものから捨てる
+        assert!(recall(MessageId::new(1)).is_none());
+        let (user, stored) = recall(MessageId::new(2)).unwrap();
+        assert_eq!(user, owner);
+        assert_eq!(stored.code.as_deref(), Some("print(2)"));
     }
-}
 
-/// api対応言語を取得。
-/// TODO: キャッシュ用に24h程度の期限で外部保存できるようにする
-#[derive(Debug, Serialize, Deserialize)]
-struct Languages(Vec<Lang>);
+    #[test]
+    fn deps_make_cargo_project() {
+        let rust = Lang {
+            language: "rust".to_string(),
+            version: "1.68.2".to_string(),
+            aliases: Vec::new(),
+        };
+        let mut with_deps = job(
+            "rust",
+            None,
+            "// deps: rand = \"0.8\"\nprintln!(\"{}\", 1);",
+        );
+        with_deps.files.push(FileContent {
+            name: "util.rs".to_string(),
+            content: String::new(),
+        });
+        let req = ReqJson::new(&rust, &with_deps).unwrap();
+        let names: Vec<&str> = req.files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["src/main.rs", "Cargo.toml", "src/util.rs"]);
+        assert_eq!(req.files[0].content, "fn main() {println!(\"{}\", 1);}");
+        assert!(req.uses_cargo());
+
+        let plain = ReqJson::new(&rust, &job("rust", None, "println!(\"a\");")).unwrap();
+        assert!(!plain.uses_cargo());
 
-impl Languages {
-    pub async fn get_from_api() -> Result<Self, reqwest::Error> {
-        let client = reqwest::Client::new();
-        let res = client
-            .get("https://emkc.org/api/v2/piston/runtimes")
-            .send()
-            .await?;
-        Ok(Self(res.json::<Vec<Lang>>().await?))
+        // 利用者の Cargo.toml や build.rs は（deps がなくても）受け取らない
+        for name in ["Cargo.toml", "build.rs"] {
+            let mut custom = job("rust", None, "println!(\"a\");");
+            custom.files.push(FileContent {
+                name: name.to_string(),
+                content: "[dependencies]\nevil = { path = \"/\" }".to_string(),
+            });
+            let err = ReqJson::new(&rust, &custom).unwrap_err();
+            assert!(err.starts_with(&format!("{name} は使えません")), "{err}");
+        }
     }
 
-    fn get<T: AsRef<str>>(&self, lang: T) -> Option<&Lang> {
-        self.0
-            .iter()
-            .find(|s| s.language.to_lowercase() == lang.as_ref().to_lowercase())
+    #[test]
+    fn extensions() {
+        assert_eq!(super::extension_to_lang("main.rs"), Some("rust"));
+        assert_eq!(super::extension_to_lang("Main.JAVA"), Some("java"));
+        assert_eq!(super::extension_to_lang("a.kt"), Some("kt"));
+        assert_eq!(super::extension_to_lang("Makefile"), None);
     }
-}
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Lang {
-    language: String,
-    version: String,
-}
+    #[test]
+    fn result_sections() {
+        let resp: Resp = serde_json::from_value(json!({
+            "language": "rust",
+            "version": "1.68.2",
+            "compile": {
+                "stdout": "",
+                "stderr": "error[E0425]: cannot find value `x`",
+                "code": 1,
+                "signal": null,
+                "output": "error[E0425]: cannot find value `x`",
+            },
+        }))
+        .unwrap();
+        assert_eq!(
+            resp.to_string(),
+            "lang: rust\nversion: 1.68.2\ncompile: ⚠️ exit code 1\n```\nerror[E0425]: cannot find value `x`\n```\nrun: コンパイルに失敗したため実行されませんでした"
+        );
+
+        let resp: Resp = serde_json::from_value(json!({
+            "language": "python",
+            "version": "3.10.0",
+            "run": {
+                "stdout": "a\n",
+                "stderr": "Traceback\n",
+                "code": null,
+                "signal": "SIGKILL",
+                "output": "a\nTraceback\n",
+            },
+        }))
+        .unwrap();
+        assert_eq!(
+            resp.to_string(),
+            "lang: python\nversion: 3.10.0\nstdout:\n```\na\n```\nstderr:\n```\nTraceback\n```\n⚠️ signal SIGKILL"
+        );
+    }
 
-// struct Cache {
-//     time_stamp: chrono::DateTime<chrono::Utc>,
-//     content: Languages,
-// }
-
-// impl Cache {
-//     async fn new() -> Result<Self, reqwest::Error> {
-//         Ok(Self {
-//             time_stamp: chrono::Utc::now(),
-//             content: Languages::get_from_api().await?,
-//         })
-//     }
-//     fn is_cache_outdated(&self) -> bool {
-//         let now = chrono::Utc::now();
-//         let elapsed = now - self.time_stamp;
-//         elapsed > chrono::Duration::hours(24)
-//     }
-//     fn get_about_lang<T: AsRef<str>>(&self, lang: T) -> Option<&Lang> {
-//         // 本当はHashMapにした方が検索時間が減るが、apiはリストを返すので変換が不便
-//         // また要素数は極めて限定的なため検索時間におけるオーバーヘッドは無視できるはす
-//         self.content
-//             .0
-//             .iter()
-//             .find(|s| s.language == lang.as_ref().to_string())
-//     }
-//     async fn update(&mut self) -> Result<(), reqwest::Error> {
-//         let lst = Languages::get_from_api().await?;
-//         self.time_stamp = chrono::Utc::now();
-//         self.content = lst;
-//         Ok(())
-//     }
-//     fn check(&mut self) {
-//         if self.is_cache_outdated() {
-//             self.update();
-//         }
-//     }
-// }
+    #[test]
+    fn choices() {
+        let langs: Languages = serde_json::from_value(json!([
+            {"language": "python", "version": "3.10.0", "aliases": ["py", "py3"]},
+            {"language": "python", "version": "3.12.0", "aliases": ["py", "py3"]},
+            {"language": "rust", "version": "1.68.2", "aliases": []},
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            lang_choices(&langs, "py"),
+            [("python (py, py3)".to_string(), "python".to_string())]
+        );
+        assert_eq!(lang_choices(&langs, "").len(), 2);
+        assert_eq!(
+            version_choices(&langs, "py", ""),
+            [
+                ("3.12.0 (latest)".to_string(), "3.12.0".to_string()),
+                ("3.10.0".to_string(), "3.10.0".to_string()),
+            ]
+        );
+        assert_eq!(version_choices(&langs, "py", "3.10").len(), 1);
+        assert!(version_choices(&langs, "cobol", "").is_empty());
+    }
+}
@@ -0,0 +1,463 @@
+// 実行可能なコードの生成
+//
+// main が必要な言語では、貼られたコードを main で包む。
+// - すでにエントリーポイント (fn main など) があればそのまま
+// - use / #include / import や struct / fn の定義は main の外に出す
+// - Rust は最後の式を REPL のように {:?} で表示する
+
+/// main() {}が必要かどうか
+pub fn reqire_main(lang: &str) -> bool {
+    matches!(
+        lang.to_lowercase().as_str(),
+        "rust" | "c++" | "c" | "go" | "java" | "zig"
+    )
+}
+
+/// `reqire_main()`によってmain(){}などを追加して実行可能なコードを生成
+pub fn code_generator<T: AsRef<str>>(code: T, lang: &str, without_main: bool) -> String {
+    let code = code.as_ref().to_string();
+    let lang = lang.to_lowercase();
+    if !reqire_main(&lang) || without_main || has_entry_point(&lang, &code) {
+        return code;
+    }
+    let (items, body) = split_items(&code, &lang);
+    let items = if items.is_empty() {
+        items
+    } else {
+        items + "\n"
+    };
+    match lang.as_str() {
+        "rust" => rust_main(&items, &body),
+        "c" => format!(
+            "#include <stdio.h>\n#include <stdlib.h>\n#include <string.h>\n#include <math.h>\n{items}int main() {{{body}}}"
+        ),
+        "c++" => format!(
+            "#include <iostream>\n#include <string>\n#include <vector>\n#include <array>\n{items}int main() {{{body}}}"
+        ),
+        "go" => {
+            // 使っていない import はエラーになるので、fmt は使うときだけ足す
+            let fmt = if code.contains("fmt.") && !items.contains("\"fmt\"") {
+                "import \"fmt\"\n"
+            } else {
+                ""
+            };
+            format!("package main\n{fmt}{items}func main(){{{body}}}")
+        }
+        "java" => java_main(&items, &body),
+        // for zig
+        _ => {
+            let std = if items.contains("const std ") {
+                ""
+            } else {
+                "const std = @import(\"std\");\n"
+            };
+            format!("{std}{items}pub fn main() !void {{{body}}}")
+        }
+    }
+}
+
+/// すでにエントリーポイントが書かれているか
+fn has_entry_point(lang: &str, code: &str) -> bool {
+    let (items, _) = split_items(code, lang);
+    let starts = |prefixes: &[&str]| {
+        items
+            .lines()
+            .map(|l| l.trim_start())
+            .any(|l| prefixes.iter().any(|p| l.starts_with(p)))
+    };
+    match lang {
+        "rust" => starts(&["fn main(", "pub fn main(", "async fn main("]),
+        "c" | "c++" => starts(&["int main(", "int main (", "void main("]),
+        "go" => starts(&["package "]) || starts(&["func main("]),
+        "java" => code.contains("static void main("),
+        _ => starts(&["pub fn main("]),
+    }
+}
+
+/// 最後の式があれば表示する main
+fn rust_main(items: &str, body: &str) -> String {
+    let Some((stmts, expr)) = trailing_expr(body) else {
+        return format!("{items}fn main() {{{body}}}");
+    };
+    // () は表示しない（println!() などで終わる場合）
+    format!(
+        "{items}fn __show<T: std::fmt::Debug>(v: T) {{\n    if std::any::type_name::<T>() != \"()\" {{\n        println!(\"{{:?}}\", v);\n    }}\n}}\nfn main() {{{stmts}\n__show({{\n{expr}\n}});\n}}"
+    )
+}
+
+/// import は先頭、クラスなどの型は Main の外、メソッドは Main の中に置く
+fn java_main(items: &str, body: &str) -> String {
+    let mut imports = String::new();
+    let mut types = String::new();
+    let mut members = String::new();
+    let mut current = &mut members;
+    let mut scan = Scanner::default();
+    for line in items.lines() {
+        let trimmed = line.trim_start();
+        let mut line = line.to_string();
+        if scan.depth == 0 {
+            current = if trimmed.starts_with("import ") {
+                &mut imports
+            } else if is_java_type(trimmed) {
+                // Main.java の中で public なクラスは Main だけ
+                if let Some(rest) = trimmed.strip_prefix("public ") {
+                    line = rest.to_string();
+                }
+                &mut types
+            } else {
+                &mut members
+            };
+        }
+        scan.line(trimmed);
+        current.push_str(&line);
+        current.push('\n');
+    }
+    format!(
+        "{imports}public class Main {{\n{members}public static void main(String[] args) {{{body}}}}}\n{types}"
+    )
+}
+
+fn is_java_type(line: &str) -> bool {
+    let line = strip_modifiers(line, &["public ", "final ", "abstract ", "sealed "]);
+    ["class ", "interface ", "enum ", "record "]
+        .iter()
+        .any(|k| line.starts_with(k))
+}
+
+fn strip_modifiers<'a>(mut line: &'a str, modifiers: &[&str]) -> &'a str {
+    while let Some(rest) = modifiers.iter().find_map(|m| line.strip_prefix(m)) {
+        line = rest.trim_start();
+    }
+    line
+}
+
+/// トップレベルの宣言（main の外に出すもの）と、それ以外（main に入れる文）に分ける
+fn split_items(code: &str, lang: &str) -> (String, String) {
+    let mut items = Vec::new();
+    let mut body = Vec::new();
+    let mut scan = Scanner::default();
+    let mut in_item = false;
+    for line in code.lines() {
+        let trimmed = line.trim();
+        if scan.depth == 0 && !scan.in_literal() && !in_item && is_item(lang, trimmed) {
+            in_item = true;
+        }
+        scan.line(line);
+        if in_item {
+            items.push(line);
+        } else {
+            body.push(line);
+        }
+        if in_item && scan.depth == 0 && item_ends(lang, trimmed) {
+            in_item = false;
+        }
+    }
+    (items.join("\n"), body.join("\n"))
+}
+
+/// main の外に出す宣言の書き出しか
+fn is_item(lang: &str, line: &str) -> bool {
+    let starts = |keywords: &[&str]| keywords.iter().any(|k| line.starts_with(k));
+    match lang {
+        "rust" => {
+            let line = strip_modifiers(line, &["pub(crate) ", "pub ", "unsafe ", "async "]);
+            [
+                "use ",
+                "mod ",
+                "struct ",
+                "enum ",
+                "union ",
+                "fn ",
+                "impl ",
+                "impl<",
+                "trait ",
+                "type ",
+                "const ",
+                "static ",
+                "extern ",
+                "macro_rules!",
+                "#[",
+                "#![",
+            ]
+            .iter()
+            .any(|k| line.starts_with(k))
+        }
+        "c" | "c++" => {
+            starts(&[
+                "#",
+                "typedef ",
+                "using ",
+                "template",
+                "namespace ",
+                "extern ",
+            ]) || (starts(&["struct ", "class ", "enum ", "union "]) && !line.contains('='))
+                || looks_like_function(line)
+        }
+        "go" => starts(&["import ", "import(", "type ", "func ", "package "]),
+        "java" => {
+            starts(&["import ", "@"])
+                || is_java_type(line)
+                || starts(&["static ", "private ", "public ", "protected "])
+                || looks_like_function(line)
+        }
+        // zig: ローカルの値を参照しうる const は中に残す
+        _ => {
+            starts(&["fn ", "pub ", "extern ", "export ", "test "])
+                || (starts(&["const "])
+                    && [
+                        "@import(", "= struct", "= enum", "= union", "= packed", "= extern",
+                    ]
+                    .iter()
+                    .any(|k| line.contains(k)))
+        }
+    }
+}
+
+/// 宣言がこの行で終わるか（深さ 0 に戻った後で呼ぶ）
+fn item_ends(lang: &str, line: &str) -> bool {
+    // 属性・アノテーションは次の行の宣言に付く
+    if line.is_empty() || line.starts_with("#[") || line.starts_with('@') {
+        return false;
+    }
+    match lang {
+        "go" => !line.ends_with(','),
+        "c" | "c++" if line.starts_with('#') => !line.ends_with('\\'),
+        _ => line.ends_with(';') || line.ends_with('}'),
+    }
+}
+
+/// "int add(int a, int b) {" のような関数定義の1行目か (C / C++ / Java)
+fn looks_like_function(line: &str) -> bool {
+    const NOT_TYPES: &[&str] = &[
+        "if", "for", "while", "switch", "return", "else", "do", "case", "sizeof", "new", "delete",
+        "throw",
+    ];
+    let Some(paren) = line.find('(') else {
+        return false;
+    };
+    let head = line[..paren].trim();
+    let words: Vec<&str> = head.split_whitespace().collect();
+    let Some(name) = words.last().map(|w| w.trim_start_matches(['*', '&'])) else {
+        return false;
+    };
+    // 型と名前の2語以上で、代入・メソッド呼び出し・出力 (<<) ではない
+    words.len() >= 2
+        && !NOT_TYPES.contains(&words[0])
+        && !head.contains(['=', '.', '"'])
+        && !head.contains("<<")
+        && !line.ends_with(';')
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == ':')
+}
+
+/// 最後の式を (それまでの文, 式) に分ける。式で終わっていなければ None
+pub fn trailing_expr(body: &str) -> Option<(&str, &str)> {
+    let mut scan = Scanner::default();
+    let mut end = 0;
+    for (i, c) in body.char_indices() {
+        let in_code = scan.in_code();
+        let top = scan.depth == 0;
+        scan.push(c, body[i + c.len_utf8()..].chars().next());
+        // 深さ 0 の ; と、ブロックを閉じる } が文の終わり
+        if in_code && ((top && c == ';') || (c == '}' && scan.depth == 0)) {
+            end = i + 1;
+        }
+    }
+    let expr = body[end..].trim();
+    let is_comment = expr.lines().all(|l| {
+        let l = l.trim();
+        l.is_empty() || l.starts_with("//")
+    });
+    (!is_comment).then(|| (&body[..end], expr))
+}
+
+/// 文字列・コメントを読み飛ばしつつ括弧の深さを数える
+#[derive(Debug, Default)]
+struct Scanner {
+    depth: i32,
+    // 文字列の中 (閉じる引用符)
+    string: Option<char>,
+    escaped: bool,
+    // 文字リテラルの中 (読んだ文字数, \ で始まったか)
+    char_lit: Option<(usize, bool)>,
+    line_comment: bool,
+    block_comment: bool,
+    prev: Option<char>,
+}
+
+impl Scanner {
+    fn in_literal(&self) -> bool {
+        self.string.is_some() || self.char_lit.is_some() || self.block_comment
+    }
+
+    fn in_code(&self) -> bool {
+        !self.in_literal() && !self.line_comment
+    }
+
+    fn line(&mut self, line: &str) {
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            self.push(c, chars.peek().copied());
+        }
+        self.push('\n', None);
+    }
+
+    fn push(&mut self, c: char, next: Option<char>) {
+        let prev = self.prev.replace(c);
+        if self.line_comment {
+            self.line_comment = c != '\n';
+            return;
+        }
+        if self.block_comment {
+            if prev == Some('*') && c == '/' {
+                self.block_comment = false;
+                self.prev = None;
+            }
+            return;
+        }
+        if let Some(quote) = self.string {
+            if self.escaped {
+                self.escaped = false;
+            } else if c == '\\' && quote != '`' {
+                self.escaped = true;
+            } else if c == quote {
+                self.string = None;
+            }
+            return;
+        }
+        if let Some((len, escape)) = &mut self.char_lit {
+            *len += 1;
+            match (*len, *escape) {
+                (1, _) => {
+                    *escape = c == '\\';
+                    return;
+                }
+                // '\'' のような2文字目は閉じない
+                (2, true) => return,
+                (_, true) | (2, false) if c == '\'' => {
+                    self.char_lit = None;
+                    return;
+                }
+                (_, true) => return,
+                // 'a の後に ' が来なければライフタイム。この文字は普通に読む
+                _ => self.char_lit = None,
+            }
+        }
+        match c {
+            '/' if next == Some('/') => self.line_comment = true,
+            '/' if next == Some('*') => {
+                self.block_comment = true;
+                self.prev = None;
+            }
+            '"' | '`' => self.string = Some(c),
+            '\'' => self.char_lit = Some((0, false)),
+            '(' | '[' | '{' => self.depth += 1,
+            ')' | ']' | '}' => self.depth = (self.depth - 1).max(0),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_existing_entry_point() {
+        let code = "use std::collections::HashMap;\n\nfn main() {\n    let m: HashMap<i32, i32> = HashMap::new();\n}";
+        assert_eq!(code_generator(code, "rust", false), code);
+        let code = "#include <stdio.h>\nint main() { puts(\"hi\"); }";
+        assert_eq!(code_generator(code, "c", false), code);
+        let code = "class A { public static void main(String[] a) {} }";
+        assert_eq!(code_generator(code, "java", false), code);
+        assert_eq!(code_generator("print(1)", "python", false), "print(1)");
+    }
+
+    #[test]
+    fn rust_hoists_items() {
+        let code = "use std::fmt;\n#[derive(Debug)]\nstruct P {\n    x: i32,\n}\nfn twice(n: i32) -> i32 {\n    n * 2\n}\nprintln!(\"{}\", twice(2));";
+        assert_eq!(
+            code_generator(code, "rust", false),
+            "use std::fmt;\n#[derive(Debug)]\nstruct P {\n    x: i32,\n}\nfn twice(n: i32) -> i32 {\n    n * 2\n}\nfn main() {println!(\"{}\", twice(2));}"
+        );
+        // 1行だけの文はそのまま包む
+        assert_eq!(
+            code_generator("println!(\"{}\", 1 + 2);", "rust", false),
+            "fn main() {println!(\"{}\", 1 + 2);}"
+        );
+    }
+
+    #[test]
+    fn rust_expression_mode() {
+        let out = code_generator(
+            "let v = vec![1, 2];\nv.iter().map(|x| { x * 2 }).collect::<Vec<_>>()",
+            "rust",
+            false,
+        );
+        assert!(out.contains("fn __show<T: std::fmt::Debug>"));
+        assert!(out.ends_with(
+            "fn main() {let v = vec![1, 2];\n__show({\nv.iter().map(|x| { x * 2 }).collect::<Vec<_>>()\n});\n}"
+        ));
+
+        // ブロックや文字列中の ; } に惑わされない
+        assert_eq!(
+            trailing_expr("for i in 0..3 { println!(\"{}\", i); }"),
+            None
+        );
+        assert_eq!(
+            trailing_expr("let s = \"a;b}\";\ns.len() // 長さ"),
+            Some(("let s = \"a;b}\";", "s.len() // 長さ"))
+        );
+        assert_eq!(
+            trailing_expr("let c = '}';\nc"),
+            Some(("let c = '}';", "c"))
+        );
+        assert_eq!(trailing_expr("x += 1; // done"), None);
+    }
+
+    #[test]
+    fn c_family() {
+        let code = "#include <assert.h>\nint add(int a, int b) {\n    return a + b;\n}\nprintf(\"%d\\n\", add(1, 2));";
+        let out = code_generator(code, "c", false);
+        assert!(out.ends_with("#include <assert.h>\nint add(int a, int b) {\n    return a + b;\n}\nint main() {printf(\"%d\\n\", add(1, 2));}"));
+
+        let code = "using namespace std;\nstruct P { int x; };\nvector<int> v(3);\ncout << v.size() << endl;";
+        let out = code_generator(code, "c++", false);
+        assert!(out.ends_with("using namespace std;\nstruct P { int x; };\nint main() {vector<int> v(3);\ncout << v.size() << endl;}"));
+    }
+
+    #[test]
+    fn go_and_zig() {
+        assert_eq!(
+            code_generator("x := 1\nprintln(x)", "go", false),
+            "package main\nfunc main(){x := 1\nprintln(x)}"
+        );
+        assert_eq!(
+            code_generator(
+                "import \"strings\"\nfmt.Println(strings.ToUpper(\"a\"))",
+                "go",
+                false
+            ),
+            "package main\nimport \"fmt\"\nimport \"strings\"\nfunc main(){fmt.Println(strings.ToUpper(\"a\"))}"
+        );
+        assert_eq!(
+            code_generator("const x = 1;\nstd.debug.print(\"{}\", .{x});", "zig", false),
+            "const std = @import(\"std\");\npub fn main() !void {const x = 1;\nstd.debug.print(\"{}\", .{x});}"
+        );
+    }
+
+    #[test]
+    fn java_layout() {
+        let code = "import java.util.*;\npublic record P(int x) {}\nstatic int twice(int n) {\n    return n * 2;\n}\nSystem.out.println(twice(new P(2).x()));";
+        assert_eq!(
+            code_generator(code, "java", false),
+            "import java.util.*;\npublic class Main {\nstatic int twice(int n) {\n    return n * 2;\n}\npublic static void main(String[] args) {System.out.println(twice(new P(2).x()));}}\nrecord P(int x) {}\n"
+        );
+    }
+
+    #[test]
+    fn without_main_is_untouched() {
+        assert_eq!(code_generator("1 + 1", "rust", true), "1 + 1");
+    }
+}
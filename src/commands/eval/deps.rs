@@ -0,0 +1,220 @@
+// Rust コードの "// deps: serde = "1", rand = "0.8"" ヘッダー
+//
+// ヘッダーがあるコードは Piston (素の rustc) では動かないので、
+// Cargo.toml 付きの小さなプロジェクトにしてローカルの Cargo で実行する (cargo_runner.rs)。
+// rrepl のセッションでは入力が連結されるので、ヘッダーは先頭以外の行にあってもよい。
+
+use super::codegen;
+
+pub const MANIFEST: &str = "Cargo.toml";
+pub const MAIN: &str = "src/main.rs";
+// 利用者からは受け取らないファイル名（ビルドの設定・ビルドスクリプト）
+const RESERVED: &[&str] = &[MANIFEST, "Cargo.lock", "build.rs", ".cargo"];
+
+// インラインテーブルで指定できるキー（path や git は不可）
+const TABLE_KEYS: &[&str] = &["version", "features", "default-features"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dep {
+    pub name: String,
+    // Cargo.toml にそのまま書く値 ("1" や { version = "1", features = ["derive"] })
+    pub spec: String,
+}
+
+/// "// deps:" の行を取り出して (依存, 残りのコード) にする
+pub fn take_deps(code: &str) -> Result<(Vec<Dep>, String), String> {
+    let mut deps: Vec<Dep> = Vec::new();
+    let mut rest = Vec::new();
+    for line in code.lines() {
+        let header = line
+            .trim()
+            .strip_prefix("//")
+            .map(str::trim_start)
+            .and_then(|l| l.strip_prefix("deps:"));
+        match header {
+            Some(list) => {
+                for dep in parse_list(list)? {
+                    // 同じクレートは後の指定で上書き
+                    deps.retain(|d| d.name != dep.name);
+                    deps.push(dep);
+                }
+            }
+            None => rest.push(line),
+        }
+    }
+    Ok((deps, rest.join("\n")))
+}
+
+/// 利用者が渡してはいけないファイルか（どのディレクトリにあっても）
+pub fn is_reserved(name: &str) -> bool {
+    name.split(['/', '\\'])
+        .any(|part| RESERVED.iter().any(|r| part.eq_ignore_ascii_case(r)))
+}
+
+/// ヘッダーがあれば Cargo プロジェクトのファイル (名前, 中身) を作る。main.rs が先頭
+pub fn cargo_project(
+    code: &str,
+    without_main: bool,
+) -> Result<Option<Vec<(String, String)>>, String> {
+    let (deps, rest) = take_deps(code)?;
+    if deps.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(vec![
+        (
+            MAIN.to_string(),
+            codegen::code_generator(rest, "rust", without_main),
+        ),
+        (MANIFEST.to_string(), manifest(&deps)),
+    ]))
+}
+
+pub fn manifest(deps: &[Dep]) -> String {
+    let deps: String = deps
+        .iter()
+        .map(|d| format!("{} = {}\n", d.name, d.spec))
+        .collect();
+    format!(
+        "[package]\nname = \"main\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\n{deps}"
+    )
+}
+
+/// `serde = "1", rand = "0.8"` を読む
+fn parse_list(list: &str) -> Result<Vec<Dep>, String> {
+    split_top(list, ',')
+        .into_iter()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(parse_dep)
+        .collect()
+}
+
+fn parse_dep(entry: &str) -> Result<Dep, String> {
+    let invalid = || format!("deps の書き方が正しくありません: {entry}");
+    let (name, spec) = match entry.split_once('=') {
+        Some((name, spec)) => (name.trim(), spec.trim()),
+        // バージョン省略は最新
+        None => (entry, "\"*\""),
+    };
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(invalid());
+    }
+    let spec = if let Some(table) = spec.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+        for field in split_top(table, ',').into_iter().map(str::trim) {
+            let (key, value) = field.split_once('=').ok_or_else(invalid)?;
+            if !TABLE_KEYS.contains(&key.trim()) || !is_value(value.trim()) {
+                return Err(invalid());
+            }
+        }
+        spec.to_string()
+    } else if is_version(spec.trim_matches('"')) {
+        format!("\"{}\"", spec.trim_matches('"'))
+    } else {
+        return Err(invalid());
+    };
+    Ok(Dep {
+        name: name.to_string(),
+        spec,
+    })
+}
+
+/// "1.0" / ["derive", "std"] / true
+fn is_value(value: &str) -> bool {
+    let quoted = |s: &str| {
+        s.len() >= 2
+            && s.starts_with('"')
+            && s.ends_with('"')
+            && !s[1..s.len() - 1].contains(['"', '\\'])
+    };
+    match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+        Some(items) => items
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .all(quoted),
+        None => quoted(value) || value == "true" || value == "false",
+    }
+}
+
+fn is_version(v: &str) -> bool {
+    !v.is_empty()
+        && v.chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".^~*<>=-, ".contains(c))
+}
+
+/// 括弧・文字列の外にある `sep` で分ける
+fn split_top(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut quoted, mut start) = (0i32, false, 0);
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '{' | '[' if !quoted => depth += 1,
+            '}' | ']' if !quoted => depth -= 1,
+            c if c == sep && !quoted && depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_header() {
+        let code = "// deps: serde = { version = \"1\", features = [\"derive\"] }, rand = \"0.8\"\n//deps: itertools\nuse rand::Rng;\nprintln!(\"hi\");";
+        let (deps, rest) = take_deps(code).unwrap();
+        assert_eq!(
+            deps.iter().map(|d| d.name.as_str()).collect::<Vec<_>>(),
+            ["serde", "rand", "itertools"]
+        );
+        assert_eq!(deps[0].spec, "{ version = \"1\", features = [\"derive\"] }");
+        assert_eq!(deps[2].spec, "\"*\"");
+        assert_eq!(rest, "use rand::Rng;\nprintln!(\"hi\");");
+        assert!(
+            manifest(&deps).ends_with("[dependencies]\nserde = { version = \"1\", features = [\"derive\"] }\nrand = \"0.8\"\nitertools = \"*\"\n")
+        );
+
+        // パス・git 指定やおかしな名前は使えない
+        assert!(take_deps("// deps: evil = { path = \"/etc\" }").is_err());
+        assert!(take_deps("// deps: a b = \"1\"").is_err());
+    }
+
+    #[test]
+    fn reserves_build_files() {
+        for name in [
+            "Cargo.toml",
+            "cargo.toml",
+            "build.rs",
+            "sub/build.rs",
+            ".cargo/config.toml",
+        ] {
+            assert!(is_reserved(name), "{name}");
+        }
+        assert!(!is_reserved("util.rs"));
+        assert!(!is_reserved("src/builder.rs"));
+    }
+
+    #[test]
+    fn builds_project_only_with_deps() {
+        assert_eq!(cargo_project("println!(\"a\");", false).unwrap(), None);
+        let files = cargo_project("// deps: rand = \"0.8\"\nprintln!(\"a\");", false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            files[0],
+            (MAIN.to_string(), "fn main() {println!(\"a\");}".to_string())
+        );
+        assert_eq!(files[1].0, MANIFEST);
+    }
+}
@@ -3,11 +3,10 @@ use std::{collections::HashMap, time::Duration};
 use once_cell::sync::Lazy;
 use reqwest::Client;
 use serenity::{
-    builder::{
-        CreateAttachment, CreateCommand, CreateCommandOption, CreateMessage, EditAttachments,
-    },
+    async_trait,
+    builder::{CreateAttachment, CreateCommandOption, CreateMessage, EditAttachments},
     model::{
-        application::{CommandDataOptionValue, CommandOptionType},
+        application::{CommandInteraction, CommandOptionType},
         channel::Message,
     },
     prelude::Context,
@@ -15,6 +14,8 @@ use serenity::{
 use std::sync::Mutex;
 use url::Url;
 
+use super::args::{self, Args, FromArgs};
+
 const MAX_FILE_SIZE: usize = 10_000_000; // 10 MB
 const MAX_MESSAGE_SIZE: usize = 1900; // for code block safety
 const TIMEOUT_SECS: u64 = 5;
@@ -86,6 +87,20 @@ async fn http_get(
     Ok((bytes, content_type))
 }
 
+struct GetArgs {
+    url: String,
+    headers: Option<String>,
+}
+
+impl FromArgs for GetArgs {
+    fn from_args(args: &Args) -> Result<Self, String> {
+        Ok(Self {
+            url: args.required_str("url")?,
+            headers: args.str("headers").map(str::to_string),
+        })
+    }
+}
+
 pub fn is_html(content_type: &str) -> bool {
     content_type.starts_with("text/html")
 }
@@ -157,61 +172,33 @@ pub async fn run(ctx: &Context, msg: &Message) -> serenity::Result<()> {
             .await?;
         return Ok(());
     }
-    let content = msg.content.trim();
-    let rest = content
-        .strip_prefix(super::PREFIX)
-        .map(str::trim_start)
-        .and_then(|s| s.strip_prefix(NAME))
-        .map(str::trim)
-        .unwrap_or("");
-
-    if rest.is_empty() {
+    // parse: url, optional --headers <json> (JSON は括弧が閉じるまで1つの値として読む)
+    let Ok(GetArgs { url, headers }) = args::from_message::<GetArgs>(&Get, msg) else {
         msg.channel_id
             .say(&ctx.http, "使い方: !get <url> [--headers <json>]")
             .await?;
         return Ok(());
-    }
-
-    // parse: first token is url, optional --headers <json>
-    let mut parts = rest.split_whitespace();
-    let url = parts.next().unwrap_or("");
+    };
 
-    if let Err(e) = validate_url(url) {
+    if let Err(e) = validate_url(&url) {
         msg.channel_id.say(&ctx.http, e).await?;
         return Ok(());
     }
 
-    // Collect the remainder as potential --headers JSON (supports spaces in JSON by joining)
-    let mut headers_json = String::new();
-    let mut saw_flag = false;
-    for p in parts {
-        if !saw_flag {
-            if p == "--headers" {
-                saw_flag = true;
-            }
-            continue;
-        }
-        if !headers_json.is_empty() {
-            headers_json.push(' ');
-        }
-        headers_json.push_str(p);
-    }
-
-    let headers = if saw_flag {
-        match parse_headers_json(&headers_json) {
+    let headers = match headers {
+        Some(s) => match parse_headers_json(&s) {
             Ok(h) => h,
             Err(e) => {
                 msg.channel_id.say(&ctx.http, e).await?;
                 return Ok(());
             }
-        }
-    } else {
-        HashMap::new()
+        },
+        None => HashMap::new(),
     };
 
     msg.channel_id.say(&ctx.http, "取得中…").await?;
 
-    match http_get(url, headers).await {
+    match http_get(&url, headers).await {
         Ok((bytes, ct)) => {
             if let Some(mut s) = to_display_text(&bytes, &ct) {
                 if s.len() > MAX_MESSAGE_SIZE {
@@ -252,10 +239,7 @@ pub async fn run(ctx: &Context, msg: &Message) -> serenity::Result<()> {
 }
 
 // スラッシュ実行: /get url:<url> headers:<json?>
-pub async fn slash_execute(
-    ctx: &Context,
-    command: &serenity::model::application::CommandInteraction,
-) -> serenity::Result<()> {
+pub async fn slash_execute(ctx: &Context, command: &CommandInteraction) -> serenity::Result<()> {
     // rate limit per user (no await while holding lock)
     let mut cooldown_remain: Option<u64> = None;
     {
@@ -287,30 +271,12 @@ pub async fn slash_execute(
             .await?;
         return Ok(());
     }
-    let mut url: Option<String> = None;
-    let mut headers_json: Option<String> = None;
-    for opt in &command.data.options {
-        match (opt.name.as_str(), &opt.value) {
-            ("url", CommandDataOptionValue::String(s)) => url = Some(s.clone()),
-            ("headers", CommandDataOptionValue::String(s)) => headers_json = Some(s.clone()),
-            _ => {}
-        }
-    }
-
-    let url = match url {
-        Some(u) => u,
-        None => {
-            command
-                .create_response(
-                    &ctx.http,
-                    serenity::builder::CreateInteractionResponse::Message(
-                        serenity::builder::CreateInteractionResponseMessage::new()
-                            .content("url が必要です"),
-                    ),
-                )
-                .await?;
-            return Ok(());
-        }
+    let GetArgs {
+        url,
+        headers: headers_json,
+    } = match args::from_interaction::<GetArgs>(command) {
+        Ok(a) => a,
+        Err(e) => return super::respond(ctx, command, e).await,
     };
 
     if let Err(e) = validate_url(&url) {
@@ -439,15 +405,38 @@ pub async fn slash_execute(
     }
 }
 
-pub fn slash_register() -> CreateCommand {
-    CreateCommand::new(NAME)
-        .description(DESCRIPTION)
-        .add_option(
+pub struct Get;
+
+#[async_trait]
+impl super::Command for Get {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn description(&self) -> &'static str {
+        DESCRIPTION
+    }
+
+    fn options(&self) -> Vec<CreateCommandOption> {
+        vec![
             CreateCommandOption::new(CommandOptionType::String, "url", "取得先URL").required(true),
-        )
-        .add_option(CreateCommandOption::new(
-            CommandOptionType::String,
-            "headers",
-            "JSON 形式のヘッダー (任意)",
-        ))
+            CreateCommandOption::new(
+                CommandOptionType::String,
+                "headers",
+                "JSON 形式のヘッダー (任意)",
+            ),
+        ]
+    }
+
+    async fn run(&self, ctx: &Context, msg: &Message) -> serenity::Result<()> {
+        run(ctx, msg).await
+    }
+
+    async fn slash_execute(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> serenity::Result<()> {
+        slash_execute(ctx, command).await
+    }
 }
@@ -1,61 +1,87 @@
+// /gpt: LLM に質問する
+//
+// thread を付けるとスレッドを作り、そこに書いたメッセージはそれまでのやり取りを踏まえて答える
+// （会話は conversation.rs）。答えの下の「分岐」でその時点から別のスレッドに分かれ、
+// 「終了」で会話を終えてスレッドをしまう。
+// 答えは届いた分から表示し（書き換えは EDIT_INTERVAL ごと）、最後に全体で書き直す。
+// system プロンプトはプレフィックス・スラッシュとも prompts.rs の設定（/gpt-config）を使う。
+
 use serenity::{
-    builder::{CreateCommand, CreateCommandOption, EditAttachments},
+    async_trait,
+    builder::{
+        CreateActionRow, CreateAttachment, CreateButton, CreateCommandOption,
+        CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage, CreateThread,
+        EditInteractionResponse, EditMessage, EditThread,
+    },
     model::{
-        application::{CommandDataOptionValue, CommandOptionType},
-        channel::Message,
+        application::{ButtonStyle, CommandInteraction, CommandOptionType, ComponentInteraction},
+        channel::{ChannelType, Message},
+        id::{ChannelId, MessageId, UserId},
     },
     prelude::Context,
 };
 
-use std::process::Stdio;
-use tokio::process::Command;
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc;
+
+use super::args::{self, Args, FromArgs};
+use crate::llm::{self, ChatMessage, LlmProvider};
+
+mod conversation;
+pub mod prompts;
 
 const MAX_MESSAGE_SIZE: usize = 1900; // safety margin for code blocks
 const MAX_FILE_SIZE: usize = 10_000_000; // 10 MB
-const TIMEOUT_SECS: u64 = 30; // external tool timeout
+// スレッド名の最大文字数 (Discord の上限は 100)
+const MAX_THREAD_NAME: usize = 90;
+// 受け取り途中の答えで書き換える間隔（Discord のレート制限に掛からないように）
+const EDIT_INTERVAL: Duration = Duration::from_millis(1500);
+// 答えが届くまでの表示
+pub const THINKING: &str = "💭 …";
 
 pub const NAME: &str = "gpt";
-pub const DESCRIPTION: &str = "tgpt で回答を取得します";
-
-async fn run_tgpt(query: &str, preprompt: &str) -> Result<Vec<u8>, String> {
-    // Run: tgpt --quiet --preprompt <preprompt> "query"
-    let mut cmd = Command::new("tgpt");
-    cmd.arg("--quiet")
-        .arg("--whole")
-        .arg("--provider")
-        .arg("sky")
-        .arg("--preprompt")
-        .arg(preprompt)
-        .arg(query)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
-
-    let child = cmd
-        .spawn()
-        .map_err(|e| format!("コマンド起動エラー: {}", e))?;
-
-    // Apply timeout to avoid hanging; wait_with_output will read stdout/stderr to completion.
-    match tokio::time::timeout(
-        std::time::Duration::from_secs(TIMEOUT_SECS),
-        child.wait_with_output(),
-    )
-    .await
-    {
-        Err(_) => Err(format!("タイムアウトしました ({}秒)", TIMEOUT_SECS)),
-        Ok(Err(e)) => Err(format!("コマンド実行エラー: {}", e)),
-        Ok(Ok(output)) => {
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                let msg = if stderr.trim().is_empty() {
-                    "tgpt 実行に失敗しました (詳細不明)".to_string()
-                } else {
-                    format!("tgpt 実行に失敗: {}", stderr.trim())
-                };
-                Err(msg)
-            } else {
-                Ok(output.stdout)
+pub const DESCRIPTION: &str = "LLM で回答を取得します";
+
+/// preprompt を system として渡す質問
+fn question(query: &str, preprompt: &str) -> [ChatMessage; 2] {
+    [ChatMessage::system(preprompt), ChatMessage::user(query)]
+}
+
+/// 答えを届いた分から受け取り、EDIT_INTERVAL ごとに途中までの表示を `show` に渡す
+async fn stream_chat<F: Future<Output = ()>>(
+    llm: &dyn LlmProvider,
+    messages: &[ChatMessage],
+    mut show: impl FnMut(String) -> F,
+) -> Result<String, String> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let answer = async {
+        llm.chat_stream(messages, tx)
+            .await
+            .map_err(|e| format!("{} {e}", llm.name()))
+    };
+    let display = async {
+        let mut text = String::new();
+        let mut shown: Option<Instant> = None;
+        while let Some(chunk) = rx.recv().await {
+            text.push_str(&chunk);
+            if !text.trim().is_empty() && shown.is_none_or(|at| at.elapsed() >= EDIT_INTERVAL) {
+                show(preview(&text)).await;
+                shown = Some(Instant::now());
             }
         }
+    };
+    let (answer, ()) = tokio::join!(answer, display);
+    answer
+}
+
+/// 受け取り途中の答え。長くなったら終わりのほうだけ見せる
+fn preview(text: &str) -> String {
+    let text = text.trim();
+    let start = text.len().saturating_sub(MAX_MESSAGE_SIZE);
+    match text.char_indices().map(|(i, _)| i).find(|&i| i >= start) {
+        Some(0) | None => format!("{text} ▌"),
+        Some(i) => format!("…{} ▌", &text[i..]),
     }
 }
 
@@ -79,25 +105,189 @@ fn to_message_or_file_bytes(bytes: Vec<u8>) -> Result<String, (Vec<u8>, String)>
     }
 }
 
-// Prefix: !gpt <質問>
-pub async fn run(ctx: &Context, msg: &Message) -> serenity::Result<()> {
-    let content = msg.content.trim();
-    let query = content
-        .strip_prefix(super::PREFIX)
-        .map(str::trim_start)
-        .and_then(|s| s.strip_prefix(NAME))
+/// 答えの本文と、長ければ添付するファイル
+fn answer_parts(bytes: Vec<u8>) -> (String, Option<CreateAttachment>) {
+    match to_message_or_file_bytes(bytes) {
+        // Respond as plain text (no code block)
+        Ok(text) => (text, None),
+        Err((bytes, _)) if bytes.len() > MAX_FILE_SIZE => (
+            "応答が長すぎるため送信できませんでした (10MB 超) ✋".to_string(),
+            None,
+        ),
+        Err((bytes, filename)) => (
+            "回答が長いためファイルで送信します".to_string(),
+            Some(CreateAttachment::bytes(bytes, filename)),
+        ),
+    }
+}
+
+/// 答えを表示するところ（/summarize でも使う）
+#[derive(Clone, Copy)]
+pub enum Reply<'a> {
+    /// プレフィックス・スレッドでは先に送ったメッセージを書き換える
+    Message(ChannelId, MessageId),
+    /// スラッシュでは Defer した応答を書き換える
+    Interaction(&'a CommandInteraction),
+}
+
+impl Reply<'_> {
+    pub async fn edit(
+        self,
+        ctx: &Context,
+        content: String,
+        file: Option<CreateAttachment>,
+        components: Vec<CreateActionRow>,
+    ) -> serenity::Result<Message> {
+        match self {
+            Self::Message(channel, message) => {
+                let mut edit = EditMessage::new().content(content).components(components);
+                if let Some(file) = file {
+                    edit = edit.new_attachment(file);
+                }
+                channel.edit_message(&ctx.http, message, edit).await
+            }
+            Self::Interaction(command) => {
+                let mut edit = EditInteractionResponse::new()
+                    .content(content)
+                    .components(components);
+                if let Some(file) = file {
+                    edit = edit.new_attachment(file);
+                }
+                command.edit_response(&ctx.http, edit).await
+            }
+        }
+    }
+
+    /// 答えを受け取りながら書き換え、最後に全体（長ければファイル）と `components` にする。
+    /// 答えられたら答えと書き換えたメッセージを返す
+    pub async fn stream(
+        self,
+        ctx: &Context,
+        messages: &[ChatMessage],
+        components: Vec<CreateActionRow>,
+    ) -> serenity::Result<Option<(String, Message)>> {
+        let answer = stream_chat(llm::client(), messages, |text| async move {
+            // 途中の表示に失敗しても受け取りは続ける
+            let _ = self.edit(ctx, text, None, Vec::new()).await;
+        })
+        .await;
+        match answer {
+            Ok(answer) => {
+                let (content, file) = answer_parts(answer.clone().into_bytes());
+                let sent = self.edit(ctx, content, file, components).await?;
+                Ok(Some((answer, sent)))
+            }
+            Err(e) => {
+                self.edit(ctx, format!("エラー: {e}"), None, Vec::new())
+                    .await?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// 質問の1行目をスレッド名にする
+fn thread_name(query: &str) -> String {
+    query
+        .lines()
         .map(str::trim)
-        .unwrap_or("");
+        .find(|l| !l.is_empty())
+        .unwrap_or(NAME)
+        .chars()
+        .take(MAX_THREAD_NAME)
+        .collect()
+}
+
+/// スレッドでの答えの下のボタン
+fn conversation_buttons() -> Vec<CreateActionRow> {
+    vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(super::custom_id(&Gpt, "fork"))
+            .label("分岐")
+            .emoji('🔀')
+            .style(ButtonStyle::Secondary),
+        CreateButton::new(super::custom_id(&Gpt, "end"))
+            .label("終了")
+            .emoji('⏹')
+            .style(ButtonStyle::Danger),
+    ])]
+}
 
-    if query.is_empty() {
-        msg.channel_id.say(&ctx.http, "使い方: !gpt <質問>").await?;
+/// 会話のスレッドに書かれた質問に答え、やり取りを履歴に足す
+async fn answer_in_thread(
+    ctx: &Context,
+    thread: ChannelId,
+    question: &str,
+) -> serenity::Result<()> {
+    // 前の質問に答え終わってから履歴を読む（答えが入れ違いにならないように）
+    let Some(turn) = conversation::turn(thread) else {
+        return Ok(());
+    };
+    let _turn = turn.lock().await;
+    let Some(messages) = conversation::prompt(thread, question, llm::config().context_tokens)
+    else {
         return Ok(());
+    };
+    let placeholder = thread.say(&ctx.http, THINKING).await?;
+    if let Some((answer, sent)) = Reply::Message(thread, placeholder.id)
+        .stream(ctx, &messages, conversation_buttons())
+        .await?
+    {
+        conversation::record(thread, question, answer.trim(), sent.id);
     }
+    Ok(())
+}
+
+/// 作ったスレッドで会話を始め、最初の質問に答える
+async fn start_conversation(
+    ctx: &Context,
+    thread: ChannelId,
+    owner: UserId,
+    system: &str,
+    query: &str,
+) -> serenity::Result<()> {
+    conversation::start(thread, owner, system, Vec::new());
+    answer_in_thread(ctx, thread, query).await
+}
+
+/// 会話中のスレッドか（main の message から呼ぶ）
+pub fn is_conversation(channel: ChannelId) -> bool {
+    conversation::is_active(channel)
+}
+
+/// 会話中のスレッドに書かれたメッセージを続きの質問として答える
+pub async fn continue_conversation(ctx: &Context, msg: &Message) -> serenity::Result<()> {
+    let question = msg.content.trim();
+    if question.is_empty() {
+        return Ok(());
+    }
+    answer_in_thread(ctx, msg.channel_id, question).await
+}
+
+struct GptArgs {
+    query: String,
+    thread: bool,
+}
+
+impl FromArgs for GptArgs {
+    fn from_args(args: &Args) -> Result<Self, String> {
+        Ok(Self {
+            query: args.required_str("query")?,
+            thread: args.bool("thread").unwrap_or(false),
+        })
+    }
+}
+
+// Prefix: !gpt [--thread] <質問>
+pub async fn run(ctx: &Context, msg: &Message) -> serenity::Result<()> {
+    let Ok(GptArgs { query, thread }) = args::from_message::<GptArgs>(&Gpt, msg) else {
+        msg.channel_id
+            .say(&ctx.http, "使い方: !gpt [--thread] <質問>")
+            .await?;
+        return Ok(());
+    };
 
     // Build preprompt, appending replied message content if present
-    let mut preprompt = String::from(
-        "あなたの名前は'rust-bot'。ソフトウエア研究サークルのDiscordボット。*respond in brief*.",
-    );
+    let mut preprompt = prompts::resolve(ctx, msg.guild_id, msg.channel_id).await;
     if let Some(referenced) = &msg.referenced_message {
         let replied = referenced.content.trim();
         if !replied.is_empty() {
@@ -106,141 +296,265 @@ pub async fn run(ctx: &Context, msg: &Message) -> serenity::Result<()> {
         }
     }
 
-    match run_tgpt(query, &preprompt).await {
-        Ok(bytes) => match to_message_or_file_bytes(bytes) {
-            Ok(text) => {
-                // Respond as plain text (no code block)
-                msg.channel_id.say(&ctx.http, text).await?;
-                Ok(())
+    if thread {
+        // 質問のメッセージからスレッドを作り、そこで答える
+        return match msg
+            .channel_id
+            .create_thread_from_message(&ctx.http, msg.id, CreateThread::new(thread_name(&query)))
+            .await
+        {
+            Ok(thread) => {
+                start_conversation(ctx, thread.id, msg.author.id, &preprompt, &query).await
             }
-            Err((bytes, filename)) => {
-                if bytes.len() > MAX_FILE_SIZE {
-                    msg.channel_id
-                        .say(
-                            &ctx.http,
-                            "応答が長すぎるため送信できませんでした (10MB 超) ✋",
-                        )
-                        .await?;
-                    return Ok(());
-                }
-                let attachment = serenity::builder::CreateAttachment::bytes(bytes, filename);
+            Err(e) => {
                 msg.channel_id
-                    .send_message(
-                        &ctx.http,
-                        serenity::builder::CreateMessage::new()
-                            .content("回答が長いためファイルで送信します")
-                            .add_file(attachment),
-                    )
+                    .say(&ctx.http, format!("スレッドを作れませんでした: {e}"))
                     .await?;
                 Ok(())
             }
-        },
-        Err(e) => {
-            msg.channel_id
-                .say(
-                    &ctx.http,
-                    format!(
-                        "エラー: {}\n' tgpt ' がインストールされているか確認してください。",
-                        e
-                    ),
-                )
-                .await?;
-            Ok(())
-        }
+        };
     }
-}
 
-// Slash: /gpt query:<質問>
-pub async fn slash_execute(
-    ctx: &Context,
-    command: &serenity::model::application::CommandInteraction,
-) -> serenity::Result<()> {
-    let mut query: Option<String> = None;
-    for opt in &command.data.options {
-        if let ("query", CommandDataOptionValue::String(s)) = (opt.name.as_str(), &opt.value) {
-            query = Some(s.clone());
-        }
-    }
+    let placeholder = msg.channel_id.say(&ctx.http, THINKING).await?;
+    Reply::Message(msg.channel_id, placeholder.id)
+        .stream(ctx, &question(&query, &preprompt), Vec::new())
+        .await?;
+    Ok(())
+}
 
-    let Some(query) = query.filter(|q| !q.trim().is_empty()) else {
-        command
-            .create_response(
-                &ctx.http,
-                serenity::builder::CreateInteractionResponse::Message(
-                    serenity::builder::CreateInteractionResponseMessage::new()
-                        .content("query が必要です"),
-                ),
-            )
-            .await?;
-        return Ok(());
+// Slash: /gpt query:<質問> thread:<スレッドで続ける?>
+pub async fn slash_execute(ctx: &Context, command: &CommandInteraction) -> serenity::Result<()> {
+    let GptArgs { query, thread } = match args::from_interaction::<GptArgs>(command) {
+        Ok(a) => a,
+        Err(e) => return super::respond(ctx, command, e).await,
     };
 
-    // Defer immediately (tgpt may take time)
+    // For slash commands, there is no replied message context; use the configured prompt only
+    let preprompt = prompts::resolve(ctx, command.guild_id, command.channel_id).await;
+
+    // Defer immediately (the LLM may take time)
     command
         .create_response(
             &ctx.http,
-            serenity::builder::CreateInteractionResponse::Defer(
-                serenity::builder::CreateInteractionResponseMessage::new(),
-            ),
+            CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new()),
         )
         .await?;
 
-    // For slash commands, there is no replied message context; use base preprompt
-    match run_tgpt(&query, "respond in brief").await {
-        Ok(bytes) => match to_message_or_file_bytes(bytes) {
-            Ok(text) => {
-                command
-                    .edit_response(
-                        &ctx.http,
-                        serenity::builder::EditInteractionResponse::new().content(text),
-                    )
-                    .await?;
-                Ok(())
+    if thread {
+        // 応答のメッセージからスレッドを作り、そこで答える
+        let response = command
+            .edit_response(
+                &ctx.http,
+                EditInteractionResponse::new().content(format!("💬 {query}")),
+            )
+            .await?;
+        return match command
+            .channel_id
+            .create_thread_from_message(
+                &ctx.http,
+                response.id,
+                CreateThread::new(thread_name(&query)),
+            )
+            .await
+        {
+            Ok(thread) => {
+                start_conversation(ctx, thread.id, command.user.id, &preprompt, &query).await
             }
-            Err((bytes, filename)) => {
-                if bytes.len() > MAX_FILE_SIZE {
-                    command
-                        .edit_response(
-                            &ctx.http,
-                            serenity::builder::EditInteractionResponse::new()
-                                .content("応答が長すぎるため送信できませんでした (10MB 超) ✋"),
-                        )
-                        .await?;
-                    return Ok(());
-                }
-                let files = EditAttachments::new()
-                    .add(serenity::builder::CreateAttachment::bytes(bytes, filename));
+            Err(e) => {
                 command
                     .edit_response(
                         &ctx.http,
-                        serenity::builder::EditInteractionResponse::new()
-                            .content("回答が長いためファイルで送信します")
-                            .attachments(files),
+                        EditInteractionResponse::new()
+                            .content(format!("スレッドを作れませんでした: {e}")),
                     )
                     .await?;
                 Ok(())
             }
-        },
-        Err(e) => {
-            command
-                .edit_response(
+        };
+    }
+
+    Reply::Interaction(command)
+        .stream(ctx, &question(&query, &preprompt), Vec::new())
+        .await?;
+    Ok(())
+}
+
+/// スレッドでの答えの下のボタン
+pub async fn component(ctx: &Context, component: &ComponentInteraction) -> serenity::Result<()> {
+    let (_, action) = super::split_custom_id(&component.data.custom_id);
+    let thread = component.channel_id;
+    let reply = |content: String, ephemeral: bool| {
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(content)
+                .ephemeral(ephemeral),
+        )
+    };
+    let Some(owner) = conversation::owner(thread) else {
+        return component
+            .create_response(
+                &ctx.http,
+                reply(
+                    "この会話は終了しています。もう一度 /gpt thread:True で始めてください。"
+                        .to_string(),
+                    true,
+                ),
+            )
+            .await;
+    };
+
+    match action {
+        "end" if component.user.id == owner => {
+            conversation::end(thread);
+            component
+                .create_response(&ctx.http, reply("⏹ 会話を終了しました".to_string(), false))
+                .await?;
+            // 終わった会話のスレッドはしまう
+            thread
+                .edit_thread(&ctx.http, EditThread::new().archived(true))
+                .await?;
+        }
+        "end" => {
+            component
+                .create_response(
                     &ctx.http,
-                    serenity::builder::EditInteractionResponse::new().content(format!(
-                        "エラー: {}\n' tgpt ' がインストールされているか確認してください。",
-                        e
-                    )),
+                    reply("会話を始めた人だけが終了できます".to_string(), true),
                 )
                 .await?;
-            Ok(())
         }
+        "fork" => {
+            // 元のスレッドと同じチャンネルに新しいスレッドを作る
+            let channel = thread.to_channel(&ctx).await?.guild();
+            let Some((name, parent)) = channel.and_then(|c| Some((c.name, c.parent_id?))) else {
+                return component
+                    .create_response(&ctx.http, reply("ここでは分岐できません".to_string(), true))
+                    .await;
+            };
+            let forked = parent
+                .create_thread(
+                    &ctx.http,
+                    CreateThread::new(thread_name(&format!("{name} (分岐)")))
+                        .kind(ChannelType::PublicThread),
+                )
+                .await?;
+            conversation::fork(thread, component.message.id, forked.id, component.user.id);
+            let intro = forked
+                .id
+                .send_message(
+                    &ctx.http,
+                    CreateMessage::new()
+                        .content(format!(
+                            "🔀 {} から分かれた会話です。続きをここに書いてください。",
+                            component.message.link()
+                        ))
+                        .components(conversation_buttons()),
+                )
+                .await?;
+            conversation::mark(forked.id, intro.id);
+            component
+                .create_response(
+                    &ctx.http,
+                    reply(format!("🔀 <#{}> に分岐しました", forked.id), false),
+                )
+                .await?;
+        }
+        _ => {}
     }
+    Ok(())
 }
 
-pub fn slash_register() -> CreateCommand {
-    CreateCommand::new(NAME)
-        .description(DESCRIPTION)
-        .add_option(
+pub struct Gpt;
+
+#[async_trait]
+impl super::Command for Gpt {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn description(&self) -> &'static str {
+        DESCRIPTION
+    }
+
+    fn options(&self) -> Vec<CreateCommandOption> {
+        vec![
             CreateCommandOption::new(CommandOptionType::String, "query", "質問/プロンプト")
                 .required(true),
-        )
+            CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "thread",
+                "スレッドを作って会話を続ける",
+            ),
+        ]
+    }
+
+    async fn run(&self, ctx: &Context, msg: &Message) -> serenity::Result<()> {
+        run(ctx, msg).await
+    }
+
+    async fn slash_execute(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> serenity::Result<()> {
+        slash_execute(ctx, command).await
+    }
+
+    async fn component(
+        &self,
+        ctx: &Context,
+        interaction: &ComponentInteraction,
+    ) -> serenity::Result<()> {
+        component(ctx, interaction).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{Role, mock::Mock};
+
+    async fn ask(llm: &dyn LlmProvider, query: &str, preprompt: &str) -> Result<Vec<u8>, String> {
+        stream_chat(llm, &question(query, preprompt), |_| async {})
+            .await
+            .map(String::into_bytes)
+    }
+
+    #[tokio::test]
+    async fn asks_with_preprompt() {
+        let llm = Mock::echo();
+        let bytes = ask(&llm, "hello", "respond in brief").await.unwrap();
+        assert_eq!(
+            to_message_or_file_bytes(bytes),
+            Ok("mock: hello".to_string())
+        );
+        let sent = &llm.calls()[0];
+        assert_eq!(sent[0].role, Role::System);
+        assert_eq!(sent[0].content, "respond in brief");
+
+        // 長い回答はファイルにする
+        let llm = Mock::with(|_| "a".repeat(MAX_MESSAGE_SIZE + 1));
+        let bytes = ask(&llm, "q", "").await.unwrap();
+        assert_eq!(to_message_or_file_bytes(bytes).unwrap_err().1, "gpt.txt");
+    }
+
+    #[tokio::test]
+    async fn streams_with_throttled_previews() {
+        let llm = Mock::with(|_| "one two three".to_string());
+        let mut shown = Vec::new();
+        let answer = stream_chat(&llm, &question("q", ""), |text| {
+            shown.push(text);
+            async {}
+        })
+        .await
+        .unwrap();
+        assert_eq!(answer, "one two three");
+        // すぐ届いた分は次の間隔まで書き換えない
+        assert_eq!(shown, ["one ▌"]);
+
+        // 長い途中経過は終わりのほうだけ
+        let long = format!("{}end", "あ".repeat(MAX_MESSAGE_SIZE));
+        let p = preview(&long);
+        assert!(p.starts_with('…') && p.ends_with("end ▌"));
+        assert!(p.len() <= MAX_MESSAGE_SIZE + "… ▌".len());
+    }
 }
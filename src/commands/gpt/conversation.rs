@@ -0,0 +1,230 @@
+// /gpt のスレッドでの会話（スレッドごと）
+//
+// スレッドに書かれたメッセージを質問として、それまでのやり取りと一緒に LLM に渡す。
+// 渡す履歴は LLM_CONTEXT_TOKENS に収まるよう古いものから捨てる（覚えておく分は捨てない）。
+// 返信ごとにその時点の履歴の長さを覚えておき、分岐ではそこまでを新しいスレッドに写す。
+// 続けて書かれた質問は順番に答える（前の答えを履歴に足してから次の質問を送る）。
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use serenity::model::id::{ChannelId, MessageId, UserId};
+
+use crate::llm::{ChatMessage, Role, estimate_tokens};
+
+// これより長く止まっている会話は忘れる
+const TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const MAX_CONVERSATIONS: usize = 500;
+// 覚えておくメッセージの最大数（これより古いものは捨てる）
+const MAX_MESSAGES: usize = 200;
+
+#[derive(Debug, Clone)]
+struct Conversation {
+    owner: UserId,
+    system: String,
+    messages: Vec<ChatMessage>,
+    // ボタンを付けた返信 → その時点の messages の長さ
+    marks: HashMap<MessageId, usize>,
+    at: Instant,
+    // 答えている間は持っておく（同じスレッドの質問を1つずつにする）
+    turn: Arc<tokio::sync::Mutex<()>>,
+}
+
+static CONVERSATIONS: Lazy<Mutex<HashMap<ChannelId, Conversation>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// スレッドで会話を始める（`messages` はそれまでのやり取り）
+pub fn start(thread: ChannelId, owner: UserId, system: &str, messages: Vec<ChatMessage>) {
+    let mut conversations = CONVERSATIONS.lock().unwrap();
+    conversations.retain(|_, c| c.at.elapsed() < TTL);
+    if conversations.len() >= MAX_CONVERSATIONS
+        && let Some(oldest) = conversations
+            .iter()
+            .min_by_key(|(_, c)| c.at)
+            .map(|(id, _)| *id)
+    {
+        conversations.remove(&oldest);
+    }
+    conversations.insert(
+        thread,
+        Conversation {
+            owner,
+            system: system.to_string(),
+            messages,
+            marks: HashMap::new(),
+            at: Instant::now(),
+            turn: Arc::default(),
+        },
+    );
+}
+
+/// 会話中のスレッドか
+pub fn is_active(thread: ChannelId) -> bool {
+    CONVERSATIONS
+        .lock()
+        .unwrap()
+        .get(&thread)
+        .is_some_and(|c| c.at.elapsed() < TTL)
+}
+
+/// スレッドで答える順番。答え終わるまで lock したままにする。会話中でなければ None
+pub fn turn(thread: ChannelId) -> Option<Arc<tokio::sync::Mutex<()>>> {
+    CONVERSATIONS
+        .lock()
+        .unwrap()
+        .get(&thread)
+        .map(|c| c.turn.clone())
+}
+
+/// 質問を足して LLM に送る会話（system + 予算に収まる履歴 + 質問）。会話中でなければ None
+pub fn prompt(thread: ChannelId, question: &str, budget: usize) -> Option<Vec<ChatMessage>> {
+    let conversations = CONVERSATIONS.lock().unwrap();
+    let c = conversations.get(&thread)?;
+    let mut messages = c.messages.clone();
+    messages.push(ChatMessage::user(question));
+    Some(trim(&c.system, &messages, budget))
+}
+
+/// 質問と答えを履歴に足し、答えを送ったメッセージを分岐点として覚える
+pub fn record(thread: ChannelId, question: &str, answer: &str, reply: MessageId) {
+    let mut conversations = CONVERSATIONS.lock().unwrap();
+    let Some(c) = conversations.get_mut(&thread) else {
+        return;
+    };
+    c.messages.push(ChatMessage::user(question));
+    c.messages.push(ChatMessage::assistant(answer));
+    if c.messages.len() > MAX_MESSAGES {
+        let excess = c.messages.len() - MAX_MESSAGES;
+        c.messages.drain(..excess);
+        c.marks.retain(|_, len| *len > excess);
+        c.marks.values_mut().for_each(|len| *len -= excess);
+    }
+    c.marks.insert(reply, c.messages.len());
+    c.at = Instant::now();
+}
+
+/// 今の履歴の長さを分岐点として覚える（最初の案内メッセージ用）
+pub fn mark(thread: ChannelId, message: MessageId) {
+    if let Some(c) = CONVERSATIONS.lock().unwrap().get_mut(&thread) {
+        c.marks.insert(message, c.messages.len());
+    }
+}
+
+/// 会話を始めた人
+pub fn owner(thread: ChannelId) -> Option<UserId> {
+    CONVERSATIONS.lock().unwrap().get(&thread).map(|c| c.owner)
+}
+
+/// 会話を終える。会話中だったら true
+pub fn end(thread: ChannelId) -> bool {
+    CONVERSATIONS.lock().unwrap().remove(&thread).is_some()
+}
+
+/// `message` の時点までの会話を `to` に写す。`message` を知らなければ今の会話全部
+pub fn fork(from: ChannelId, message: MessageId, to: ChannelId, owner: UserId) -> bool {
+    let Some(c) = CONVERSATIONS.lock().unwrap().get(&from).cloned() else {
+        return false;
+    };
+    let len = c.marks.get(&message).copied().unwrap_or(c.messages.len());
+    start(to, owner, &c.system, c.messages[..len].to_vec());
+    true
+}
+
+/// system と、`budget` に収まるだけの新しい履歴。最後の質問は収まらなくても残す。
+/// 履歴は質問から始まるようにする
+pub fn trim(system: &str, messages: &[ChatMessage], budget: usize) -> Vec<ChatMessage> {
+    let mut used =
+        estimate_tokens(system) + messages.last().map_or(0, |m| estimate_tokens(&m.content));
+    let mut start = messages.len().saturating_sub(1);
+    while start > 0 {
+        let cost = estimate_tokens(&messages[start - 1].content);
+        if used + cost > budget {
+            break;
+        }
+        used += cost;
+        start -= 1;
+    }
+    while start + 1 < messages.len() && messages[start].role != Role::User {
+        start += 1;
+    }
+    std::iter::once(ChatMessage::system(system))
+        .chain(messages[start..].iter().cloned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trims_oldest_first() {
+        let messages = [
+            ChatMessage::user("a".repeat(400)),
+            ChatMessage::assistant("b".repeat(400)),
+            ChatMessage::user("c".repeat(40)),
+            ChatMessage::assistant("d".repeat(40)),
+            ChatMessage::user("最後の質問"),
+        ];
+        let contents = |budget| -> Vec<String> {
+            trim("sys", &messages, budget)
+                .into_iter()
+                .map(|m| m.content)
+                .collect()
+        };
+        // 全部収まる
+        assert_eq!(contents(1000).len(), 6);
+        // 古い 400 文字のやり取りを捨てる
+        assert_eq!(
+            contents(100),
+            [
+                "sys",
+                "c".repeat(40).as_str(),
+                "d".repeat(40).as_str(),
+                "最後の質問"
+            ]
+        );
+        // 答えから始まらないようにする
+        assert_eq!(contents(30), ["sys", "最後の質問"]);
+        // 予算が足りなくても最後の質問は残す
+        assert_eq!(contents(0), ["sys", "最後の質問"]);
+    }
+
+    #[test]
+    fn continues_ends_and_forks() {
+        let (thread, forked) = (ChannelId::new(1), ChannelId::new(2));
+        let user = UserId::new(10);
+        start(thread, user, "sys", vec![]);
+        assert!(is_active(thread));
+
+        // 答えている間は次の質問を待たせる
+        let first = turn(thread).unwrap();
+        let answering = first.try_lock().unwrap();
+        assert!(turn(thread).unwrap().try_lock().is_err());
+        drop(answering);
+        assert!(turn(thread).unwrap().try_lock().is_ok());
+
+        let sent = prompt(thread, "1+1?", 1000).unwrap();
+        assert_eq!(sent.len(), 2);
+        record(thread, "1+1?", "2", MessageId::new(100));
+        record(thread, "times 3?", "6", MessageId::new(101));
+        let sent = prompt(thread, "minus 1?", 1000).unwrap();
+        let contents: Vec<&str> = sent.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, ["sys", "1+1?", "2", "times 3?", "6", "minus 1?"]);
+
+        // 最初の答えのところから分岐する
+        assert!(fork(thread, MessageId::new(100), forked, UserId::new(11)));
+        assert_eq!(owner(forked), Some(UserId::new(11)));
+        assert_eq!(prompt(forked, "x", 1000).unwrap().len(), 4);
+        assert!(!Arc::ptr_eq(&turn(forked).unwrap(), &first));
+
+        assert!(end(thread));
+        assert!(!is_active(thread));
+        assert!(prompt(thread, "x", 1000).is_none());
+        assert!(!fork(thread, MessageId::new(100), forked, user));
+        assert!(end(forked));
+    }
+}
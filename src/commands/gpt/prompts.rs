@@ -0,0 +1,206 @@
+// /gpt の system プロンプト（サーバーごと・チャンネルごと）
+//
+// チャンネルの設定 → (スレッドなら) 親チャンネルの設定 → サーバーの設定 → 既定 (DEFAULT_PROMPT) の順に使う。
+// 設定は /gpt-config で変え、ファイルに保存して再起動後も使う。
+//
+// 設定は環境変数で行う
+// - GPT_PROMPTS_PATH: 保存するファイル (既定: data/gpt_prompts.json。空にするとファイルに保存しない)
+
+use std::{collections::BTreeMap, path::PathBuf, sync::Mutex};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    model::{
+        channel::ChannelType,
+        id::{ChannelId, GuildId},
+    },
+    prelude::Context,
+};
+
+const DEFAULT_PATH: &str = "data/gpt_prompts.json";
+
+pub const DEFAULT_PROMPT: &str =
+    "あなたの名前は'rust-bot'。ソフトウエア研究サークルのDiscordボット。*respond in brief*.";
+
+/// 設定する範囲
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Guild(GuildId),
+    Channel(ChannelId),
+}
+
+/// 保存ファイルの中身（ID → プロンプト）
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Saved {
+    #[serde(default)]
+    guilds: BTreeMap<u64, String>,
+    #[serde(default)]
+    channels: BTreeMap<u64, String>,
+}
+
+impl Saved {
+    fn map(&mut self, scope: Scope) -> (&mut BTreeMap<u64, String>, u64) {
+        match scope {
+            Scope::Guild(id) => (&mut self.guilds, id.get()),
+            Scope::Channel(id) => (&mut self.channels, id.get()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Prompts {
+    path: Option<PathBuf>,
+    saved: Mutex<Saved>,
+}
+
+impl Prompts {
+    /// `path` があれば保存済みの設定を読み込む
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let saved = path
+            .as_ref()
+            .and_then(|p| std::fs::read(p).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            saved: Mutex::new(saved),
+        }
+    }
+
+    fn from_env() -> Self {
+        Self::new(match std::env::var("GPT_PROMPTS_PATH") {
+            Ok(p) if p.trim().is_empty() => None,
+            Ok(p) => Some(PathBuf::from(p.trim())),
+            Err(_) => Some(PathBuf::from(DEFAULT_PATH)),
+        })
+    }
+
+    pub fn get(&self, scope: Scope) -> Option<String> {
+        let mut saved = self.saved.lock().unwrap();
+        let (map, id) = saved.map(scope);
+        map.get(&id).cloned()
+    }
+
+    pub fn set(&self, scope: Scope, prompt: &str) {
+        let mut saved = self.saved.lock().unwrap();
+        let (map, id) = saved.map(scope);
+        map.insert(id, prompt.to_string());
+        self.save(&saved);
+    }
+
+    /// 設定を消す。設定があったら true
+    pub fn reset(&self, scope: Scope) -> bool {
+        let mut saved = self.saved.lock().unwrap();
+        let (map, id) = saved.map(scope);
+        let removed = map.remove(&id).is_some();
+        if removed {
+            self.save(&saved);
+        }
+        removed
+    }
+
+    /// チャンネルの設定。スレッドの中なら `parent` (親チャンネル) の設定も見る
+    pub fn channel(&self, channel: ChannelId, parent: Option<ChannelId>) -> Option<String> {
+        self.get(Scope::Channel(channel))
+            .or_else(|| parent.and_then(|p| self.get(Scope::Channel(p))))
+    }
+
+    /// このチャンネルで使うプロンプト
+    pub fn resolve(
+        &self,
+        guild: Option<GuildId>,
+        channel: ChannelId,
+        parent: Option<ChannelId>,
+    ) -> String {
+        self.channel(channel, parent)
+            .or_else(|| guild.and_then(|g| self.get(Scope::Guild(g))))
+            .unwrap_or_else(|| DEFAULT_PROMPT.to_string())
+    }
+
+    fn save(&self, saved: &Saved) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let result = serde_json::to_vec_pretty(saved)
+            .map_err(std::io::Error::from)
+            .and_then(|bytes| std::fs::write(path, bytes));
+        if let Err(e) = result {
+            println!("gpt のプロンプト設定の保存に失敗: {e}");
+        }
+    }
+}
+
+static PROMPTS: Lazy<Prompts> = Lazy::new(Prompts::from_env);
+
+/// 環境変数の設定で読み込んだ共有の設定
+pub fn store() -> &'static Prompts {
+    &PROMPTS
+}
+
+/// スレッドなら親チャンネル
+pub async fn thread_parent(ctx: &Context, channel: ChannelId) -> Option<ChannelId> {
+    let channel = channel.to_channel(ctx).await.ok()?.guild()?;
+    match channel.kind {
+        ChannelType::PublicThread | ChannelType::PrivateThread | ChannelType::NewsThread => {
+            channel.parent_id
+        }
+        _ => None,
+    }
+}
+
+/// このチャンネル（スレッドなら親チャンネルも見て）で使う共有の設定のプロンプト
+pub async fn resolve(ctx: &Context, guild: Option<GuildId>, channel: ChannelId) -> String {
+    let parent = thread_parent(ctx, channel).await;
+    store().resolve(guild, channel, parent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_and_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data/prompts.json");
+        let (guild, channel, other) = (GuildId::new(1), ChannelId::new(2), ChannelId::new(3));
+        let thread = ChannelId::new(4);
+
+        let prompts = Prompts::new(Some(path.clone()));
+        assert_eq!(prompts.resolve(Some(guild), channel, None), DEFAULT_PROMPT);
+        prompts.set(Scope::Guild(guild), "サーバーの設定");
+        prompts.set(Scope::Channel(channel), "チャンネルの設定");
+        assert_eq!(
+            prompts.resolve(Some(guild), channel, None),
+            "チャンネルの設定"
+        );
+        assert_eq!(prompts.resolve(Some(guild), other, None), "サーバーの設定");
+        assert_eq!(prompts.resolve(None, other, None), DEFAULT_PROMPT);
+        // スレッドは親チャンネルの設定をサーバーの設定より先に使う
+        assert_eq!(
+            prompts.resolve(Some(guild), thread, Some(channel)),
+            "チャンネルの設定"
+        );
+        prompts.set(Scope::Channel(thread), "スレッドの設定");
+        assert_eq!(
+            prompts.resolve(Some(guild), thread, Some(channel)),
+            "スレッドの設定"
+        );
+
+        // 再起動後も読み込める
+        let prompts = Prompts::new(Some(path));
+        assert_eq!(
+            prompts.resolve(Some(guild), channel, None),
+            "チャンネルの設定"
+        );
+        assert!(prompts.reset(Scope::Channel(channel)));
+        assert!(!prompts.reset(Scope::Channel(channel)));
+        assert_eq!(
+            prompts.resolve(Some(guild), channel, None),
+            "サーバーの設定"
+        );
+    }
+}
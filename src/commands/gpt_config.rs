@@ -0,0 +1,197 @@
+// /gpt-config: /gpt の system プロンプトをサーバー・チャンネルごとに設定する（管理者用）
+//
+// - /gpt-config show: このチャンネルで使われるプロンプトと、それぞれの設定を表示
+// - /gpt-config set scope:<server|channel> prompt:<プロンプト>: 設定する
+// - /gpt-config reset scope:<server|channel>: 設定を消す（上の範囲か既定に戻る）
+
+use serenity::{
+    async_trait,
+    builder::{
+        CreateCommand, CreateCommandOption, CreateInteractionResponse,
+        CreateInteractionResponseMessage,
+    },
+    model::{
+        Permissions,
+        application::{CommandInteraction, CommandOptionType},
+    },
+    prelude::Context,
+};
+
+use super::args::Args;
+use super::gpt::prompts::{self, DEFAULT_PROMPT, Scope};
+
+pub const NAME: &str = "gpt-config";
+pub const DESCRIPTION: &str = "/gpt の system プロンプトを設定します (管理者用)";
+
+// プロンプトの最大文字数
+const MAX_PROMPT: u16 = 2000;
+// 表示するときの最大文字数（引用の "> " も含む。3つ並べても 2000 文字に収まるように）
+const MAX_QUOTE: usize = 500;
+
+fn usage() -> &'static str {
+    "使い方: /gpt-config show | set scope:<server|channel> prompt:<プロンプト> | reset scope:<server|channel>"
+}
+
+fn scope_option() -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::String, "scope", "設定する範囲")
+        .required(true)
+        .add_string_choice("サーバー", "server")
+        .add_string_choice("チャンネル", "channel")
+}
+
+/// 表示用に引用にする（引用にしてから長ければ省略する。改行が多くても MAX_QUOTE に収まる）
+fn quote(prompt: Option<&str>) -> String {
+    let Some(prompt) = prompt else {
+        return "(未設定)\n".to_string();
+    };
+    let quoted: String = prompt.lines().map(|l| format!("> {l}\n")).collect();
+    if quoted.chars().count() <= MAX_QUOTE {
+        return quoted;
+    }
+    let mut clipped: String = quoted.chars().take(MAX_QUOTE - 2).collect();
+    clipped.push_str("…\n");
+    clipped
+}
+
+/// スレッドの中ならそのスレッドか親チャンネルの設定を見せる
+async fn show(ctx: &Context, command: &CommandInteraction) -> String {
+    let store = prompts::store();
+    let parent = prompts::thread_parent(ctx, command.channel_id).await;
+    let channel = store.channel(command.channel_id, parent);
+    let guild = command.guild_id.and_then(|g| store.get(Scope::Guild(g)));
+    let used = if channel.is_some() {
+        "チャンネル"
+    } else if guild.is_some() {
+        "サーバー"
+    } else {
+        "既定"
+    };
+    format!(
+        "このチャンネルでは **{used}** の設定を使います\n**チャンネル**\n{}**サーバー**\n{}**既定**\n{}",
+        quote(channel.as_deref()),
+        quote(guild.as_deref()),
+        quote(Some(DEFAULT_PROMPT)),
+    )
+}
+
+async fn execute(ctx: &Context, command: &CommandInteraction) -> String {
+    let args = Args::from_interaction(command);
+    let Some(guild) = command.guild_id else {
+        return "サーバーの中で使ってください".to_string();
+    };
+    let scope = match args.str("scope") {
+        Some("server") => Some((Scope::Guild(guild), "サーバー")),
+        Some("channel") => Some((Scope::Channel(command.channel_id), "このチャンネル")),
+        _ => None,
+    };
+    // 既定の権限で隠しているが、念のためここでも確かめる
+    let is_admin = command
+        .member
+        .as_ref()
+        .and_then(|m| m.permissions)
+        .is_some_and(|p| p.manage_guild());
+
+    match (args.subcommand(), scope) {
+        (Some("show"), _) => show(ctx, command).await,
+        (Some("set" | "reset"), _) if !is_admin => {
+            "サーバーの管理権限がある人だけが変更できます".to_string()
+        }
+        (Some("set"), Some((scope, label))) => match args.str("prompt").map(str::trim) {
+            Some(prompt) if !prompt.is_empty() => {
+                prompts::store().set(scope, prompt);
+                format!(
+                    "{label}の /gpt のプロンプトを設定しました\n{}",
+                    quote(Some(prompt))
+                )
+            }
+            _ => "prompt が必要です".to_string(),
+        },
+        (Some("reset"), Some((scope, label))) => {
+            if prompts::store().reset(scope) {
+                format!("{label}の /gpt のプロンプトを元に戻しました")
+            } else {
+                format!("{label}の /gpt のプロンプトは設定されていません")
+            }
+        }
+        _ => usage().to_string(),
+    }
+}
+
+pub struct GptConfig;
+
+#[async_trait]
+impl super::Command for GptConfig {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn description(&self) -> &'static str {
+        DESCRIPTION
+    }
+
+    fn options(&self) -> Vec<CreateCommandOption> {
+        vec![
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "show",
+                "このチャンネルで使われるプロンプトを表示",
+            ),
+            CreateCommandOption::new(CommandOptionType::SubCommand, "set", "プロンプトを設定")
+                .add_sub_option(scope_option())
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "prompt", "プロンプト")
+                        .required(true)
+                        .max_length(MAX_PROMPT),
+                ),
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "reset",
+                "プロンプトを既定に戻す",
+            )
+            .add_sub_option(scope_option()),
+        ]
+    }
+
+    /// サーバーの管理権限がある人にだけ見せる
+    fn register(&self) -> CreateCommand {
+        CreateCommand::new(self.name())
+            .description(self.description())
+            .set_options(self.options())
+            .default_member_permissions(Permissions::MANAGE_GUILD)
+    }
+
+    async fn slash_execute(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> serenity::Result<()> {
+        // 設定の内容は本人にだけ見せる
+        command
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content(execute(ctx, command).await)
+                        .ephemeral(true),
+                ),
+            )
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_within_limit() {
+        assert_eq!(quote(None), "(未設定)\n");
+        assert_eq!(quote(Some("a\nb")), "> a\n> b\n");
+        // 改行ばかりでも引用にした後の長さで切る
+        for prompt in ["\n".repeat(2000), "あ".repeat(2000)] {
+            let quoted = quote(Some(&prompt));
+            assert_eq!(quoted.chars().count(), MAX_QUOTE);
+            assert!(quoted.ends_with("…\n"));
+        }
+    }
+}
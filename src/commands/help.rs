@@ -1,6 +1,9 @@
 use serenity::{
-    builder::CreateCommand,
-    model::{application::ResolvedOption, channel::Message},
+    async_trait,
+    model::{
+        application::{CommandInteraction, ResolvedOption},
+        channel::Message,
+    },
     prelude::Context,
 };
 
@@ -9,7 +12,12 @@ pub async fn run(ctx: &Context, msg: &Message) -> serenity::Result<()> {
 - !ping: ポン！と返します\n\
 - !help: このヘルプを表示します\n\
 - !tex <式>: LaTeX を画像で返します\n\
-- !gpt <質問>: tgpt で回答を取得します\n\
+- !gpt [--thread] <質問>: LLM で回答を取得します (--thread でスレッドを作り、そこで会話を続けます)\n\
+- !summarize [件数] [--since <30m|2h|1d>]: チャンネルの最近のやり取りを LLM で要約します\n\
+- !eval <言語> [stdin:<入力>] [args:<引数>] ```<コード>```: コードを実行します (.txt 添付で標準入力、ソースファイル添付で複数ファイル、Rust は // deps: rand = \"0.8\" でクレートを使用)\n\
+- !rrepl [--color] ```<Rustコード>```: 簡易 REPL (前回までの入力を引き継ぎます。:show / :undo / :reset)\n\
+- !rust <fmt|clippy|expand|asm> ```<Rustコード>```: rustfmt / clippy / マクロ展開 / アセンブリ\n\
+- !huki [--x2] <テキスト>: 突然の死ジェネレーター (返信先の本文も可)\n\
 - !get <url> [--headers {JSON}]: 指定URLへ GET\n\
 - !post <url> <JSON> [--headers {JSON}]: 指定URLへ POST";
 
@@ -19,13 +27,34 @@ pub async fn run(ctx: &Context, msg: &Message) -> serenity::Result<()> {
 
 pub fn slash_run(_options: &[ResolvedOption]) -> String {
     // スラッシュ版も同じ内容を返す
-    "利用可能なコマンド:\n- /ping: ポン！と返します\n- /help: このヘルプを表示します\n- /tex formula:<式>: LaTeX を画像で返します\n- /gpt query:<質問>: tgpt で回答を取得します\n- /get url:<url> headers:<JSON?>: HTTP GET\n- /post url:<url> payload:<JSON> headers:<JSON?>: HTTP POST".to_string()
+    "利用可能なコマンド:\n- /ping: ポン！と返します\n- /help: このヘルプを表示します\n- /tex formula:<式>: LaTeX を画像で返します\n- /gpt query:<質問> thread:<スレッドで続ける?>: LLM で回答を取得します (thread でスレッドを作り、そこで会話を続けます)\n- /gpt-config <show|set|reset> scope:<server|channel> prompt:<プロンプト>: /gpt の system プロンプトをサーバー・チャンネルごとに設定します (管理者用)\n- /summarize count:<件数?> since:<期間?>: チャンネルの最近のやり取りを LLM で要約します\n- /eval lang:<言語> code:<コード?> version:<バージョン?> stdin:<入力?> args:<引数?> color:<色付き?>: コードを実行します (code を省略すると複数行の入力欄を開きます)\n- /rust <fmt|clippy|expand|asm> code:<コード?> edition:<エディション?>: rustfmt / clippy / マクロ展開 / アセンブリ (code を省略すると入力欄を開きます)\n- /huki content:<テキスト>: 突然の死ジェネレーター\n- /get url:<url> headers:<JSON?>: HTTP GET\n- /post url:<url> payload:<JSON> headers:<JSON?>: HTTP POST".to_string()
 }
 
 // スラッシュコマンド情報
 pub const NAME: &str = "help";
 pub const DESCRIPTION: &str = "このヘルプを表示します";
 
-pub fn slash_register() -> CreateCommand {
-    CreateCommand::new(NAME).description(DESCRIPTION)
+pub struct Help;
+
+#[async_trait]
+impl super::Command for Help {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn description(&self) -> &'static str {
+        DESCRIPTION
+    }
+
+    async fn run(&self, ctx: &Context, msg: &Message) -> serenity::Result<()> {
+        run(ctx, msg).await
+    }
+
+    async fn slash_execute(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> serenity::Result<()> {
+        super::respond(ctx, command, slash_run(&command.data.options())).await
+    }
 }
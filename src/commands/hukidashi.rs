@@ -1,26 +1,49 @@
 use serenity::all::{
-    CommandDataOptionValue, CommandOptionType, Context, CreateCommand, CreateCommandOption,
+    CommandInteraction, CommandOptionType, Context, CreateCommandOption, Message, async_trait,
 };
 use unicode_width::UnicodeWidthChar;
 
+use super::args::{self, Args, FromArgs};
+
 pub const NAME: &str = "huki";
 pub const DESCRIPTION: &str = "totuzen no shi generator";
 
-pub fn slash_register() -> CreateCommand {
-    CreateCommand::new(NAME)
-        .description(DESCRIPTION)
-        .add_option(
+pub struct Hukidashi;
+
+#[async_trait]
+impl super::Command for Hukidashi {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn description(&self) -> &'static str {
+        DESCRIPTION
+    }
+
+    fn options(&self) -> Vec<CreateCommandOption> {
+        vec![
             CreateCommandOption::new(CommandOptionType::String, "content", "totuzen no shi")
                 .required(true),
-        )
-        .add_option(
             CreateCommandOption::new(
                 CommandOptionType::Boolean,
                 "x2",
                 "Make the frame twice as big.",
             )
             .required(false),
-        )
+        ]
+    }
+
+    async fn run(&self, ctx: &Context, msg: &Message) -> serenity::Result<()> {
+        run(ctx, msg).await
+    }
+
+    async fn slash_execute(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> serenity::Result<()> {
+        slash_execute(ctx, command).await
+    }
 }
 
 fn get_str_len<T: AsRef<str>>(strg: T) -> u32 {
@@ -99,36 +122,25 @@ fn s2hukix2<T: AsRef<str>>(s: T) -> String {
     ss
 }
 
-pub async fn slash_execute(
-    ctx: &Context,
-    command: &serenity::model::application::CommandInteraction,
-) -> serenity::Result<()> {
-    let input = command
-        .data
-        .options
-        .iter()
-        .find(|opt| opt.name == "content")
-        .unwrap()
-        .value
-        .clone();
-    let input_x2_mode = command
-        .data
-        .options
-        .iter()
-        .find(|opt| opt.name == "x2")
-        .map(|f| f.value.clone());
-
-    let c = if let CommandDataOptionValue::String(cc) = input {
-        let x2_mode = if let CommandDataOptionValue::Boolean(x) =
-            input_x2_mode.unwrap_or(CommandDataOptionValue::Boolean(false))
-        {
-            x
-        } else {
-            false
-        };
-        if x2_mode { s2hukix2(cc) } else { s2huki(cc) }
-    } else {
-        "なんかダメだったぁ".to_string()
+struct HukiArgs {
+    content: String,
+    x2: bool,
+}
+
+impl FromArgs for HukiArgs {
+    fn from_args(args: &Args) -> Result<Self, String> {
+        Ok(Self {
+            content: args.required_str("content")?,
+            x2: args.bool("x2").unwrap_or(false),
+        })
+    }
+}
+
+pub async fn slash_execute(ctx: &Context, command: &CommandInteraction) -> serenity::Result<()> {
+    let c = match args::from_interaction::<HukiArgs>(command) {
+        Ok(HukiArgs { content, x2: true }) => s2hukix2(content),
+        Ok(HukiArgs { content, x2: false }) => s2huki(content),
+        Err(_) => "なんかダメだったぁ".to_string(),
     };
     command
         .create_response(
@@ -141,6 +153,24 @@ pub async fn slash_execute(
     Ok(())
 }
 
+// プレフィックス: !huki [--x2] <テキスト>
+// メッセージへの返信で使うとテキスト省略時は返信先の本文を囲む
+pub async fn run(ctx: &Context, msg: &Message) -> serenity::Result<()> {
+    let parsed = Args::from_message_partial(&Hukidashi, msg).and_then(|mut args| {
+        if let Some(referenced) = &msg.referenced_message {
+            args.set_default("content", referenced.content.trim());
+        }
+        HukiArgs::from_args(&args)
+    });
+    let c = match parsed {
+        Ok(HukiArgs { content, x2: true }) => s2hukix2(content),
+        Ok(HukiArgs { content, x2: false }) => s2huki(content),
+        Err(_) => "使い方: !huki [--x2] <テキスト> (返信で使うと返信先の本文)".to_string(),
+    };
+    msg.channel_id.say(&ctx.http, c).await?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::commands::hukidashi::{s2huki, s2hukix2};
@@ -155,6 +185,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(unused_variables)]
     fn test_s2huki_x2() {
         let foo = "突然の死";
         let bar = "foo\nbar\nfoobar";
@@ -1,6 +1,9 @@
 use serenity::{
-    builder::CreateCommand,
-    model::{application::ResolvedOption, channel::Message},
+    async_trait,
+    model::{
+        application::{CommandInteraction, ResolvedOption},
+        channel::Message,
+    },
     prelude::Context,
 };
 
@@ -19,7 +22,27 @@ pub fn slash_run(_options: &[ResolvedOption]) -> String {
 pub const NAME: &str = "ping";
 pub const DESCRIPTION: &str = "ポン！と返します";
 
-// スラッシュコマンドのメタデータ登録
-pub fn slash_register() -> CreateCommand {
-    CreateCommand::new(NAME).description(DESCRIPTION)
+pub struct Ping;
+
+#[async_trait]
+impl super::Command for Ping {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn description(&self) -> &'static str {
+        DESCRIPTION
+    }
+
+    async fn run(&self, ctx: &Context, msg: &Message) -> serenity::Result<()> {
+        run(ctx, msg).await
+    }
+
+    async fn slash_execute(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> serenity::Result<()> {
+        super::respond(ctx, command, slash_run(&command.data.options())).await
+    }
 }
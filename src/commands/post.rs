@@ -3,11 +3,10 @@ use std::{collections::HashMap, time::Duration};
 use once_cell::sync::Lazy;
 use reqwest::Client;
 use serenity::{
-    builder::{
-        CreateAttachment, CreateCommand, CreateCommandOption, CreateMessage, EditAttachments,
-    },
+    async_trait,
+    builder::{CreateAttachment, CreateCommandOption, CreateMessage, EditAttachments},
     model::{
-        application::{CommandDataOptionValue, CommandOptionType},
+        application::{CommandInteraction, CommandOptionType},
         channel::Message,
     },
     prelude::Context,
@@ -16,6 +15,8 @@ use std::collections::HashMap as StdHashMap;
 use std::sync::Mutex;
 use url::Url;
 
+use super::args::{self, Args, FromArgs};
+
 const MAX_FILE_SIZE: usize = 10_000_000; // 10 MB
 const MAX_MESSAGE_SIZE: usize = 1900; // for code block safety
 const TIMEOUT_SECS: u64 = 5;
@@ -56,6 +57,22 @@ fn parse_headers_json(s: &str) -> Result<HashMap<String, String>, String> {
     }
 }
 
+struct PostArgs {
+    url: String,
+    payload: String,
+    headers: Option<String>,
+}
+
+impl FromArgs for PostArgs {
+    fn from_args(args: &Args) -> Result<Self, String> {
+        Ok(Self {
+            url: args.required_str("url")?,
+            payload: args.required_str("payload")?,
+            headers: args.str("headers").map(str::to_string),
+        })
+    }
+}
+
 fn parse_payload_json(s: &str) -> Result<serde_json::Value, String> {
     serde_json::from_str(s).map_err(|e| format!("ペイロード JSON の解析に失敗: {e}"))
 }
@@ -134,15 +151,13 @@ pub async fn run(ctx: &Context, msg: &Message) -> serenity::Result<()> {
             .await?;
         return Ok(());
     }
-    let content = msg.content.trim();
-    let rest = content
-        .strip_prefix(super::PREFIX)
-        .map(str::trim_start)
-        .and_then(|s| s.strip_prefix(NAME))
-        .map(str::trim)
-        .unwrap_or("");
-
-    if rest.is_empty() {
+    // parse: url, payload json (may contain spaces), optional --headers <json>
+    let Ok(PostArgs {
+        url,
+        payload: payload_str,
+        headers,
+    }) = args::from_message::<PostArgs>(&Post, msg)
+    else {
         msg.channel_id
             .say(
                 &ctx.http,
@@ -150,29 +165,9 @@ pub async fn run(ctx: &Context, msg: &Message) -> serenity::Result<()> {
             )
             .await?;
         return Ok(());
-    }
-
-    // parse first token url, second token payload json (may contain spaces -> We try to detect --headers flag)
-    // strategy: split by --headers once
-    let (left, headers_part) = match rest.split_once(" --headers ") {
-        Some(v) => v,
-        None => (rest, ""),
     };
-    let mut left_iter = left.split_whitespace();
-    let url = left_iter.next().unwrap_or("");
-    let payload_str = left_iter.collect::<Vec<_>>().join(" "); // join back the remainder as JSON
-
-    if url.is_empty() || payload_str.is_empty() {
-        msg.channel_id
-            .say(
-                &ctx.http,
-                "使い方: !post <url> <json_payload> [--headers <json>]",
-            )
-            .await?;
-        return Ok(());
-    }
 
-    if let Err(e) = validate_url(url) {
+    if let Err(e) = validate_url(&url) {
         msg.channel_id.say(&ctx.http, e).await?;
         return Ok(());
     }
@@ -185,22 +180,21 @@ pub async fn run(ctx: &Context, msg: &Message) -> serenity::Result<()> {
         }
     };
 
-    let headers = if headers_part.is_empty() {
-        HashMap::new()
-    } else {
+    let headers = match headers {
         // Parse headers if provided
-        match parse_headers_json(headers_part) {
+        Some(s) => match parse_headers_json(&s) {
             Ok(h) => h,
             Err(e) => {
                 msg.channel_id.say(&ctx.http, e).await?;
                 return Ok(());
             }
-        }
+        },
+        None => HashMap::new(),
     };
 
     msg.channel_id.say(&ctx.http, "送信中…").await?;
 
-    match http_post(url, headers, payload).await {
+    match http_post(&url, headers, payload).await {
         // Handle the HTTP POST response
         Ok((bytes, ct)) => {
             if let Some(mut s) = to_display_text(&bytes, &ct) {
@@ -277,10 +271,7 @@ pub async fn run(ctx: &Context, msg: &Message) -> serenity::Result<()> {
 }
 
 // スラッシュ: /post url:<url> payload:<json> headers:<json?>
-pub async fn slash_execute(
-    ctx: &Context,
-    command: &serenity::model::application::CommandInteraction,
-) -> serenity::Result<()> {
+pub async fn slash_execute(ctx: &Context, command: &CommandInteraction) -> serenity::Result<()> {
     // cooldown per user: do not await while locking
     let mut cooldown_remain: Option<u64> = None;
     {
@@ -312,43 +303,13 @@ pub async fn slash_execute(
             .await?;
         return Ok(());
     }
-    let mut url: Option<String> = None;
-    let mut payload_s: Option<String> = None;
-    let mut headers_json: Option<String> = None;
-
-    for opt in &command.data.options {
-        match (opt.name.as_str(), &opt.value) {
-            ("url", CommandDataOptionValue::String(s)) => url = Some(s.clone()),
-            ("payload", CommandDataOptionValue::String(s)) => payload_s = Some(s.clone()),
-            ("headers", CommandDataOptionValue::String(s)) => headers_json = Some(s.clone()),
-            _ => {}
-        }
-    }
-
-    let Some(url) = url else {
-        command
-            .create_response(
-                &ctx.http,
-                serenity::builder::CreateInteractionResponse::Message(
-                    serenity::builder::CreateInteractionResponseMessage::new()
-                        .content("url が必要です"),
-                ),
-            )
-            .await?;
-        return Ok(());
-    };
-
-    let Some(payload_s) = payload_s else {
-        command
-            .create_response(
-                &ctx.http,
-                serenity::builder::CreateInteractionResponse::Message(
-                    serenity::builder::CreateInteractionResponseMessage::new()
-                        .content("payload(JSON) が必要です"),
-                ),
-            )
-            .await?;
-        return Ok(());
+    let PostArgs {
+        url,
+        payload: payload_s,
+        headers: headers_json,
+    } = match args::from_interaction::<PostArgs>(command) {
+        Ok(a) => a,
+        Err(e) => return super::respond(ctx, command, e).await,
     };
 
     if let Err(e) = validate_url(&url) {
@@ -493,23 +454,44 @@ pub async fn slash_execute(
     }
 }
 
-pub fn slash_register() -> CreateCommand {
-    CreateCommand::new(NAME)
-        .description(DESCRIPTION)
-        .add_option(
+pub struct Post;
+
+#[async_trait]
+impl super::Command for Post {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn description(&self) -> &'static str {
+        DESCRIPTION
+    }
+
+    fn options(&self) -> Vec<CreateCommandOption> {
+        vec![
             CreateCommandOption::new(CommandOptionType::String, "url", "送信先URL").required(true),
-        )
-        .add_option(
             CreateCommandOption::new(
                 CommandOptionType::String,
                 "payload",
                 "JSON 形式のペイロード",
             )
             .required(true),
-        )
-        .add_option(CreateCommandOption::new(
-            CommandOptionType::String,
-            "headers",
-            "JSON 形式のヘッダー (任意)",
-        ))
+            CreateCommandOption::new(
+                CommandOptionType::String,
+                "headers",
+                "JSON 形式のヘッダー (任意)",
+            ),
+        ]
+    }
+
+    async fn run(&self, ctx: &Context, msg: &Message) -> serenity::Result<()> {
+        run(ctx, msg).await
+    }
+
+    async fn slash_execute(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> serenity::Result<()> {
+        slash_execute(ctx, command).await
+    }
 }
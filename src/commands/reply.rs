@@ -0,0 +1,135 @@
+// 編集に追従する返信
+//
+// プレフィックスコマンドの返信をここ経由で送ると、元のメッセージ → 返信 の対応を覚えておく。
+// 元のメッセージが一定時間内に編集されたら main の message_update がコマンドを実行し直し、
+// そのときの返信は新しく投稿せず前回の返信を書き換える。
+// 最後に実行したときの本文も覚えておき、本文が変わっていない更新（埋め込みの展開など）では実行し直さない。
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use serenity::{
+    builder::{CreateActionRow, CreateAttachment, CreateMessage, EditMessage},
+    model::{channel::Message, id::MessageId},
+    prelude::Context,
+};
+
+// 元のメッセージの編集で再実行する期間
+pub const EDIT_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Debug, Clone)]
+struct Tracked {
+    reply: MessageId,
+    at: Instant,
+    // 最後に実行したときの元のメッセージの本文
+    content: String,
+}
+
+// 元のメッセージ → 返信
+static REPLIES: Lazy<Mutex<HashMap<MessageId, Tracked>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 期間内の返信があればその記録
+fn tracked(source: MessageId) -> Option<Tracked> {
+    let mut replies = REPLIES.lock().unwrap();
+    replies.retain(|_, t| t.at.elapsed() < EDIT_WINDOW);
+    replies.get(&source).cloned()
+}
+
+fn track(source: MessageId, reply: MessageId, content: &str) {
+    REPLIES.lock().unwrap().entry(source).or_insert(Tracked {
+        reply,
+        at: Instant::now(),
+        content: content.to_string(),
+    });
+}
+
+/// 編集されたら再実行する対象なら、最後に実行したときの本文
+pub fn last_run(source: MessageId) -> Option<String> {
+    tracked(source).map(|t| t.content)
+}
+
+/// 編集で実行し直すときに、実行する本文を覚えておく
+pub fn set_last_run(source: MessageId, content: &str) {
+    if let Some(t) = REPLIES.lock().unwrap().get_mut(&source) {
+        t.content = content.to_string();
+    }
+}
+
+/// `msg` への返信を送る。前回の返信があればそれを書き換える
+pub async fn send(
+    ctx: &Context,
+    msg: &Message,
+    content: impl Into<String>,
+    components: Vec<CreateActionRow>,
+) -> serenity::Result<Message> {
+    send_files(ctx, msg, content, components, Vec::new()).await
+}
+
+/// ファイル付きの返信。書き換えるときは前回の添付を差し替える
+pub async fn send_files(
+    ctx: &Context,
+    msg: &Message,
+    content: impl Into<String>,
+    components: Vec<CreateActionRow>,
+    files: Vec<CreateAttachment>,
+) -> serenity::Result<Message> {
+    let content = content.into();
+    if let Some(Tracked { reply, .. }) = tracked(msg.id) {
+        let edit = files.iter().cloned().fold(
+            EditMessage::new()
+                .content(content.clone())
+                .components(components.clone())
+                .remove_all_attachments(),
+            |edit, file| edit.new_attachment(file),
+        );
+        // 返信が消されていたら新しく送る
+        if let Ok(message) = msg.channel_id.edit_message(&ctx.http, reply, edit).await {
+            return Ok(message);
+        }
+        REPLIES.lock().unwrap().remove(&msg.id);
+    }
+    let message = msg
+        .channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(content)
+                .components(components)
+                .add_files(files),
+        )
+        .await?;
+    track(msg.id, message.id, &msg.content);
+    Ok(message)
+}
+
+/// テキストだけの返信
+pub async fn say(ctx: &Context, msg: &Message, content: impl Into<String>) -> serenity::Result<()> {
+    send(ctx, msg, content, Vec::new()).await.map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_first_reply_only() {
+        let source = MessageId::new(10);
+        assert_eq!(last_run(source), None);
+        track(source, MessageId::new(11), "!eval 1");
+        // 2回目以降は上書きしない（期間は最初の返信から数える）
+        track(source, MessageId::new(12), "!eval 2");
+        assert_eq!(tracked(source).map(|t| t.reply), Some(MessageId::new(11)));
+        assert_eq!(last_run(source).as_deref(), Some("!eval 1"));
+
+        // 実行し直した本文は覚え直す
+        set_last_run(source, "!eval 3");
+        assert_eq!(last_run(source).as_deref(), Some("!eval 3"));
+        // 記録のないメッセージには何もしない
+        set_last_run(MessageId::new(13), "!eval 4");
+        assert_eq!(last_run(MessageId::new(13)), None);
+    }
+}
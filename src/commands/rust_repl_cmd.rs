@@ -1,57 +1,80 @@
-use reqwest;
 use serde::{Deserialize, Serialize};
 use serenity::{
-    builder::{CreateCommand, CreateCommandOption},
-    model::{application::CommandOptionType, channel::Message},
+    async_trait,
+    builder::CreateCommandOption,
+    model::{application::CommandOptionType, channel::Message, id::MessageId},
     prelude::Context,
 };
-use std::{collections::HashMap, fmt::Display};
+use std::fmt::Display;
+
+use super::{ansi, args::Args};
+use crate::cargo_runner;
+use crate::commands::eval::{codegen, deps, lang_to_extension};
+use crate::piston::Lang;
+use crate::runner::{self, Runner};
+
+mod session;
 
 // use crate::rust_repl::rust_repl::{self, CodeRunner};
 
 pub const NAME: &str = "rrepl";
 pub const DESCRIPTION: &str = "簡易的なRust REPL";
 
-fn code_format<T: AsRef<str>>(str: T) -> (String, String) {
-    let str = str
-        .as_ref()
-        .strip_prefix(super::PREFIX)
-        .map(str::trim_start)
-        .and_then(|s| s.strip_prefix(NAME))
-        .map(str::trim_start)
-        .unwrap_or("");
-
-    match str.strip_prefix("```").and_then(|s| s.strip_suffix("```")) {
-        Some(v) => {
-            let inner = v.trim();
-            let mut lines = inner.splitn(2, '\n');
-            let lang = lines.next().unwrap_or("").trim().to_string();
-            let code = lines.next().unwrap_or("").trim().to_string();
-            (lang, code)
-        }
-        None => ("rust".to_string(), str.to_string()),
-    }
+/// "!rrepl [--color] ```lang\ncode```" から (言語, コード, 色付きか) を取り出す。言語タグが無ければ rust
+fn code_format<T: AsRef<str>>(str: T) -> (String, String, bool) {
+    let args = Args::from_content(&RustRepl, str.as_ref()).unwrap_or_default();
+    let lang = args.fence_lang("code").unwrap_or("rust").to_string();
+    let code = args.str("code").unwrap_or("").to_string();
+    (lang, code, args.bool("color").unwrap_or(false))
 }
 
 pub async fn run(ctx: &Context, msg: &Message) -> serenity::Result<()> {
     let content = msg.content.trim();
 
-    let code = code_format(content);
+    let (lang, code, color) = code_format(content);
 
-    let res = call_api(code.0, code.1)
-        .await
-        .map_err(|_| serenity::Error::Other("api error"))?;
-    msg.channel_id.say(&ctx.http, res).await?;
-    Ok(())
+    let runner = runner::client();
+    let res = match resolve(runner, &lang).await {
+        // Rust はセッションとして、前回までの入力を引き継ぐ
+        Ok(lang) if lang.language == "rust" => {
+            let key = (msg.author.id, msg.channel_id);
+            session_run(runner, &lang, key, msg.id, &code, color).await
+        }
+        _ => call_api(runner, lang, code, color)
+            .await
+            .unwrap_or_else(|e| e),
+    };
+    // 元のメッセージが編集されたら、前回の返信を書き換える
+    super::reply::say(ctx, msg, res).await
 }
 
-pub fn slash_register() -> CreateCommand {
-    CreateCommand::new(NAME)
-        .description(DESCRIPTION)
-        .add_option(
+pub struct RustRepl;
+
+#[async_trait]
+impl super::Command for RustRepl {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn description(&self) -> &'static str {
+        DESCRIPTION
+    }
+
+    fn options(&self) -> Vec<CreateCommandOption> {
+        vec![
             CreateCommandOption::new(CommandOptionType::String, "code", "簡易実行するRustコード")
                 .required(true),
-        )
+            CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "color",
+                "色付きの出力 (コンパイラの診断など。Piston では使えません)",
+            ),
+        ]
+    }
+
+    async fn run(&self, ctx: &Context, msg: &Message) -> serenity::Result<()> {
+        run(ctx, msg).await
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -59,15 +82,40 @@ struct ReqJson {
     language: String,
     version: String,
     files: Vec<FileContent>,
+    // 色付きの出力を頼む（Piston にない項目なので、使うときだけ送る）
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    color: bool,
 }
 
 impl ReqJson {
-    fn new(lang: String, code: String) -> Self {
-        Self {
-            language: lang.clone(),
-            version: "1.68.2".to_string(),
-            files: vec![FileContent::new(lang, code)],
+    /// "// deps:" 付きの Rust は Cargo プロジェクトにする
+    fn new(lang: &Lang, code: String, color: bool) -> Result<Self, String> {
+        let project = match lang.language.as_str() {
+            "rust" => deps::cargo_project(&code, false)?,
+            _ => None,
+        };
+        let files = match project {
+            Some(project) => project
+                .into_iter()
+                .map(|(name, content)| FileContent { name, content })
+                .collect(),
+            None => vec![FileContent::new(lang.language.as_str(), &code)],
+        };
+        Ok(Self {
+            language: lang.language.clone(),
+            version: lang.version.clone(),
+            files,
+            color,
+        })
+    }
+
+    /// 実行環境か、Cargo.toml 付きならローカルの Cargo で実行する
+    async fn send(&self, runner: &dyn Runner) -> Result<Resp, String> {
+        if self.files.iter().any(|f| f.name == deps::MANIFEST) {
+            return cargo_runner::client().execute(self).await;
         }
+        runner::check_color(runner, self.color)?;
+        runner::execute(runner, self).await
     }
 }
 
@@ -77,38 +125,13 @@ struct FileContent {
     content: String,
 }
 
-fn lang_to_extension<T: AsRef<str>>(lang: T) -> String {
-    // 言語 → 拡張子 のテーブル
-    let table: HashMap<&str, &str> = [
-        ("rust", "rs"),
-        ("python", "py"),
-        ("c++", "cpp"),
-        ("c", "c"),
-        ("java", "java"),
-        ("javascript", "js"),
-        ("typescript", "ts"),
-        ("go", "go"),
-        ("ruby", "rb"),
-        ("html", "html"),
-        ("css", "css"),
-        ("shell", "sh"),
-    ]
-    .iter()
-    .cloned()
-    .collect();
-
-    table
-        .get(lang.as_ref().to_lowercase().as_str())
-        .unwrap_or(&"rs")
-        .to_string()
-}
-
 impl FileContent {
+    /// `lang` は実行環境の一覧で解決済みの言語名。言語に合わせて main などで包む
     fn new<T: AsRef<str>>(lang: T, code: T) -> Self {
-        let code = code.as_ref().to_string();
+        let lang = lang.as_ref();
         Self {
             name: format!("main.{}", lang_to_extension(lang)),
-            content: format!("fn main() {{{code}}}"),
+            content: codegen::code_generator(code, lang, false),
         }
     }
 }
@@ -117,17 +140,50 @@ impl FileContent {
 struct Resp {
     language: String,
     version: String,
-    run: Run,
-    compile: Compile,
+    // コンパイルに失敗したときは無い
+    #[serde(default)]
+    run: Option<Run>,
+    #[serde(default)]
+    compile: Option<Compile>,
+}
+
+impl Resp {
+    /// コンパイル・実行とも正常終了したか
+    fn succeeded(&self) -> bool {
+        let compiled = self
+            .compile
+            .as_ref()
+            .is_none_or(|c| c.code.is_none_or(|code| code == 0));
+        compiled && self.run.as_ref().is_some_and(|r| r.code == Some(0))
+    }
+
+    /// 表示する出力。実行されなかったときはコンパイルエラー
+    fn output(&self) -> &str {
+        match (&self.run, &self.compile) {
+            (Some(run), _) => &run.output,
+            (None, Some(compile)) => &compile.stderr,
+            (None, None) => "",
+        }
+    }
+
+    /// 色が付いていれば Discord で表示できる色だけにして ```ansi にする
+    fn format(&self, output: &str) -> String {
+        let output = ansi::to_discord(output);
+        let block = if ansi::is_colored(&output) {
+            "ansi"
+        } else {
+            "bash"
+        };
+        format!(
+            "lang: {}\nversion: {}\nresult:\n```{block}\n{}```",
+            self.language, self.version, output
+        )
+    }
 }
 
 impl Display for Resp {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "lang: {}\nversion: {}\nresult:\n```bash\n{}```",
-            self.language, self.version, self.run.output
-        )
+        write!(f, "{}", self.format(self.output()))
     }
 }
 
@@ -135,40 +191,117 @@ impl Display for Resp {
 struct Run {
     stdout: String,
     stderr: String,
-    code: i32,
+    code: Option<i32>,
     output: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Compile {
     stdout: String,
+    #[serde(default)]
+    stderr: String,
+    #[serde(default)]
+    code: Option<i32>,
 }
 
-pub async fn call_api<T: AsRef<str>>(lang: T, code: T) -> Result<String, reqwest::Error> {
-    let client = reqwest::Client::new();
-    let req_info = ReqJson::new(lang.as_ref().to_string(), code.as_ref().to_string());
+/// 言語名・エイリアス (rs, py, js など) を実行環境の一覧から引く。複数バージョンあれば最新
+async fn resolve(runner: &dyn Runner, lang: &str) -> Result<Lang, String> {
+    let langs = runner.languages().await?;
+    langs
+        .get(lang)
+        .cloned()
+        .ok_or_else(|| format!("not supported lang: {lang}"))
+}
+
+pub async fn call_api<T: AsRef<str>>(
+    runner: &dyn Runner,
+    lang: T,
+    code: T,
+    color: bool,
+) -> Result<String, String> {
+    let lang = resolve(runner, lang.as_ref()).await?;
+    let req_info = ReqJson::new(&lang, code.as_ref().to_string(), color)?;
     println!("{:?}", &req_info);
-    let res = client
-        .post("https://emkc.org/api/v2/piston/execute")
-        .json(&req_info)
-        .send()
-        .await?;
-    let res = res.json::<Resp>().await?;
+    let res = req_info.send(runner).await?;
     Ok(format!("{res}"))
 }
 
+/// セッションの入力を1つ処理して、返信の本文を返す
+async fn session_run(
+    runner: &dyn Runner,
+    lang: &Lang,
+    key: session::Key,
+    source: MessageId,
+    code: &str,
+    color: bool,
+) -> String {
+    match session::Input::parse(code) {
+        session::Input::Reset => {
+            session::reset(key);
+            "セッションをリセットしました".to_string()
+        }
+        session::Input::Show => {
+            let sources = session::show(key);
+            if sources.is_empty() {
+                "セッションは空です".to_string()
+            } else {
+                format!("```rust\n{}\n```", sources.join("\n"))
+            }
+        }
+        session::Input::Undo => match session::undo(key) {
+            Some(source) => format!("取り消しました:\n```rust\n{source}\n```"),
+            None => "取り消す入力がありません".to_string(),
+        },
+        session::Input::Unknown(cmd) => format!("{cmd} は不明なコマンドです\n{}", session::HELP),
+        session::Input::Code(code) => {
+            let program = session::program(key, source, code);
+            let res = async { ReqJson::new(lang, program, color)?.send(runner).await }.await;
+            let res = match res {
+                Ok(res) => res,
+                Err(e) => return e,
+            };
+            let mut reply = res.format(session::new_output(res.output()));
+            if !res.succeeded() {
+                return reply;
+            }
+            match session::record(key, source, code) {
+                session::Recorded::Full => reply.push_str(&format!(
+                    "\n入力が{}件に達したため記録しませんでした（:reset で消せます）",
+                    session::MAX_ENTRIES
+                )),
+                session::Recorded::Replaced { dropped } if dropped > 0 => reply.push_str(&format!(
+                    "\n編集した入力より後の入力 {dropped} 件をセッションから外しました"
+                )),
+                _ => {}
+            }
+            reply
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::commands::rust_repl_cmd::{FileContent, call_api, code_format};
+    use crate::commands::rust_repl_cmd::{FileContent, call_api, code_format, session_run};
+    use crate::piston::{Lang, Piston, fake};
+    use crate::test_support::fake_http::FakeHttp;
+    use serenity::model::id::{ChannelId, MessageId, UserId};
 
     #[test]
     fn test_api() {
         let code = r#"println!("Hello worold!");"#;
         let rt = tokio::runtime::Runtime::new().unwrap();
-        let res = rt.block_on(async {
-            let res = call_api("rust", code).await.unwrap();
-            res
-        });
+        let fake = FakeHttp::start(
+            serde_json::json!([{"language": "rust", "version": "1.68.2", "aliases": ["rs"]}]),
+            |req| fake::output(req, "Hello worold!\n"),
+        );
+        let piston = Piston::new(fake::config(&fake));
+        let res = rt.block_on(async { call_api(&piston, "rust", code, false).await.unwrap() });
+        let sent = fake.requests().pop().unwrap().body;
+        assert_eq!(sent["version"], "1.68.2");
+        assert_eq!(
+            sent["files"][0]["content"],
+            "fn main() {println!(\"Hello worold!\");}"
+        );
         // println!("{res}");
         let ans = r#"lang: rust
 version: 1.68.2
@@ -179,6 +312,51 @@ Hello worold!
         assert_eq!(res, ans);
     }
 
+    #[tokio::test]
+    async fn resolves_fence_language() {
+        let fake = FakeHttp::start(
+            serde_json::json!([
+                {"language": "python", "version": "3.10.0", "aliases": ["py"]},
+                {"language": "python", "version": "3.12.0", "aliases": ["py"]},
+            ]),
+            |req| fake::output(req, "3\n"),
+        );
+        let piston = Piston::new(fake::config(&fake));
+        let res = call_api(&piston, "py", "print(1 + 2)", false)
+            .await
+            .unwrap();
+        assert!(res.starts_with("lang: python\nversion: 3.12.0\n"), "{res}");
+        let sent = fake.requests().pop().unwrap().body;
+        assert_eq!(sent["files"][0]["name"], "main.py");
+        // Python は main で包まない
+        assert_eq!(sent["files"][0]["content"], "print(1 + 2)");
+
+        let err = call_api(&piston, "cobol", "", false).await.unwrap_err();
+        assert_eq!(err, "not supported lang: cobol");
+    }
+
+    #[tokio::test]
+    async fn colored_output_uses_ansi_block() {
+        let fake = FakeHttp::start(
+            serde_json::json!([{"language": "python", "version": "3.12.0", "aliases": ["py"]}]),
+            |req| fake::output(req, "\x1b[91mred\x1b[0m\n"),
+        );
+        let piston = Piston::new(fake::config(&fake));
+        let res = call_api(&piston, "py", "print(1)", false).await.unwrap();
+        assert!(res.ends_with("```ansi\n\x1b[0;31mred\x1b[0m\n```"), "{res}");
+        // 頼まなければ color は送らない
+        assert!(fake.requests().pop().unwrap().body.get("color").is_none());
+
+        // Piston は色付きの出力を頼めないので、送らずにエラーにする
+        let sent = fake.requests().len();
+        let err = call_api(&piston, "py", "print(1)", true).await.unwrap_err();
+        assert!(err.contains("color は使えません"), "{err}");
+        assert_eq!(fake.requests().len(), sent);
+
+        let (_, _, color) = code_format("!rrepl --color ```py\nprint(1)```");
+        assert!(color);
+    }
+
     #[test]
     fn test_emb_code() {
         let code = r#"println!("Hello worold!");"#;
@@ -209,4 +387,36 @@ println!("HEllo World");
         let res = code_format(code);
         println!("lang: {}\ncode: {}", res.0, res.1);
     }
+
+    #[tokio::test]
+    async fn session_replays_previous_inputs() {
+        let fake = FakeHttp::start(serde_json::json!([]), |req| {
+            fake::output(req, "1\n__rrepl_session_marker__\n2\n")
+        });
+        let piston = Piston::new(fake::config(&fake));
+        let rust = Lang {
+            language: "rust".to_string(),
+            version: "1.68.2".to_string(),
+            aliases: Vec::new(),
+        };
+        let key = (UserId::new(10), ChannelId::new(20));
+        let run = |id, code| session_run(&piston, &rust, key, MessageId::new(id), code, false);
+
+        run(1, "let x = 1;").await;
+        let res = run(2, "x + 1").await;
+        // 実行し直した分の出力は表示しない
+        assert!(res.ends_with("result:\n```bash\n2\n```"), "{res}");
+        let sent = fake.requests()[1].body["files"][0]["content"].clone();
+        let sent = sent.as_str().unwrap();
+        assert!(sent.contains("let x = 1;\nprintln!(\"__rrepl_session_marker__\");"));
+
+        assert_eq!(run(3, ":show").await, "```rust\nlet x = 1;\nx + 1\n```");
+        assert!(
+            run(3, ":nope")
+                .await
+                .starts_with(":nope は不明なコマンドです")
+        );
+        run(3, ":reset").await;
+        assert_eq!(run(3, ":show").await, "セッションは空です");
+    }
 }
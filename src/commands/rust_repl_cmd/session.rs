@@ -0,0 +1,225 @@
+// rrepl のセッション（ユーザー・チャンネルごと）
+//
+// 成功した入力を覚えておき、次の入力の前に毎回まとめて実行し直すことで
+// let の束縛や use・struct・fn の定義を引き継ぐ (evcxr のような使い心地)。
+// 実行し直した分の出力は区切り行より前に出るので、表示では捨てる。
+// 入力は元のメッセージごとに1つで、メッセージが編集されたらその場で置き換える。
+// 編集された入力より後の入力は、編集前の内容を前提にしているので捨てる。
+
+use std::{collections::HashMap, sync::Mutex};
+
+use once_cell::sync::Lazy;
+use serenity::model::id::{ChannelId, MessageId, UserId};
+
+use crate::commands::eval::codegen;
+
+pub type Key = (UserId, ChannelId);
+
+// これより前の出力は、以前の入力を実行し直した分
+const MARKER: &str = "__rrepl_session_marker__";
+// 覚えておく入力の最大数
+pub const MAX_ENTRIES: usize = 100;
+
+#[derive(Debug, Clone)]
+struct Entry {
+    // 入力したメッセージ
+    message: MessageId,
+    // 入力されたままのコード (:show 用)
+    source: String,
+    // 実行し直すときのコード。最後の式は表示しないよう文にする
+    replay: String,
+}
+
+static SESSIONS: Lazy<Mutex<HashMap<Key, Vec<Entry>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// ":reset" などのサブコマンド
+#[derive(Debug, PartialEq)]
+pub enum Input<'a> {
+    Reset,
+    Show,
+    Undo,
+    Unknown(&'a str),
+    Code(&'a str),
+}
+
+impl<'a> Input<'a> {
+    pub fn parse(code: &'a str) -> Self {
+        match code.trim() {
+            ":reset" => Self::Reset,
+            ":show" => Self::Show,
+            ":undo" => Self::Undo,
+            s if s.starts_with(':') && !s.contains(char::is_whitespace) => Self::Unknown(s),
+            _ => Self::Code(code),
+        }
+    }
+}
+
+pub const HELP: &str =
+    ":reset セッションを消す / :show これまでの入力を表示 / :undo 最後の入力を取り消す";
+
+/// 以前の入力 + 区切り + 今回の入力 のコード（main で包む前）。
+/// `message` が編集されたものなら、その以前の入力より前だけを実行し直す
+pub fn program(key: Key, message: MessageId, code: &str) -> String {
+    let sessions = SESSIONS.lock().unwrap();
+    let mut program: Vec<&str> = sessions
+        .get(&key)
+        .into_iter()
+        .flatten()
+        .take_while(|e| e.message != message)
+        .map(|e| e.replay.as_str())
+        .collect();
+    if program.is_empty() {
+        return code.to_string();
+    }
+    let marker = format!("println!(\"{MARKER}\");");
+    program.push(&marker);
+    program.push(code);
+    program.join("\n")
+}
+
+/// 実行し直した分を除いた出力
+pub fn new_output(output: &str) -> &str {
+    match output.split_once(&format!("{MARKER}\n")) {
+        Some((_, new)) => new,
+        None => output,
+    }
+}
+
+/// 入力を覚えた結果
+#[derive(Debug, PartialEq)]
+pub enum Recorded {
+    Added,
+    // 編集された入力を置き換え、それより後の入力を `dropped` 件捨てた
+    Replaced { dropped: usize },
+    // 上限に達していたので覚えなかった
+    Full,
+}
+
+/// 成功した入力を覚える。編集されたメッセージならその入力を置き換える
+pub fn record(key: Key, message: MessageId, code: &str) -> Recorded {
+    let replay = match codegen::trailing_expr(code) {
+        // 借用にして、値を move しないようにする
+        Some((stmts, expr)) => format!("{stmts}\nlet _ = &(\n{expr}\n);"),
+        None => code.to_string(),
+    };
+    let mut sessions = SESSIONS.lock().unwrap();
+    let history = sessions.entry(key).or_default();
+    let entry = Entry {
+        message,
+        source: code.trim().to_string(),
+        replay,
+    };
+    if let Some(pos) = history.iter().position(|e| e.message == message) {
+        let dropped = history.len() - pos - 1;
+        history.truncate(pos);
+        history.push(entry);
+        return Recorded::Replaced { dropped };
+    }
+    if history.len() >= MAX_ENTRIES {
+        return Recorded::Full;
+    }
+    history.push(entry);
+    Recorded::Added
+}
+
+pub fn reset(key: Key) {
+    SESSIONS.lock().unwrap().remove(&key);
+}
+
+/// これまでの入力
+pub fn show(key: Key) -> Vec<String> {
+    SESSIONS
+        .lock()
+        .unwrap()
+        .get(&key)
+        .map(|h| h.iter().map(|e| e.source.clone()).collect())
+        .unwrap_or_default()
+}
+
+/// 最後の入力を取り消して返す
+pub fn undo(key: Key) -> Option<String> {
+    SESSIONS
+        .lock()
+        .unwrap()
+        .get_mut(&key)?
+        .pop()
+        .map(|e| e.source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_lifecycle() {
+        let key = (UserId::new(1), ChannelId::new(2));
+        let m = MessageId::new;
+        assert_eq!(program(key, m(1), "let x = 1;"), "let x = 1;");
+
+        assert_eq!(record(key, m(1), "let x = 1;"), Recorded::Added);
+        assert_eq!(
+            record(key, m(2), "use std::collections::HashMap;\nx + 1"),
+            Recorded::Added
+        );
+        assert_eq!(
+            program(key, m(3), "x * 2"),
+            format!(
+                "let x = 1;\nuse std::collections::HashMap;\nlet _ = &(\nx + 1\n);\nprintln!(\"{MARKER}\");\nx * 2"
+            )
+        );
+        assert_eq!(
+            show(key),
+            ["let x = 1;", "use std::collections::HashMap;\nx + 1"]
+        );
+        // 編集されたメッセージは、それより前の入力だけを実行し直して置き換える
+        assert_eq!(
+            program(key, m(2), "x + 2"),
+            format!("let x = 1;\nprintln!(\"{MARKER}\");\nx + 2")
+        );
+        assert_eq!(program(key, m(1), "let x = 5;"), "let x = 5;");
+        // 後の入力は編集前の内容を前提にしているので捨てる
+        assert_eq!(
+            record(key, m(1), "let x = 5;"),
+            Recorded::Replaced { dropped: 1 }
+        );
+        assert_eq!(show(key), ["let x = 5;"]);
+        assert_eq!(record(key, m(2), "x + 1"), Recorded::Added);
+        assert_eq!(
+            record(key, m(2), "x + 2"),
+            Recorded::Replaced { dropped: 0 }
+        );
+        assert_eq!(show(key), ["let x = 5;", "x + 2"]);
+
+        assert_eq!(undo(key).as_deref(), Some("x + 2"));
+        assert_eq!(show(key), ["let x = 5;"]);
+        reset(key);
+        assert!(show(key).is_empty());
+        assert_eq!(undo(key), None);
+
+        // 上限に達していても、編集された入力は置き換えられる
+        for n in 0..MAX_ENTRIES as u64 {
+            record(key, m(10 + n), "let z = 0;");
+        }
+        assert_eq!(record(key, m(5), "let w = 0;"), Recorded::Full);
+        assert_eq!(
+            record(key, m(10 + MAX_ENTRIES as u64 - 1), "let z = 1;"),
+            Recorded::Replaced { dropped: 0 }
+        );
+        assert_eq!(show(key).len(), MAX_ENTRIES);
+        assert_eq!(show(key).last().map(String::as_str), Some("let z = 1;"));
+        reset(key);
+
+        // 他のユーザー・チャンネルとは別
+        record(key, m(4), "let y = 2;");
+        assert!(show((UserId::new(1), ChannelId::new(3))).is_empty());
+    }
+
+    #[test]
+    fn parse_and_output() {
+        assert_eq!(Input::parse(" :reset "), Input::Reset);
+        assert_eq!(Input::parse(":foo"), Input::Unknown(":foo"));
+        assert_eq!(Input::parse("let a = 1;"), Input::Code("let a = 1;"));
+        assert_eq!(new_output(&format!("old\n{MARKER}\nnew\n")), "new\n");
+        assert_eq!(new_output("no marker\n"), "no marker\n");
+    }
+}
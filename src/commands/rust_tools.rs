@@ -0,0 +1,337 @@
+// /rust fmt|clippy|expand|asm: Rust Playground 互換 API でコードを整形・検査する
+//
+// 実行 (rrepl / eval) は Piston、それ以外のツールは Playground を使う。
+// 接続先は playground.rs の PLAYGROUND_URL で変更できる。
+
+use serenity::{
+    async_trait,
+    builder::{
+        CreateActionRow, CreateAttachment, CreateCommandOption, CreateInputText,
+        CreateInteractionResponse, CreateModal, EditInteractionResponse,
+    },
+    model::{
+        application::{
+            ActionRowComponent, CommandInteraction, CommandOptionType, InputTextStyle,
+            ModalInteraction,
+        },
+        channel::Message,
+    },
+    prelude::Context,
+};
+
+use super::args::Args;
+use crate::playground::{self, DEFAULT_EDITION, Output, Playground, Tool};
+
+pub const NAME: &str = "rust";
+pub const DESCRIPTION: &str = "Rust のコードを整形・検査します (fmt / clippy / expand / asm)";
+
+// これより長い結果はファイルで添付する
+const MAX_MESSAGE_SIZE: usize = 1900;
+const EDITIONS: &[&str] = &["2015", "2018", "2021", "2024"];
+
+/// 返信の内容
+#[derive(Debug, PartialEq)]
+struct Rendered {
+    content: String,
+    // (ファイル名, 中身)
+    file: Option<(String, String)>,
+}
+
+impl Rendered {
+    fn text(content: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            file: None,
+        }
+    }
+
+    fn attachment(&self) -> Option<CreateAttachment> {
+        self.file
+            .as_ref()
+            .map(|(name, body)| CreateAttachment::bytes(body.as_bytes().to_vec(), name.clone()))
+    }
+}
+
+/// 貼り付けられたコードブロックの ``` を外す
+fn strip_fence(code: &str) -> &str {
+    let code = code.trim();
+    match code.strip_prefix("```").and_then(|s| s.strip_suffix("```")) {
+        // 1行目が単語だけなら言語タグ
+        Some(inner) => match inner.split_once('\n') {
+            Some((tag, body)) if tag.chars().all(|c| c.is_ascii_alphanumeric()) => body.trim(),
+            _ => inner.trim(),
+        },
+        None => code,
+    }
+}
+
+/// cargo の進捗行 ("Compiling ...", "Finished ..." など) を除く
+fn strip_cargo_status(stderr: &str) -> String {
+    const STATUS: &[&str] = &[
+        "Compiling ",
+        "Checking ",
+        "Finished ",
+        "Running ",
+        "Blocking ",
+        "Updating ",
+        "Downloading ",
+        "Downloaded ",
+    ];
+    stderr
+        .lines()
+        .filter(|l| !(l.starts_with(' ') && STATUS.iter().any(|s| l.trim_start().starts_with(s))))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+fn code_block(lang: &str, s: &str) -> String {
+    let s = s.trim_end().replace("```", "`\u{200b}``");
+    format!("```{lang}\n{s}\n```")
+}
+
+/// Playground の応答を返信にする。長ければファイルで添付する
+fn render(tool: Tool, out: &Output) -> Rendered {
+    let stderr = strip_cargo_status(&out.stderr);
+    let (lang, body, file_name) = match tool {
+        _ if !out.success => ("", stderr.as_str(), "error.txt"),
+        Tool::Fmt => ("rust", out.code.as_deref().unwrap_or(""), "main.rs"),
+        Tool::Expand => ("rust", out.stdout.as_str(), "expanded.rs"),
+        Tool::Asm => ("x86asm", out.code.as_deref().unwrap_or(""), "main.s"),
+        Tool::Clippy if stderr.is_empty() => return Rendered::text("clippy: 警告はありません ✅"),
+        Tool::Clippy => ("", stderr.as_str(), "clippy.txt"),
+    };
+    let title = if out.success {
+        format!("{}:", tool.name())
+    } else {
+        format!("{}: ⚠️ 失敗しました", tool.name())
+    };
+    let block = code_block(lang, body);
+    if title.len() + block.len() < MAX_MESSAGE_SIZE {
+        return Rendered::text(format!("{title}\n{block}"));
+    }
+    Rendered {
+        content: format!("{title} 結果が長いためファイルで添付します"),
+        file: Some((file_name.to_string(), body.to_string())),
+    }
+}
+
+async fn execute(pg: &Playground, tool: Tool, code: &str, edition: &str) -> Rendered {
+    let code = strip_fence(code);
+    if code.is_empty() {
+        return Rendered::text("code が必要です");
+    }
+    match pg.run(tool, code, edition).await {
+        Ok(out) => render(tool, &out),
+        Err(e) => {
+            println!("Playground エラー: {e:?}");
+            Rendered::text("Playground への接続に失敗しました。")
+        }
+    }
+}
+
+fn usage() -> String {
+    let tools: Vec<&str> = Tool::ALL.iter().map(|t| t.name()).collect();
+    format!(
+        "使い方: !rust <{}> [edition:2021] ```rust\n<コード>```",
+        tools.join("|")
+    )
+}
+
+// プレフィックスコマンド: !rust fmt ```rust ...```
+pub async fn run(ctx: &Context, msg: &Message) -> serenity::Result<()> {
+    let args = match Args::from_message(&RustTools, msg) {
+        Ok(args) => args,
+        Err(e) => return super::reply::say(ctx, msg, format!("{e}\n{}", usage())).await,
+    };
+    let Some(tool) = args.subcommand().and_then(Tool::from_name) else {
+        return super::reply::say(ctx, msg, usage()).await;
+    };
+    let Some(code) = args.str("code") else {
+        return super::reply::say(ctx, msg, usage()).await;
+    };
+    let edition = args.str("edition").unwrap_or(DEFAULT_EDITION);
+
+    let typing = msg.channel_id.start_typing(&ctx.http);
+    let rendered = execute(playground::client(), tool, code, edition).await;
+    typing.stop();
+
+    let files = rendered.attachment().into_iter().collect();
+    super::reply::send_files(ctx, msg, rendered.content, Vec::new(), files).await?;
+    Ok(())
+}
+
+/// スラッシュ版の返信（defer 済みの応答を書き換える）
+fn edit_response(rendered: Rendered) -> EditInteractionResponse {
+    let edit = EditInteractionResponse::new();
+    match rendered.attachment() {
+        Some(file) => edit.content(rendered.content).new_attachment(file),
+        None => edit.content(rendered.content),
+    }
+}
+
+pub struct RustTools;
+
+#[async_trait]
+impl super::Command for RustTools {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn description(&self) -> &'static str {
+        DESCRIPTION
+    }
+
+    fn named_only(&self) -> &'static [&'static str] {
+        &["edition"]
+    }
+
+    fn options(&self) -> Vec<CreateCommandOption> {
+        Tool::ALL
+            .iter()
+            .map(|tool| {
+                let edition = EDITIONS.iter().fold(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "edition",
+                        "エディション (既定: 2021)",
+                    ),
+                    |opt, e| opt.add_string_choice(*e, *e),
+                );
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    tool.name(),
+                    tool.description(),
+                )
+                .add_sub_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "code",
+                    "Rust のコード (省略すると入力欄を開きます)",
+                ))
+                .add_sub_option(edition)
+            })
+            .collect()
+    }
+
+    async fn run(&self, ctx: &Context, msg: &Message) -> serenity::Result<()> {
+        run(ctx, msg).await
+    }
+
+    async fn slash_execute(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> serenity::Result<()> {
+        let args = Args::from_interaction(command);
+        let Some(tool) = args.subcommand().and_then(Tool::from_name) else {
+            return super::respond(ctx, command, usage()).await;
+        };
+        let edition = args.str("edition").unwrap_or(DEFAULT_EDITION);
+        // code は改行を含められないので、省略時は複数行の入力欄を開く
+        let Some(code) = args.str("code") else {
+            let input = CreateInputText::new(InputTextStyle::Paragraph, "コード", "code")
+                .placeholder("fn main() {}")
+                .max_length(4000);
+            let modal = CreateModal::new(
+                super::custom_id(self, &format!("{}:{edition}", tool.name())),
+                format!("rust {}", tool.name()),
+            )
+            .components(vec![CreateActionRow::InputText(input)]);
+            return command
+                .create_response(&ctx.http, CreateInteractionResponse::Modal(modal))
+                .await;
+        };
+
+        command.defer(&ctx.http).await?;
+        let rendered = execute(playground::client(), tool, code, edition).await;
+        command
+            .edit_response(&ctx.http, edit_response(rendered))
+            .await?;
+        Ok(())
+    }
+
+    /// 入力欄からの送信 ("rust:<tool>:<edition>")
+    async fn modal_submit(&self, ctx: &Context, modal: &ModalInteraction) -> serenity::Result<()> {
+        let (_, rest) = super::split_custom_id(&modal.data.custom_id);
+        let (tool, edition) = rest.split_once(':').unwrap_or((rest, DEFAULT_EDITION));
+        let Some(tool) = Tool::from_name(tool) else {
+            return Ok(());
+        };
+        let code = modal
+            .data
+            .components
+            .iter()
+            .flat_map(|row| &row.components)
+            .find_map(|c| match c {
+                ActionRowComponent::InputText(input) if input.custom_id == "code" => {
+                    input.value.clone()
+                }
+                _ => None,
+            })
+            .unwrap_or_default();
+
+        modal.defer(&ctx.http).await?;
+        let rendered = execute(playground::client(), tool, &code, edition).await;
+        modal
+            .edit_response(&ctx.http, edit_response(rendered))
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fake_http::FakeHttp;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn formats_through_playground() {
+        let fake = FakeHttp::start(
+            json!([]),
+            |_| json!({"success": true, "code": "fn main() {}\n", "stdout": "", "stderr": ""}),
+        );
+        let pg = Playground::new(playground::Config {
+            base_url: fake.url().to_string(),
+            ..playground::Config::default()
+        });
+        let rendered = execute(&pg, Tool::Fmt, "```rust\nfn main(){}\n```", "2018").await;
+        assert_eq!(rendered, Rendered::text("fmt:\n```rust\nfn main() {}\n```"));
+        let req = fake.requests().pop().unwrap();
+        assert_eq!(req.path, "/format");
+        assert_eq!(req.body["code"], "fn main(){}");
+        assert_eq!(req.body["edition"], "2018");
+    }
+
+    #[test]
+    fn renders_results() {
+        let out = |success, code: &str, stderr: &str| Output {
+            success,
+            code: Some(code.to_string()),
+            stdout: String::new(),
+            stderr: stderr.to_string(),
+        };
+        let checking =
+            "    Checking playground v0.0.1 (/playground)\n    Finished dev [unoptimized]";
+        assert_eq!(
+            render(Tool::Clippy, &out(true, "", checking)).content,
+            "clippy: 警告はありません ✅"
+        );
+        let lint = format!("{checking}\nwarning: unused variable: `x`");
+        assert_eq!(
+            render(Tool::Clippy, &out(true, "", &lint)).content,
+            "clippy:\n```\nwarning: unused variable: `x`\n```"
+        );
+        assert!(
+            render(Tool::Fmt, &out(false, "", "error: expected item"))
+                .content
+                .starts_with("fmt: ⚠️ 失敗しました")
+        );
+
+        // 長いアセンブリは添付
+        let asm = "mov eax, 1\n".repeat(500);
+        let rendered = render(Tool::Asm, &out(true, &asm, ""));
+        assert_eq!(rendered.file, Some(("main.s".to_string(), asm)));
+    }
+}
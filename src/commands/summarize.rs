@@ -0,0 +1,444 @@
+// /summarize: チャンネルの最近のやり取りを LLM で要約する
+//
+// 履歴を取り出して "[時刻] 名前: 本文" の行にし、/gpt と同じ LLM・system プロンプトで要約する。
+// ログが LLM_CONTEXT_TOKENS に収まらないときは、分けて要約し (map)、それをまとめる (reduce)。
+// まとめた要約もまだ長ければ、もう一度分けてまとめる。
+
+use std::future::Future;
+
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use serenity::{
+    async_trait,
+    builder::{CreateCommandOption, GetMessages},
+    model::{
+        application::{CommandInteraction, CommandOptionType},
+        channel::Message,
+        id::{ChannelId, MessageId},
+    },
+    prelude::Context,
+};
+
+use super::args::{self, Args, FromArgs};
+use super::gpt::{self, Reply, prompts};
+use crate::llm::{self, ChatMessage, LlmProvider, estimate_tokens};
+
+pub const NAME: &str = "summarize";
+pub const DESCRIPTION: &str = "チャンネルの最近のやり取りを要約します";
+
+const DEFAULT_COUNT: usize = 50;
+const MAX_COUNT: usize = 500;
+// 1回に取り出せる件数 (Discord の上限)
+const PAGE_SIZE: usize = 100;
+// since で遡れる最大の長さ
+const MAX_SINCE_DAYS: i64 = 7;
+// 要約の指示と答えのために空けておくトークン数
+const RESERVED_TOKENS: usize = 1000;
+const MIN_CHUNK_TOKENS: usize = 500;
+// まとめ直す最大の回数（それでも収まらなければ最初の分だけ使う）
+const MAX_ROUNDS: usize = 3;
+// 時刻は日本時間で書く
+const TZ_OFFSET_HOURS: i32 = 9;
+
+const SUMMARY_INSTRUCTION: &str = "以下は Discord のチャンネルの会話ログです（[時刻] 名前: 本文）。話題ごとに要点を箇条書きで要約してください。";
+const MAP_INSTRUCTION: &str = "以下は Discord のチャンネルの会話ログの一部です（[時刻] 名前: 本文）。あとでまとめるので、誰が何を話したかの要点を箇条書きで書き出してください。";
+const REDUCE_INSTRUCTION: &str = "以下は長い会話ログを順に分けて要約したものです。まとめて、話題ごとに要点を箇条書きで1つの要約にしてください。";
+
+fn usage() -> &'static str {
+    "使い方: !summarize [件数] [--since <30m|2h|1d>]"
+}
+
+/// "30m" / "2h" / "1d" を期間にする
+fn parse_since(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let unit = s.chars().last()?;
+    let n: i64 = s[..s.len() - unit.len_utf8()].trim().parse().ok()?;
+    // 大きすぎる数は Duration にできないので try_* で作る
+    let since = match unit {
+        'm' => Duration::try_minutes(n),
+        'h' => Duration::try_hours(n),
+        'd' => Duration::try_days(n),
+        _ => return None,
+    }?;
+    (n > 0 && since <= Duration::days(MAX_SINCE_DAYS)).then_some(since)
+}
+
+struct SummarizeArgs {
+    count: usize,
+    since: Option<Duration>,
+}
+
+impl FromArgs for SummarizeArgs {
+    fn from_args(args: &Args) -> Result<Self, String> {
+        // プレフィックス版の位置引数は文字列の since に入るので、数だけなら件数として読む
+        let (count, since) = match args.str("since") {
+            Some(s) if args.int("count").is_none() && s.trim().parse::<i64>().is_ok() => {
+                (s.trim().parse::<i64>().ok(), None)
+            }
+            since => (args.int("count"), since),
+        };
+        let since = match since {
+            Some(s) => Some(parse_since(s).ok_or_else(|| {
+                format!("since は 30m / 2h / 1d のように書いてください（最大 {MAX_SINCE_DAYS} 日）")
+            })?),
+            None => None,
+        };
+        // since だけなら、その間を上限まで
+        let default = if since.is_some() {
+            MAX_COUNT
+        } else {
+            DEFAULT_COUNT
+        };
+        let count = count.map_or(default, |n| n.clamp(1, MAX_COUNT as i64) as usize);
+        Ok(Self { count, since })
+    }
+}
+
+/// `before` より前のメッセージを新しいほうから `count` 件（`since` より前は取らない）。古い順で返す
+async fn fetch_history(
+    ctx: &Context,
+    channel: ChannelId,
+    mut before: Option<MessageId>,
+    count: usize,
+    since: Option<DateTime<Utc>>,
+) -> serenity::Result<Vec<Message>> {
+    let mut messages = Vec::new();
+    while messages.len() < count {
+        let limit = (count - messages.len()).min(PAGE_SIZE);
+        let mut get = GetMessages::new().limit(limit as u8);
+        if let Some(before) = before {
+            get = get.before(before);
+        }
+        let page = channel.messages(&ctx.http, get).await?;
+        let last_page = page.len() < limit;
+        before = page.last().map(|m| m.id);
+        for msg in page {
+            if since.is_some_and(|since| *msg.timestamp < since) {
+                messages.reverse();
+                return Ok(messages);
+            }
+            messages.push(msg);
+        }
+        if last_page {
+            break;
+        }
+    }
+    messages.reverse();
+    Ok(messages)
+}
+
+/// ログの1行 "[MM/DD HH:MM] 名前: 本文"
+fn format_line(time: DateTime<Utc>, author: &str, content: &str, files: &[&str]) -> String {
+    let offset = FixedOffset::east_opt(TZ_OFFSET_HOURS * 60 * 60).expect("valid offset");
+    let mut line = format!(
+        "[{}] {author}: {}",
+        time.with_timezone(&offset).format("%m/%d %H:%M"),
+        content.trim().replace('\n', " / ")
+    );
+    for file in files {
+        line.push_str(&format!(" [添付: {file}]"));
+    }
+    line
+}
+
+/// 本文も添付もないメッセージ（参加通知など）は除く
+fn log_line(msg: &Message) -> Option<String> {
+    let files: Vec<&str> = msg
+        .attachments
+        .iter()
+        .map(|a| a.filename.as_str())
+        .collect();
+    if msg.content.trim().is_empty() && files.is_empty() {
+        return None;
+    }
+    Some(format_line(
+        *msg.timestamp,
+        msg.author.display_name(),
+        &msg.content,
+        &files,
+    ))
+}
+
+/// 行を `budget` トークンずつにまとめる。1行で収まらないものは切り詰める
+fn chunk(lines: &[String], budget: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current: Vec<String> = Vec::new();
+    let mut used = 0;
+    for line in lines {
+        let line = if estimate_tokens(line) > budget {
+            line.chars().take(budget).collect::<String>() + "…"
+        } else {
+            line.clone()
+        };
+        let cost = estimate_tokens(&line);
+        if used + cost > budget && !current.is_empty() {
+            chunks.push(current.join("\n"));
+            current.clear();
+            used = 0;
+        }
+        used += cost;
+        current.push(line);
+    }
+    if !current.is_empty() {
+        chunks.push(current.join("\n"));
+    }
+    chunks
+}
+
+/// 要約を頼む最後の会話。ログが `budget` に収まらなければ、分けて要約してからまとめる会話にする。
+/// `progress` には分けて要約するたびに (何番目, いくつ中) を渡す
+async fn prepare<F: Future<Output = ()>>(
+    llm: &dyn LlmProvider,
+    system: &str,
+    lines: Vec<String>,
+    budget: usize,
+    mut progress: impl FnMut(usize, usize) -> F,
+) -> Result<Vec<ChatMessage>, String> {
+    let mut parts = lines;
+    let mut instruction = SUMMARY_INSTRUCTION;
+    for round in 1.. {
+        let chunks = chunk(&parts, budget);
+        if chunks.len() <= 1 || round > MAX_ROUNDS {
+            let body = chunks.into_iter().next().unwrap_or_default();
+            return Ok(vec![
+                ChatMessage::system(system),
+                ChatMessage::user(format!("{instruction}\n\n{body}")),
+            ]);
+        }
+        let mut summaries = Vec::new();
+        for (i, part) in chunks.iter().enumerate() {
+            progress(i + 1, chunks.len()).await;
+            let messages = [
+                ChatMessage::system(system),
+                ChatMessage::user(format!(
+                    "{MAP_INSTRUCTION} ({}/{})\n\n{part}",
+                    i + 1,
+                    chunks.len()
+                )),
+            ];
+            let summary = llm
+                .chat(&messages)
+                .await
+                .map_err(|e| format!("{} {e}", llm.name()))?;
+            summaries.push(summary.trim().to_string());
+        }
+        parts = summaries;
+        instruction = REDUCE_INSTRUCTION;
+    }
+    unreachable!()
+}
+
+/// 取り出したメッセージを要約して `reply` に表示する
+async fn summarize(
+    ctx: &Context,
+    reply: Reply<'_>,
+    system: &str,
+    messages: &[Message],
+) -> serenity::Result<()> {
+    let lines: Vec<String> = messages.iter().filter_map(log_line).collect();
+    if lines.is_empty() {
+        reply
+            .edit(
+                ctx,
+                "要約するメッセージがありません".to_string(),
+                None,
+                Vec::new(),
+            )
+            .await?;
+        return Ok(());
+    }
+    let budget = llm::config()
+        .context_tokens
+        .saturating_sub(estimate_tokens(system) + RESERVED_TOKENS)
+        .max(MIN_CHUNK_TOKENS);
+    let count = lines.len();
+    let prepared = prepare(llm::client(), system, lines, budget, |i, n| async move {
+        // 途中経過の表示に失敗しても続ける
+        let _ = reply
+            .edit(
+                ctx,
+                format!("💭 {count} 件を分けて要約しています… ({i}/{n})"),
+                None,
+                Vec::new(),
+            )
+            .await;
+    })
+    .await;
+    match prepared {
+        Ok(prompt) => {
+            reply.stream(ctx, &prompt, Vec::new()).await?;
+        }
+        Err(e) => {
+            reply
+                .edit(ctx, format!("エラー: {e}"), None, Vec::new())
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+// Prefix: !summarize [件数] [--since <30m|2h|1d>]
+pub async fn run(ctx: &Context, msg: &Message) -> serenity::Result<()> {
+    let SummarizeArgs { count, since } = match args::from_message::<SummarizeArgs>(&Summarize, msg)
+    {
+        Ok(a) => a,
+        Err(e) => {
+            msg.channel_id
+                .say(&ctx.http, format!("{e}\n{}", usage()))
+                .await?;
+            return Ok(());
+        }
+    };
+    let placeholder = msg.channel_id.say(&ctx.http, gpt::THINKING).await?;
+    let reply = Reply::Message(msg.channel_id, placeholder.id);
+    // コマンドのメッセージより前を要約する
+    let since = since.map(|d| Utc::now() - d);
+    let messages = match fetch_history(ctx, msg.channel_id, Some(msg.id), count, since).await {
+        Ok(messages) => messages,
+        Err(e) => {
+            let content = format!("履歴を取り出せませんでした: {e}");
+            return reply.edit(ctx, content, None, Vec::new()).await.map(drop);
+        }
+    };
+    let system = prompts::resolve(ctx, msg.guild_id, msg.channel_id).await;
+    summarize(ctx, reply, &system, &messages).await
+}
+
+// Slash: /summarize count:<件数?> since:<期間?>
+pub async fn slash_execute(ctx: &Context, command: &CommandInteraction) -> serenity::Result<()> {
+    let SummarizeArgs { count, since } = match args::from_interaction::<SummarizeArgs>(command) {
+        Ok(a) => a,
+        Err(e) => return super::respond(ctx, command, e).await,
+    };
+    command.defer(&ctx.http).await?;
+    let reply = Reply::Interaction(command);
+    let since = since.map(|d| Utc::now() - d);
+    let messages = match fetch_history(ctx, command.channel_id, None, count, since).await {
+        Ok(messages) => messages,
+        Err(e) => {
+            let content = format!("履歴を取り出せませんでした: {e}");
+            return reply.edit(ctx, content, None, Vec::new()).await.map(drop);
+        }
+    };
+    let system = prompts::resolve(ctx, command.guild_id, command.channel_id).await;
+    summarize(ctx, reply, &system, &messages).await
+}
+
+pub struct Summarize;
+
+#[async_trait]
+impl super::Command for Summarize {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn description(&self) -> &'static str {
+        DESCRIPTION
+    }
+
+    fn options(&self) -> Vec<CreateCommandOption> {
+        vec![
+            CreateCommandOption::new(
+                CommandOptionType::Integer,
+                "count",
+                "要約するメッセージの件数 (既定: 50)",
+            )
+            .min_int_value(1)
+            .max_int_value(MAX_COUNT as u64),
+            CreateCommandOption::new(
+                CommandOptionType::String,
+                "since",
+                "この期間のメッセージを要約 (30m / 2h / 1d)",
+            ),
+        ]
+    }
+
+    async fn run(&self, ctx: &Context, msg: &Message) -> serenity::Result<()> {
+        run(ctx, msg).await
+    }
+
+    async fn slash_execute(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> serenity::Result<()> {
+        slash_execute(ctx, command).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::Command;
+    use crate::llm::mock::Mock;
+    use chrono::TimeZone;
+
+    #[test]
+    fn parses_args_and_formats_lines() {
+        assert_eq!(parse_since("30m"), Some(Duration::minutes(30)));
+        assert_eq!(parse_since("2h"), Some(Duration::hours(2)));
+        assert_eq!(parse_since("8d"), None);
+        assert_eq!(parse_since("99999999999999d"), None);
+        assert_eq!(parse_since("9223372036854775807m"), None);
+        assert_eq!(parse_since("h"), None);
+
+        let args = args::Args::parse(
+            "--count 120 --since 1d",
+            &args::schema(&Summarize.options()),
+        )
+        .and_then(|a| SummarizeArgs::from_args(&a))
+        .unwrap();
+        assert_eq!((args.count, args.since), (120, Some(Duration::days(1))));
+        let args = args::Args::parse("120", &args::schema(&Summarize.options()))
+            .and_then(|a| SummarizeArgs::from_args(&a))
+            .unwrap();
+        assert_eq!((args.count, args.since), (120, None));
+        // 大きすぎる期間はパニックせずに使い方を返す
+        let err = args::Args::parse(
+            "--since 99999999999999d",
+            &args::schema(&Summarize.options()),
+        )
+        .and_then(|a| SummarizeArgs::from_args(&a))
+        .err()
+        .unwrap();
+        assert!(err.starts_with("since は"), "{err}");
+
+        let time = Utc.with_ymd_and_hms(2026, 10, 17, 3, 4, 0).unwrap();
+        assert_eq!(
+            format_line(time, "alice", "hello\nworld", &["a.png"]),
+            "[10/17 12:04] alice: hello / world [添付: a.png]"
+        );
+    }
+
+    #[tokio::test]
+    async fn map_reduces_long_logs() {
+        let lines: Vec<String> = (0..30)
+            .map(|i| format!("[00:00] u{i}: {}", "x".repeat(80)))
+            .collect();
+
+        // 収まればそのまま1回で頼む
+        let llm = Mock::echo();
+        let prompt = prepare(&llm, "sys", lines[..2].to_vec(), 10_000, |_, _| async {})
+            .await
+            .unwrap();
+        assert!(llm.calls().is_empty());
+        assert!(prompt[1].content.starts_with(SUMMARY_INSTRUCTION));
+        assert!(prompt[1].content.ends_with(&lines[1]));
+
+        // 長ければ分けて要約してからまとめる
+        let llm = Mock::with(|_| "要約".to_string());
+        let mut progress = Vec::new();
+        let prompt = prepare(&llm, "sys", lines, 100, |i, n| {
+            progress.push((i, n));
+            async {}
+        })
+        .await
+        .unwrap();
+        let n = llm.calls().len();
+        assert!(n > 1);
+        assert_eq!(progress.last(), Some(&(n, n)));
+        assert!(llm.calls()[0][1].content.starts_with(MAP_INSTRUCTION));
+        assert_eq!(prompt[0].content, "sys");
+        assert!(prompt[1].content.starts_with(REDUCE_INSTRUCTION));
+        assert!(prompt[1].content.ends_with(&vec!["要約"; n].join("\n")));
+    }
+}
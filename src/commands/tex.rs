@@ -1,12 +1,15 @@
 use serenity::{
-    builder::{CreateCommand, CreateCommandOption},
+    async_trait,
+    builder::CreateCommandOption,
     model::{
-        application::{CommandOptionType, ResolvedOption, ResolvedValue},
+        application::{CommandInteraction, CommandOptionType},
         channel::Message,
     },
     prelude::Context,
 };
 
+use super::args::{self, Args, FromArgs};
+
 // スラッシュコマンド情報
 pub const NAME: &str = "tex";
 pub const DESCRIPTION: &str = "LaTeX をレンダリングして画像URLを返します";
@@ -19,59 +22,76 @@ fn build_image_url(latex: &str) -> String {
     format!("https://latex.codecogs.com/png.latex?{}", encoded)
 }
 
+struct TexArgs {
+    formula: String,
+}
+
+impl FromArgs for TexArgs {
+    fn from_args(args: &Args) -> Result<Self, String> {
+        Ok(Self {
+            formula: args.required_str("formula")?,
+        })
+    }
+}
+
 // プレフィックスコマンド: !tex <式>
 pub async fn run(ctx: &Context, msg: &Message) -> serenity::Result<()> {
-    let content = msg.content.trim();
-
     // 期待フォーマット: "!tex <latex>"
-    let latex = content
-        .strip_prefix(super::PREFIX)
-        .map(str::trim_start)
-        .and_then(|s| s.strip_prefix(NAME))
-        .map(str::trim_start)
-        .unwrap_or("");
-
-    if latex.is_empty() {
+    let Ok(TexArgs { formula }) = args::from_message::<TexArgs>(&Tex, msg) else {
         let usage = r"使い方: !tex <LaTeX 式>
 例: !tex \frac{-b \pm \sqrt{b^2 - 4ac}}{2a}";
         msg.channel_id.say(&ctx.http, usage).await?;
         return Ok(());
-    }
+    };
 
-    let url = build_image_url(latex);
+    let url = build_image_url(&formula);
     msg.channel_id.say(&ctx.http, url).await?;
     Ok(())
 }
 
 // スラッシュコマンドの実行: /tex formula:<式>
-pub fn slash_run(options: &[ResolvedOption]) -> String {
-    let formula: Option<String> = options.iter().find_map(|opt| {
-        if opt.name == "formula" {
-            match &opt.value {
-                ResolvedValue::String(s) => Some(s.to_string()),
-                _ => None,
-            }
-        } else {
-            None
+pub fn slash_run(command: &CommandInteraction) -> String {
+    match args::from_interaction::<TexArgs>(command) {
+        Ok(TexArgs { formula }) => build_image_url(&formula),
+        Err(_) => {
+            "式が指定されていません。/tex で formula: <LaTeX 式> を入力してください。".to_string()
         }
-    });
-
-    match formula {
-        Some(s) if !s.trim().is_empty() => build_image_url(s.as_str()),
-        _ => "式が指定されていません。/tex で formula: <LaTeX 式> を入力してください。".to_string(),
     }
 }
 
-// スラッシュコマンドのメタデータ登録
-pub fn slash_register() -> CreateCommand {
-    CreateCommand::new(NAME)
-        .description(DESCRIPTION)
-        .add_option(
+pub struct Tex;
+
+#[async_trait]
+impl super::Command for Tex {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn description(&self) -> &'static str {
+        DESCRIPTION
+    }
+
+    // スラッシュコマンドのオプション定義
+    fn options(&self) -> Vec<CreateCommandOption> {
+        vec![
             CreateCommandOption::new(
                 CommandOptionType::String,
                 "formula",
                 "レンダリングしたい LaTeX 式",
             )
             .required(true),
-        )
+        ]
+    }
+
+    async fn run(&self, ctx: &Context, msg: &Message) -> serenity::Result<()> {
+        run(ctx, msg).await
+    }
+
+    async fn slash_execute(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+    ) -> serenity::Result<()> {
+        super::respond(ctx, command, slash_run(command)).await
+    }
 }
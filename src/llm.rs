@@ -0,0 +1,248 @@
+// /gpt などで使う LLM のプロバイダ
+//
+// LlmProvider を実装したものを設定で選ぶ
+// - openai: OpenAI 互換の Chat Completions API (OpenAI, llama.cpp の server, Ollama など)
+// - tgpt: tgpt コマンド (API を用意できないときの代わり)
+// - mock: 最後の質問をそのまま返す（動作確認・テスト用）
+//
+// 設定は環境変数で行う
+// - LLM_PROVIDER: openai / tgpt / mock (既定: LLM_API_URL か LLM_API_KEY があれば openai、なければ tgpt)
+// - LLM_API_URL: API のベース URL (既定: https://api.openai.com/v1。Ollama なら http://localhost:11434/v1)
+// - LLM_API_KEY: API キー (ローカルのサーバーなら不要)
+// - LLM_MODEL: モデル名 (既定: gpt-4o-mini)
+// - LLM_TIMEOUT_SECS: 応答が途切れてからのタイムアウト秒数 (既定: 60)
+// - TGPT_PROVIDER: tgpt の --provider (既定: sky)
+// - LLM_CONTEXT_TOKENS: /gpt のスレッドで送る履歴のトークン数の上限 (既定: 4000)
+
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use tokio::sync::mpsc::UnboundedSender;
+
+pub mod mock;
+pub mod openai;
+pub mod tgpt;
+
+const DEFAULT_API_URL: &str = "https://api.openai.com/v1";
+const DEFAULT_MODEL: &str = "gpt-4o-mini";
+const DEFAULT_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_TGPT_PROVIDER: &str = "sky";
+const DEFAULT_CONTEXT_TOKENS: usize = 4000;
+// 1メッセージごとに掛かる役割などの分
+const MESSAGE_OVERHEAD: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+/// 会話の1件 (OpenAI の messages と同じ形)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::System,
+            content: content.into(),
+        }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::User,
+            content: content.into(),
+        }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::Assistant,
+            content: content.into(),
+        }
+    }
+}
+
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// エラーメッセージなどに出す名前
+    fn name(&self) -> &'static str;
+
+    /// 会話の続きを返す
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, String>;
+
+    /// 会話の続きを届いた分から `chunks` に送り、最後に全体を返す。
+    /// 少しずつ受け取れないプロバイダは chat の結果を1度に送る
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        chunks: UnboundedSender<String>,
+    ) -> Result<String, String> {
+        let reply = self.chat(messages).await?;
+        let _ = chunks.send(reply.clone());
+        Ok(reply)
+    }
+}
+
+/// 使うプロバイダ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    OpenAi,
+    Tgpt,
+    Mock,
+}
+
+impl Provider {
+    fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "openai" => Some(Self::OpenAi),
+            "tgpt" => Some(Self::Tgpt),
+            "mock" => Some(Self::Mock),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub provider: Provider,
+    pub api_url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+    pub timeout: Duration,
+    pub tgpt_provider: String,
+    pub context_tokens: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            provider: Provider::Tgpt,
+            api_url: DEFAULT_API_URL.to_string(),
+            api_key: None,
+            model: DEFAULT_MODEL.to_string(),
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+            tgpt_provider: DEFAULT_TGPT_PROVIDER.to_string(),
+            context_tokens: DEFAULT_CONTEXT_TOKENS,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// 環境変数の読み方を差し替えられるようにしたもの（テスト用）
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let default = Self::default();
+        let value = |key: &str| {
+            lookup(key)
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+        };
+        let api_url = value("LLM_API_URL").map(|s| s.trim_end_matches('/').to_string());
+        let api_key = value("LLM_API_KEY");
+        // API の設定があれば API を使う
+        let provider = value("LLM_PROVIDER")
+            .and_then(|s| Provider::parse(&s))
+            .unwrap_or(if api_url.is_some() || api_key.is_some() {
+                Provider::OpenAi
+            } else {
+                default.provider
+            });
+        Self {
+            provider,
+            api_url: api_url.unwrap_or(default.api_url),
+            api_key,
+            model: value("LLM_MODEL").unwrap_or(default.model),
+            timeout: value("LLM_TIMEOUT_SECS")
+                .and_then(|s| s.parse::<u64>().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(default.timeout),
+            tgpt_provider: value("TGPT_PROVIDER").unwrap_or(default.tgpt_provider),
+            context_tokens: value("LLM_CONTEXT_TOKENS")
+                .and_then(|s| s.parse::<usize>().ok())
+                .unwrap_or(default.context_tokens),
+        }
+    }
+}
+
+/// 設定のプロバイダを作る
+pub fn from_config(config: Config) -> Box<dyn LlmProvider> {
+    match config.provider {
+        Provider::OpenAi => Box::new(openai::OpenAi::new(config)),
+        Provider::Tgpt => Box::new(tgpt::Tgpt::new(config)),
+        Provider::Mock => Box::new(mock::Mock::echo()),
+    }
+}
+
+/// 1メッセージのだいたいのトークン数。ASCII は4文字で1つ、それ以外（日本語など）は1文字で1つと数える
+pub fn estimate_tokens(s: &str) -> usize {
+    let (ascii, other): (usize, usize) =
+        s.chars().fold(
+            (0, 0),
+            |(a, o), c| {
+                if c.is_ascii() { (a + 1, o) } else { (a, o + 1) }
+            },
+        );
+    ascii.div_ceil(4) + other + MESSAGE_OVERHEAD
+}
+
+static CONFIG: Lazy<Config> = Lazy::new(Config::from_env);
+static CLIENT: Lazy<Box<dyn LlmProvider>> = Lazy::new(|| from_config(config().clone()));
+
+/// 環境変数の設定
+pub fn config() -> &'static Config {
+    &CONFIG
+}
+
+/// 環境変数の設定で作った共有のプロバイダ
+pub fn client() -> &'static dyn LlmProvider {
+    CLIENT.as_ref()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_from_lookup() {
+        let c = Config::from_lookup(|_| None);
+        assert_eq!(c.provider, Provider::Tgpt);
+        assert_eq!(c.api_url, DEFAULT_API_URL);
+        assert_eq!(c.context_tokens, DEFAULT_CONTEXT_TOKENS);
+
+        // URL があれば OpenAI 互換 API
+        let c = Config::from_lookup(|key| match key {
+            "LLM_API_URL" => Some("http://localhost:11434/v1/".to_string()),
+            "LLM_MODEL" => Some("llama3".to_string()),
+            _ => None,
+        });
+        assert_eq!(c.provider, Provider::OpenAi);
+        assert_eq!(c.api_url, "http://localhost:11434/v1");
+        assert_eq!(c.model, "llama3");
+
+        let c = Config::from_lookup(|key| match key {
+            "LLM_PROVIDER" => Some("Mock".to_string()),
+            "LLM_API_KEY" => Some("sk-x".to_string()),
+            _ => None,
+        });
+        assert_eq!(c.provider, Provider::Mock);
+        assert_eq!(from_config(c).name(), "mock");
+    }
+
+    #[test]
+    fn estimates_tokens() {
+        assert_eq!(estimate_tokens("abcdefgh"), 2 + MESSAGE_OVERHEAD);
+        assert_eq!(estimate_tokens("日本語"), 3 + MESSAGE_OVERHEAD);
+    }
+}
@@ -0,0 +1,71 @@
+// 決まった応答を返すプロバイダ (LLM_PROVIDER=mock)
+//
+// 外部に繋がずに /gpt の流れを確かめるためのもの。既定では最後の質問を "mock: <質問>" として返す。
+// 受け取った会話は記録するので、テストでは何を送ったかを確かめられる。
+// chat_stream では応答を空白ごとに区切って送る。
+
+use std::sync::Mutex;
+
+use serenity::async_trait;
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::{ChatMessage, LlmProvider, Role};
+
+type Reply = dyn Fn(&[ChatMessage]) -> String + Send + Sync;
+
+pub struct Mock {
+    reply: Box<Reply>,
+    calls: Mutex<Vec<Vec<ChatMessage>>>,
+}
+
+impl Mock {
+    /// 最後の質問をそのまま返す
+    pub fn echo() -> Self {
+        Self::with(|messages| {
+            let last = messages
+                .iter()
+                .rev()
+                .find(|m| m.role == Role::User)
+                .map_or("", |m| m.content.as_str());
+            format!("mock: {last}")
+        })
+    }
+
+    /// 会話から応答を作る関数を指定する
+    pub fn with(reply: impl Fn(&[ChatMessage]) -> String + Send + Sync + 'static) -> Self {
+        Self {
+            reply: Box::new(reply),
+            calls: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// これまでに受け取った会話
+    #[cfg(test)]
+    pub fn calls(&self) -> Vec<Vec<ChatMessage>> {
+        self.calls.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for Mock {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, String> {
+        self.calls.lock().unwrap().push(messages.to_vec());
+        Ok((self.reply)(messages))
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        chunks: UnboundedSender<String>,
+    ) -> Result<String, String> {
+        let reply = self.chat(messages).await?;
+        for chunk in reply.split_inclusive(' ') {
+            let _ = chunks.send(chunk.to_string());
+        }
+        Ok(reply)
+    }
+}
@@ -0,0 +1,265 @@
+// OpenAI 互換の Chat Completions API (POST {LLM_API_URL}/chat/completions)
+//
+// OpenAI のほか、llama.cpp の server や Ollama (/v1) など同じ形の API ならそのまま使える。
+// chat_stream では "stream": true で頼み、text/event-stream の "data: {...}" 行から差分を読む。
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+use serenity::async_trait;
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::{ChatMessage, Config, LlmProvider};
+
+#[derive(Debug, Deserialize)]
+struct Completion {
+    choices: Vec<Choice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: Reply,
+}
+
+#[derive(Debug, Deserialize)]
+struct Reply {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+/// ストリーミングの1件 ({"choices": [{"delta": {"content": ...}}]})
+#[derive(Debug, Deserialize)]
+struct Chunk {
+    choices: Vec<DeltaChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeltaChoice {
+    delta: Reply,
+}
+
+/// ストリーミング応答の1行
+#[derive(Debug, PartialEq)]
+enum Event {
+    Delta(String),
+    Done,
+    Error(String),
+    Skip,
+}
+
+impl Event {
+    fn parse(line: &str) -> Self {
+        let Some(data) = line.trim_end().strip_prefix("data:") else {
+            return Self::Skip;
+        };
+        let data = data.trim();
+        if data == "[DONE]" {
+            return Self::Done;
+        }
+        let Ok(value) = serde_json::from_str::<Value>(data) else {
+            return Self::Skip;
+        };
+        if value.get("error").is_some() {
+            return Self::Error(error_message(data));
+        }
+        serde_json::from_value::<Chunk>(value)
+            .ok()
+            .and_then(|c| c.choices.into_iter().next())
+            .and_then(|c| c.delta.content)
+            .filter(|s| !s.is_empty())
+            .map_or(Self::Skip, Self::Delta)
+    }
+}
+
+pub struct OpenAi {
+    config: Config,
+    client: reqwest::Client,
+}
+
+impl OpenAi {
+    pub fn new(config: Config) -> Self {
+        // ストリーミングで長く続く応答を切らないよう、途切れた時間で打ち切る
+        let client = reqwest::Client::builder()
+            .read_timeout(config.timeout)
+            .build()
+            .unwrap_or_default();
+        Self { config, client }
+    }
+
+    fn request_error(&self, e: reqwest::Error) -> String {
+        if e.is_timeout() {
+            format!("タイムアウトしました ({}秒)", self.config.timeout.as_secs())
+        } else {
+            format!("API に接続できませんでした: {e}")
+        }
+    }
+
+    /// POST {api_url}/chat/completions して、成功した応答を返す
+    async fn send(
+        &self,
+        messages: &[ChatMessage],
+        stream: bool,
+    ) -> Result<reqwest::Response, String> {
+        let mut req = self
+            .client
+            .post(format!("{}/chat/completions", self.config.api_url))
+            .json(&json!({
+                "model": self.config.model,
+                "messages": messages,
+                "stream": stream,
+            }));
+        if let Some(key) = &self.config.api_key {
+            req = req.bearer_auth(key);
+        }
+        let resp = req.send().await.map_err(|e| self.request_error(e))?;
+        let status = resp.status();
+        if !status.is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            return Err(format!("API エラー ({status}): {}", error_message(&body)));
+        }
+        Ok(resp)
+    }
+}
+
+/// エラー応答の {"error": {"message": ...}} を取り出す
+fn error_message(body: &str) -> String {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|v| v["error"]["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| body.chars().take(200).collect())
+}
+
+#[async_trait]
+impl LlmProvider for OpenAi {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, String> {
+        let completion: Completion = self
+            .send(messages, false)
+            .await?
+            .json()
+            .await
+            .map_err(|e| format!("応答を読めませんでした: {e}"))?;
+        completion
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|c| c.message.content)
+            .ok_or_else(|| "応答が空でした".to_string())
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        chunks: UnboundedSender<String>,
+    ) -> Result<String, String> {
+        let mut resp = self.send(messages, true).await?;
+        let mut buf = Vec::new();
+        let mut reply = String::new();
+        'read: while let Some(bytes) = resp.chunk().await.map_err(|e| self.request_error(e))? {
+            buf.extend_from_slice(&bytes);
+            // 行が揃った分だけ読む（文字の途中で切れていることがある）
+            while let Some(end) = buf.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buf.drain(..=end).collect();
+                match Event::parse(&String::from_utf8_lossy(&line)) {
+                    Event::Delta(text) => {
+                        reply.push_str(&text);
+                        let _ = chunks.send(text);
+                    }
+                    Event::Done => break 'read,
+                    Event::Error(e) => return Err(format!("API エラー: {e}")),
+                    Event::Skip => {}
+                }
+            }
+        }
+        if reply.is_empty() {
+            return Err("応答が空でした".to_string());
+        }
+        Ok(reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fake_http::FakeHttp;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn chats_with_compatible_api() {
+        let fake = FakeHttp::start(json!([]), |req| {
+            let last = req["messages"].as_array().unwrap().last().unwrap()["content"].clone();
+            json!({"choices": [{"message": {"role": "assistant", "content": format!("re: {}", last.as_str().unwrap())}}]})
+        });
+        let llm = OpenAi::new(Config {
+            api_url: fake.url().to_string(),
+            api_key: Some("sk-test".to_string()),
+            model: "llama3".to_string(),
+            timeout: Duration::from_secs(5),
+            ..Config::default()
+        });
+        let reply = llm
+            .chat(&[ChatMessage::system("brief"), ChatMessage::user("hello")])
+            .await
+            .unwrap();
+        assert_eq!(reply, "re: hello");
+
+        let req = fake.requests().pop().unwrap();
+        assert_eq!(req.path, "/chat/completions");
+        assert_eq!(req.auth.as_deref(), Some("Bearer sk-test"));
+        assert_eq!(req.body["model"], "llama3");
+        assert_eq!(
+            req.body["messages"],
+            json!([{"role": "system", "content": "brief"}, {"role": "user", "content": "hello"}])
+        );
+    }
+
+    #[tokio::test]
+    async fn streams_deltas() {
+        let fake = FakeHttp::start(json!([]), |_| {
+            let events = ["こん", "にちは", "!"].map(|s| {
+                format!(
+                    "data: {}\n\n",
+                    json!({"choices": [{"delta": {"content": s}}]})
+                )
+            });
+            json!(format!(
+                ": keep-alive\n\n{}data: [DONE]\n\n",
+                events.concat()
+            ))
+        });
+        let llm = OpenAi::new(Config {
+            api_url: fake.url().to_string(),
+            timeout: Duration::from_secs(5),
+            ..Config::default()
+        });
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let reply = llm
+            .chat_stream(&[ChatMessage::user("hello")], tx)
+            .await
+            .unwrap();
+        assert_eq!(reply, "こんにちは!");
+        let mut chunks = Vec::new();
+        while let Some(chunk) = rx.recv().await {
+            chunks.push(chunk);
+        }
+        assert_eq!(chunks, ["こん", "にちは", "!"]);
+        assert_eq!(fake.requests()[0].body["stream"], true);
+
+        assert_eq!(
+            Event::parse(r#"data: {"error": {"message": "overloaded"}}"#),
+            Event::Error("overloaded".to_string())
+        );
+        assert_eq!(Event::parse("event: ping"), Event::Skip);
+    }
+
+    #[test]
+    fn reads_error_body() {
+        assert_eq!(
+            error_message(r#"{"error": {"message": "model not found"}}"#),
+            "model not found"
+        );
+        assert_eq!(error_message("Bad Gateway"), "Bad Gateway");
+    }
+}
@@ -0,0 +1,218 @@
+// tgpt コマンドを使うプロバイダ (API を用意できないときの代わり)
+//
+// tgpt は1問1答なので、system と以前のやり取りは --preprompt にまとめ、最後の質問を渡す。
+// chat_stream では --whole を付けずに起動し、標準出力を届いた分から読む。
+// 標準エラーは詰まらないよう別のタスクで読み続け、失敗したときのために最後の部分だけ残す。
+
+use std::{process::Stdio, time::Duration};
+
+use serenity::async_trait;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    process::{Child, Command},
+    sync::mpsc::UnboundedSender,
+};
+
+use super::{ChatMessage, Config, LlmProvider, Role};
+
+// 失敗したときに表示する標準エラーの最大バイト数（最後の部分を残す）
+const STDERR_TAIL: usize = 4096;
+
+pub struct Tgpt {
+    provider: String,
+    timeout: Duration,
+}
+
+impl Tgpt {
+    pub fn new(config: Config) -> Self {
+        Self {
+            provider: config.tgpt_provider,
+            timeout: config.timeout,
+        }
+    }
+
+    /// tgpt --quiet [--whole] --provider <provider> --preprompt <preprompt> <質問>
+    fn args(&self, messages: &[ChatMessage], whole: bool) -> Vec<String> {
+        let (query, history) = match messages.split_last() {
+            Some((last, history)) if last.role == Role::User => (last.content.as_str(), history),
+            _ => ("", messages),
+        };
+        let mut preprompt: Vec<String> = history
+            .iter()
+            .filter(|m| m.role == Role::System)
+            .map(|m| m.content.clone())
+            .collect();
+        let turns: Vec<String> = history
+            .iter()
+            .filter_map(|m| match m.role {
+                Role::User => Some(format!("User: {}", m.content)),
+                Role::Assistant => Some(format!("Assistant: {}", m.content)),
+                Role::System => None,
+            })
+            .collect();
+        if !turns.is_empty() {
+            preprompt.push(format!("Conversation so far:\n{}", turns.join("\n")));
+        }
+        let preprompt = preprompt.join("\n");
+        let mut args = vec!["--quiet"];
+        if whole {
+            args.push("--whole");
+        }
+        args.extend([
+            "--provider",
+            &self.provider,
+            "--preprompt",
+            &preprompt,
+            query,
+        ]);
+        args.into_iter().map(String::from).collect()
+    }
+
+    fn spawn(&self, args: Vec<String>) -> Result<Child, String> {
+        Command::new("tgpt")
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| {
+                format!(
+                    "コマンド起動エラー: {e}\n' tgpt ' がインストールされているか確認してください。"
+                )
+            })
+    }
+
+    fn timeout_error(&self) -> String {
+        format!("タイムアウトしました ({}秒)", self.timeout.as_secs())
+    }
+}
+
+/// パイプを最後まで読み、最後の STDERR_TAIL バイトだけ返す
+async fn tail(mut pipe: impl AsyncRead + Unpin) -> Vec<u8> {
+    let mut kept = Vec::new();
+    let mut buf = [0; 4096];
+    while let Ok(n) = pipe.read(&mut buf).await
+        && n > 0
+    {
+        kept.extend_from_slice(&buf[..n]);
+        if kept.len() > STDERR_TAIL {
+            kept.drain(..kept.len() - STDERR_TAIL);
+        }
+    }
+    kept
+}
+
+fn failure(stderr: &[u8]) -> String {
+    let stderr = String::from_utf8_lossy(stderr);
+    if stderr.trim().is_empty() {
+        "tgpt 実行に失敗しました (詳細不明)".to_string()
+    } else {
+        format!("tgpt 実行に失敗: {}", stderr.trim())
+    }
+}
+
+#[async_trait]
+impl LlmProvider for Tgpt {
+    fn name(&self) -> &'static str {
+        "tgpt"
+    }
+
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, String> {
+        let child = self.spawn(self.args(messages, true))?;
+
+        // 止まったままにならないようにタイムアウトを掛ける
+        let output = match tokio::time::timeout(self.timeout, child.wait_with_output()).await {
+            Err(_) => return Err(self.timeout_error()),
+            Ok(output) => output.map_err(|e| format!("コマンド実行エラー: {e}"))?,
+        };
+        if !output.status.success() {
+            return Err(failure(&output.stderr));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        chunks: UnboundedSender<String>,
+    ) -> Result<String, String> {
+        let mut child = self.spawn(self.args(messages, false))?;
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = tokio::spawn(tail(child.stderr.take().expect("stderr is piped")));
+
+        // 出力が途切れたまま止まらないよう、読むたびにタイムアウトを掛ける
+        let mut bytes = Vec::new();
+        let mut sent = 0;
+        let mut buf = [0; 4096];
+        loop {
+            let n = match tokio::time::timeout(self.timeout, stdout.read(&mut buf)).await {
+                Err(_) => return Err(self.timeout_error()),
+                Ok(n) => n.map_err(|e| format!("コマンド実行エラー: {e}"))?,
+            };
+            if n == 0 {
+                break;
+            }
+            bytes.extend_from_slice(&buf[..n]);
+            // 文字の途中までは送らない
+            let valid = match std::str::from_utf8(&bytes[sent..]) {
+                Ok(s) => s.len(),
+                Err(e) => e.valid_up_to(),
+            };
+            if valid > 0 {
+                let text = String::from_utf8_lossy(&bytes[sent..sent + valid]).into_owned();
+                let _ = chunks.send(text);
+                sent += valid;
+            }
+        }
+
+        let status = match tokio::time::timeout(self.timeout, child.wait()).await {
+            Err(_) => return Err(self.timeout_error()),
+            Ok(status) => status.map_err(|e| format!("コマンド実行エラー: {e}"))?,
+        };
+        if !status.success() {
+            return Err(failure(&stderr.await.unwrap_or_default()));
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_history_into_preprompt() {
+        let tgpt = Tgpt::new(Config::default());
+        let messages = [
+            ChatMessage::system("respond in brief"),
+            ChatMessage::user("1+1?"),
+            ChatMessage::assistant("2"),
+            ChatMessage::user("times 3?"),
+        ];
+        let args = tgpt.args(&messages, true);
+        assert_eq!(
+            args,
+            [
+                "--quiet",
+                "--whole",
+                "--provider",
+                "sky",
+                "--preprompt",
+                "respond in brief\nConversation so far:\nUser: 1+1?\nAssistant: 2",
+                "times 3?",
+            ]
+        );
+        // 少しずつ読むときは --whole を付けない
+        assert_eq!(tgpt.args(&messages, false)[..2], ["--quiet", "--provider"]);
+    }
+
+    #[tokio::test]
+    async fn keeps_stderr_tail() {
+        let mut stderr = vec![b'a'; STDERR_TAIL * 3];
+        stderr.extend_from_slice(b"error: boom");
+        let kept = tail(stderr.as_slice()).await;
+        assert_eq!(kept.len(), STDERR_TAIL);
+        assert!(kept.ends_with(b"error: boom"));
+        assert_eq!(tail(&b"short"[..]).await, b"short");
+    }
+}
@@ -5,106 +5,91 @@ use serenity::async_trait;
 use serenity::model::{
     application::{Command, Interaction},
     channel::Message,
+    event::MessageUpdateEvent,
     gateway::Ready,
     id::GuildId,
 };
 use serenity::prelude::*;
 
+mod cargo_runner;
 mod commands;
+mod llm;
+mod piston;
+mod playground;
+mod runner;
+#[cfg(test)]
+mod test_support;
 
 struct Handler;
 
+/// "!<command> ..." をコマンドのハンドラにディスパッチする
+async fn run_prefix_command(ctx: &Context, msg: &Message) {
+    let content = msg.content.trim();
+
+    // シンプルなプレフィックス解析
+    if !content.starts_with(commands::PREFIX) {
+        return;
+    }
+
+    // プレフィックスを外し、コマンドと引数に分割
+    let without_prefix = content[commands::PREFIX.len()..].trim();
+    if without_prefix.is_empty() {
+        return;
+    }
+    let mut parts = without_prefix.split_whitespace();
+    let command = parts.next().unwrap_or("");
+    // let args: Vec<&str> = parts.collect(); // 将来のために引数を使う場合
+
+    // コマンドごとのハンドラにディスパッチ
+    let Some(cmd) = commands::find(command) else {
+        return; // 不明なコマンドは現状スルー
+    };
+    let result = cmd.run(ctx, msg).await;
+
+    if let Err(why) = result {
+        println!("コマンド '{}' の実行中にエラーが発生: {:?}", command, why);
+    }
+}
+
 #[async_trait]
 impl EventHandler for Handler {
-    async fn interaction_create(&self, _ctx: Context, interaction: Interaction) {
-        if let Interaction::Command(command) = interaction {
-            let name = command.data.name.as_str();
-            match name {
-                commands::ping::NAME => {
-                    let content = commands::ping::slash_run(&command.data.options());
-                    if let Err(why) = command
-                        .create_response(
-                            &_ctx.http,
-                            serenity::builder::CreateInteractionResponse::Message(
-                                serenity::builder::CreateInteractionResponseMessage::new()
-                                    .content(content),
-                            ),
-                        )
-                        .await
-                    {
-                        println!("スラッシュコマンドの応答に失敗: {why:?}");
-                    }
-                }
-                commands::help::NAME => {
-                    let content = commands::help::slash_run(&command.data.options());
-                    if let Err(why) = command
-                        .create_response(
-                            &_ctx.http,
-                            serenity::builder::CreateInteractionResponse::Message(
-                                serenity::builder::CreateInteractionResponseMessage::new()
-                                    .content(content),
-                            ),
-                        )
-                        .await
-                    {
-                        println!("スラッシュコマンドの応答に失敗: {why:?}");
-                    }
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        match interaction {
+            Interaction::Command(command) => {
+                let name = command.data.name.as_str();
+                let result = match commands::find(name) {
+                    Some(cmd) => cmd.slash_execute(&ctx, &command).await,
+                    None => commands::respond(&ctx, &command, "未対応のコマンドです").await,
+                };
+                if let Err(why) = result {
+                    println!("/{name} 実行エラー: {why:?}");
                 }
-                commands::tex::NAME => {
-                    let content = commands::tex::slash_run(&command.data.options());
-                    if let Err(why) = command
-                        .create_response(
-                            &_ctx.http,
-                            serenity::builder::CreateInteractionResponse::Message(
-                                serenity::builder::CreateInteractionResponseMessage::new()
-                                    .content(content),
-                            ),
-                        )
-                        .await
-                    {
-                        println!("スラッシュコマンドの応答に失敗: {why:?}");
-                    }
-                }
-                commands::gpt::NAME => {
-                    if let Err(why) = commands::gpt::slash_execute(&_ctx, &command).await {
-                        println!("/gpt 実行エラー: {why:?}");
-                    }
-                }
-                commands::get::NAME => {
-                    if let Err(why) = commands::get::slash_execute(&_ctx, &command).await {
-                        println!("/get 実行エラー: {why:?}");
-                    }
-                }
-                commands::post::NAME => {
-                    if let Err(why) = commands::post::slash_execute(&_ctx, &command).await {
-                        println!("/post 実行エラー: {why:?}");
-                    }
-                }
-                commands::eval::NAME => {
-                    if let Err(why) = commands::eval::slash_execute(&_ctx, &command).await {
-                        println!("/eval 実行エラー: {why:?}");
-                    }
+            }
+            Interaction::Autocomplete(command) => {
+                let name = command.data.name.as_str();
+                if let Some(cmd) = commands::find(name)
+                    && let Err(why) = cmd.autocomplete(&ctx, &command).await
+                {
+                    println!("/{name} 補完エラー: {why:?}");
                 }
-                commands::hukidashi::NAME => {
-                    if let Err(why) = commands::hukidashi::slash_execute(&_ctx, &command).await {
-                        println!("/huki 実行エラー: {why:?}");
-                    }
+            }
+            Interaction::Modal(modal) => {
+                let (name, _) = commands::split_custom_id(&modal.data.custom_id);
+                if let Some(cmd) = commands::find(name)
+                    && let Err(why) = cmd.modal_submit(&ctx, &modal).await
+                {
+                    println!("/{name} モーダル処理エラー: {why:?}");
                 }
-                _ => {
-                    if let Err(why) = command
-                        .create_response(
-                            &_ctx.http,
-                            serenity::builder::CreateInteractionResponse::Message(
-                                serenity::builder::CreateInteractionResponseMessage::new()
-                                    .content("未対応のコマンドです"),
-                            ),
-                        )
-                        .await
-                    {
-                        println!("スラッシュコマンドの応答に失敗: {why:?}");
-                    }
+            }
+            Interaction::Component(component) => {
+                let (name, _) = commands::split_custom_id(&component.data.custom_id);
+                if let Some(cmd) = commands::find(name)
+                    && let Err(why) = cmd.component(&ctx, &component).await
+                {
+                    println!("/{name} ボタン処理エラー: {why:?}");
                 }
             }
+            _ => {}
         }
     }
 
@@ -113,48 +98,71 @@ impl EventHandler for Handler {
         if msg.author.bot {
             return;
         }
-        
+
         let content = msg.content.trim();
-        
+
         // "yay" に "-Syu" を返す
         if content == "yay" {
             let _ = msg.channel_id.say(&ctx.http, "-Syu").await;
             return;
         }
 
-        // シンプルなプレフィックス解析
-        if !content.starts_with(commands::PREFIX) {
+        // /gpt の会話スレッドへの書き込みは、コマンドでなければ会話の続き
+        if !content.starts_with(commands::PREFIX) && commands::gpt::is_conversation(msg.channel_id)
+        {
+            if let Err(why) = commands::gpt::continue_conversation(&ctx, &msg).await {
+                println!("gpt の会話でエラーが発生: {why:?}");
+            }
             return;
         }
 
-        // プレフィックスを外し、コマンドと引数に分割
-        let without_prefix = content[commands::PREFIX.len()..].trim();
-        if without_prefix.is_empty() {
+        run_prefix_command(&ctx, &msg).await;
+    }
+
+    async fn message_update(
+        &self,
+        ctx: Context,
+        _old: Option<Message>,
+        new: Option<Message>,
+        event: MessageUpdateEvent,
+    ) {
+        // 少し前にコマンドへ返信したメッセージの本文が、前回実行したときから変わったら実行し直す
+        // （返信は commands::reply が前回のものを書き換える）
+        let Some(content) = &event.content else {
+            return;
+        };
+        let Some(last) = commands::reply::last_run(event.id) else {
+            return;
+        };
+        // 埋め込みの展開などで本文が変わっていない更新は無視する
+        if *content == last {
+            return;
+        }
+        // 別のコマンドに書き換えられたら前回の返信とは別物なので、書き換えずにそのままにする
+        if commands::command_name(content) != commands::command_name(&last) {
             return;
         }
-        let mut parts = without_prefix.split_whitespace();
-        let command = parts.next().unwrap_or("");
-        // let args: Vec<&str> = parts.collect(); // 将来のために引数を使う場合
-
-        // コマンドごとのハンドラにディスパッチ
-        let result = match command {
-            "ping" => commands::ping::run(&ctx, &msg).await,
-            "help" => commands::help::run(&ctx, &msg).await,
-            "tex" => commands::tex::run(&ctx, &msg).await,
-            "rrepl" => commands::rust_repl_cmd::run(&ctx, &msg).await,
-            "gpt" => commands::gpt::run(&ctx, &msg).await,
-            "get" => commands::get::run(&ctx, &msg).await,
-            "post" => commands::post::run(&ctx, &msg).await,
-            _ => Ok(()), // 不明なコマンドは現状スルー
+        commands::reply::set_last_run(event.id, content);
+        let msg = match new {
+            Some(msg) => msg,
+            None => match event.channel_id.message(&ctx.http, event.id).await {
+                Ok(msg) => msg,
+                Err(_) => return,
+            },
         };
-
-        if let Err(why) = result {
-            println!("コマンド '{}' の実行中にエラーが発生: {:?}", command, why);
+        if msg.author.bot {
+            return;
         }
+        run_prefix_command(&ctx, &msg).await;
     }
 
     async fn ready(&self, ctx: Context, ready: Ready) {
         println!("{} として接続しました", ready.user.name);
+        // 対応言語リストを裏で更新し続ける（/eval は毎回取得しなくて済む）
+        piston::client().spawn_refresh();
+        // "// deps:" 用のクレートを先にビルドしておく（CARGO_RUNNER が有効なときだけ）
+        cargo_runner::client().spawn_warmup();
+
         // グローバルコマンドとして登録（反映に最大1時間）
         let cmds = commands::slash_commands();
         match Command::set_global_commands(&ctx.http, cmds).await {
@@ -0,0 +1,383 @@
+// Piston (コード実行 API) クライアント
+//
+// 接続先などは環境変数で変更できる（自前の Piston コンテナを使う場合など）
+// - PISTON_URL: API のベース URL (既定: https://emkc.org/api/v2/piston)
+// - PISTON_AUTH: Authorization ヘッダーの値 (任意)
+// - PISTON_TIMEOUT_SECS: リクエスト全体のタイムアウト秒数 (既定: 30)
+// - PISTON_CONNECT_TIMEOUT_SECS: 接続のタイムアウト秒数 (既定: 5)
+// - PISTON_CACHE_PATH: 対応言語リストの保存先 (既定: data/piston_runtimes.json, 空なら保存しない)
+// - PISTON_CACHE_TTL_HOURS: 対応言語リストの有効期限 (既定: 24)
+
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+pub mod cache;
+#[cfg(test)]
+pub mod fake;
+
+use cache::RuntimeCache;
+
+const DEFAULT_URL: &str = "https://emkc.org/api/v2/piston";
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 5;
+const DEFAULT_CACHE_PATH: &str = "data/piston_runtimes.json";
+const DEFAULT_CACHE_TTL_HOURS: u64 = 24;
+// バックグラウンド更新で期限を確認する間隔
+const REFRESH_CHECK_SECS: u64 = 10 * 60;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub base_url: String,
+    pub auth: Option<String>,
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    pub cache_path: Option<PathBuf>,
+    pub cache_ttl: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_URL.to_string(),
+            auth: None,
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+            connect_timeout: Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECS),
+            cache_path: None,
+            cache_ttl: Duration::from_secs(DEFAULT_CACHE_TTL_HOURS * 60 * 60),
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// 環境変数の読み方を差し替えられるようにしたもの（テスト用）
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let default = Self::default();
+        let secs = |key: &str, default: Duration| {
+            lookup(key)
+                .and_then(|s| s.trim().parse::<u64>().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(default)
+        };
+        Self {
+            base_url: lookup("PISTON_URL")
+                .map(|s| s.trim().trim_end_matches('/').to_string())
+                .filter(|s| !s.is_empty())
+                .unwrap_or(default.base_url),
+            auth: lookup("PISTON_AUTH").filter(|s| !s.trim().is_empty()),
+            timeout: secs("PISTON_TIMEOUT_SECS", default.timeout),
+            connect_timeout: secs("PISTON_CONNECT_TIMEOUT_SECS", default.connect_timeout),
+            cache_path: match lookup("PISTON_CACHE_PATH") {
+                Some(p) if p.trim().is_empty() => None,
+                Some(p) => Some(PathBuf::from(p.trim())),
+                None => Some(PathBuf::from(DEFAULT_CACHE_PATH)),
+            },
+            cache_ttl: lookup("PISTON_CACHE_TTL_HOURS")
+                .and_then(|s| s.trim().parse::<u64>().ok())
+                .map(|h| Duration::from_secs(h * 60 * 60))
+                .unwrap_or(default.cache_ttl),
+        }
+    }
+}
+
+/// api対応言語
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Languages(pub Vec<Lang>);
+
+impl Languages {
+    /// 言語名またはエイリアス (py, rs, js など) で検索。複数バージョンあれば最新のもの
+    pub fn get<T: AsRef<str>>(&self, lang: T) -> Option<&Lang> {
+        self.find(lang, None)
+    }
+
+    /// バージョン指定つきの検索。"3.10" のような前方一致 (区切りは '.') も受け付け、
+    /// 当てはまるものが複数あれば最新を返す
+    pub fn find<T: AsRef<str>>(&self, lang: T, version: Option<&str>) -> Option<&Lang> {
+        let version = version.map(str::trim).filter(|v| !v.is_empty());
+        self.candidates(lang.as_ref())
+            .into_iter()
+            .filter(|l| version.is_none_or(|v| l.matches_version(v)))
+            .max_by(|a, b| compare_versions(&a.version, &b.version))
+    }
+
+    /// 指定言語のバージョン一覧（新しい順）
+    pub fn versions<T: AsRef<str>>(&self, lang: T) -> Vec<&str> {
+        let mut versions: Vec<&str> = self
+            .candidates(lang.as_ref())
+            .into_iter()
+            .map(|l| l.version.as_str())
+            .collect();
+        versions.sort_by(|a, b| compare_versions(b, a));
+        versions.dedup();
+        versions
+    }
+
+    /// 言語名・エイリアスに `query` を含む言語（言語ごとに最新の1つ、前方一致を優先）
+    pub fn search(&self, query: &str) -> Vec<&Lang> {
+        let query = query.trim().to_lowercase();
+        let mut hits: Vec<(bool, &Lang)> = Vec::new();
+        for lang in &self.0 {
+            let names = std::iter::once(&lang.language).chain(&lang.aliases);
+            let names: Vec<String> = names.map(|n| n.to_lowercase()).collect();
+            if !names.iter().any(|n| n.contains(&query)) {
+                continue;
+            }
+            let prefix = names.iter().any(|n| n.starts_with(&query));
+            match hits.iter_mut().find(|(_, l)| l.language == lang.language) {
+                Some(hit) => {
+                    if compare_versions(&lang.version, &hit.1.version).is_gt() {
+                        hit.1 = lang;
+                    }
+                }
+                None => hits.push((prefix, lang)),
+            }
+        }
+        hits.sort_by(|(a, l), (b, r)| b.cmp(a).then_with(|| l.language.cmp(&r.language)));
+        hits.into_iter().map(|(_, l)| l).collect()
+    }
+
+    /// 言語名で一致するものがあればそれを、なければエイリアスで一致するものを返す
+    fn candidates(&self, lang: &str) -> Vec<&Lang> {
+        let lang = lang.trim().to_lowercase();
+        let by_name: Vec<&Lang> = self
+            .0
+            .iter()
+            .filter(|s| s.language.to_lowercase() == lang)
+            .collect();
+        if !by_name.is_empty() {
+            return by_name;
+        }
+        self.0
+            .iter()
+            .filter(|s| s.aliases.contains(&lang))
+            .collect()
+    }
+}
+
+/// "3.10.0" と "3.9.4" のようなバージョン文字列を数値として比較する
+fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    let parts = |v: &str| -> Vec<u64> {
+        v.split(['.', '-', '+'])
+            .map(|p| p.parse().unwrap_or(0))
+            .collect()
+    };
+    parts(a).cmp(&parts(b)).then_with(|| a.cmp(b))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lang {
+    pub language: String,
+    pub version: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+impl Lang {
+    fn matches_version(&self, version: &str) -> bool {
+        self.version == version
+            || self
+                .version
+                .strip_prefix(version)
+                .is_some_and(|rest| rest.starts_with('.'))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Piston {
+    config: Config,
+    client: reqwest::Client,
+    runtimes: Arc<RuntimeCache>,
+}
+
+impl Piston {
+    pub fn new(config: Config) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .connect_timeout(config.connect_timeout)
+            .build()
+            .unwrap_or_default();
+        let runtimes = Arc::new(RuntimeCache::new(
+            config.cache_path.clone(),
+            config.cache_ttl,
+        ));
+        Self {
+            config,
+            client,
+            runtimes,
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{path}", self.config.base_url)
+    }
+
+    fn with_auth(&self, req: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.config.auth {
+            Some(auth) => req.header(reqwest::header::AUTHORIZATION, auth),
+            None => req,
+        }
+    }
+
+    /// GET /runtimes
+    pub async fn runtimes<T: DeserializeOwned>(&self) -> Result<T, reqwest::Error> {
+        self.with_auth(self.client.get(self.url("runtimes")))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    /// 対応言語リスト（キャッシュ経由）
+    pub async fn languages(&self) -> Result<Arc<Languages>, reqwest::Error> {
+        self.runtimes.get(|| self.runtimes()).await
+    }
+
+    /// 定期的に対応言語リストを取り直す。何度呼んでも起動するのは1回だけ
+    pub fn spawn_refresh(&'static self) {
+        if !self.runtimes.start_refreshing() {
+            return;
+        }
+        tokio::spawn(async move {
+            loop {
+                if self.runtimes.needs_refresh()
+                    && let Err(e) = self.runtimes.refresh(|| self.runtimes()).await
+                {
+                    println!("言語リストの更新に失敗: {e}");
+                }
+                tokio::time::sleep(Duration::from_secs(REFRESH_CHECK_SECS)).await;
+            }
+        });
+    }
+
+    /// POST /execute
+    pub async fn execute<Req: Serialize, Resp: DeserializeOwned>(
+        &self,
+        req: &Req,
+    ) -> Result<Resp, reqwest::Error> {
+        self.with_auth(self.client.post(self.url("execute")))
+            .json(req)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+}
+
+static CLIENT: Lazy<Piston> = Lazy::new(|| Piston::new(Config::from_env()));
+
+/// 環境変数の設定で作った共有クライアント
+pub fn client() -> &'static Piston {
+    &CLIENT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fake_http::FakeHttp;
+    use serde_json::{Value, json};
+
+    #[test]
+    fn config_from_lookup() {
+        let c = Config::from_lookup(|_| None);
+        assert_eq!(c.base_url, DEFAULT_URL);
+        assert_eq!(c.auth, None);
+        assert_eq!(c.cache_path, Some(PathBuf::from(DEFAULT_CACHE_PATH)));
+
+        let c = Config::from_lookup(|key| match key {
+            "PISTON_URL" => Some("http://localhost:2000/api/v2/".to_string()),
+            "PISTON_AUTH" => Some("Bearer xyz".to_string()),
+            "PISTON_TIMEOUT_SECS" => Some("7".to_string()),
+            "PISTON_CONNECT_TIMEOUT_SECS" => Some("oops".to_string()),
+            "PISTON_CACHE_PATH" => Some("".to_string()),
+            "PISTON_CACHE_TTL_HOURS" => Some("1".to_string()),
+            _ => None,
+        });
+        assert_eq!(c.base_url, "http://localhost:2000/api/v2");
+        assert_eq!(c.auth.as_deref(), Some("Bearer xyz"));
+        assert_eq!(c.timeout, Duration::from_secs(7));
+        assert_eq!(
+            c.connect_timeout,
+            Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECS)
+        );
+        assert_eq!(c.cache_path, None);
+        assert_eq!(c.cache_ttl, Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn version_lookup() {
+        let langs: Languages = serde_json::from_value(json!([
+            {"language": "python", "version": "3.9.4", "aliases": ["py"]},
+            {"language": "python", "version": "3.12.0", "aliases": ["py"]},
+            {"language": "python", "version": "3.10.0", "aliases": ["py"]},
+            {"language": "typescript", "version": "5.0.3", "aliases": ["ts"]},
+            {"language": "deno", "version": "1.32.3", "aliases": ["deno-ts", "typescript"]},
+        ]))
+        .unwrap();
+
+        assert_eq!(langs.get("py").unwrap().version, "3.12.0");
+        assert_eq!(
+            langs.find("python", Some("3.10")).unwrap().version,
+            "3.10.0"
+        );
+        assert_eq!(langs.find("py", Some("3.9.4")).unwrap().version, "3.9.4");
+        assert!(langs.find("py", Some("3.1")).is_none());
+        // 言語名が一致するものをエイリアスより優先
+        assert_eq!(langs.get("typescript").unwrap().language, "typescript");
+        assert_eq!(langs.versions("py"), ["3.12.0", "3.10.0", "3.9.4"]);
+
+        let hits: Vec<&str> = langs
+            .search("ts")
+            .iter()
+            .map(|l| l.language.as_str())
+            .collect();
+        assert_eq!(hits, ["typescript", "deno"]);
+        let hits = langs.search("");
+        assert_eq!(hits.len(), 3);
+        assert_eq!(
+            hits.iter()
+                .find(|l| l.language == "python")
+                .unwrap()
+                .version,
+            "3.12.0"
+        );
+    }
+
+    #[tokio::test]
+    async fn talks_to_fake_server() {
+        let fake = FakeHttp::start(
+            json!([{"language": "rust", "version": "1.0.0", "aliases": ["rs"]}]),
+            |req| fake::output(req, "ok\n"),
+        );
+        let piston = Piston::new(Config {
+            auth: Some("secret".to_string()),
+            ..fake::config(&fake)
+        });
+
+        let runtimes: Value = piston.runtimes().await.unwrap();
+        assert_eq!(runtimes[0]["language"], "rust");
+        // キャッシュ経由なら2回目はリクエストしない
+        piston.languages().await.unwrap();
+        let langs = piston.languages().await.unwrap();
+        assert_eq!(langs.get("rs").unwrap().version, "1.0.0");
+
+        let res: Value = piston
+            .execute(&json!({"language": "rust", "version": "1.0.0", "files": []}))
+            .await
+            .unwrap();
+        assert_eq!(res["run"]["output"], "ok\n");
+
+        let reqs = fake.requests();
+        assert_eq!(reqs.len(), 3);
+        assert_eq!(reqs[0].method, "GET");
+        assert_eq!(reqs[2].path, "/execute");
+        assert_eq!(reqs[2].auth.as_deref(), Some("secret"));
+        assert_eq!(reqs[2].body["language"], "rust");
+    }
+}
@@ -0,0 +1,229 @@
+// 対応言語リスト (GET /runtimes) のキャッシュ
+//
+// - 期限 (TTL) 内はメモリ上のリストを返す
+// - 期限切れなら取り直し、取得に失敗したら古いリストで続行する
+// - 取得したリストはファイルに保存し、再起動後もそこから読み込む
+
+use std::{
+    future::Future,
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::Languages;
+
+#[derive(Debug, Clone)]
+struct Entry {
+    // 取得時刻 (UNIX 秒)
+    time_stamp: i64,
+    content: Arc<Languages>,
+}
+
+/// 保存ファイルの中身
+#[derive(Serialize, Deserialize)]
+struct Saved {
+    time_stamp: i64,
+    content: Languages,
+}
+
+#[derive(Debug)]
+pub struct RuntimeCache {
+    path: Option<PathBuf>,
+    ttl: Duration,
+    entry: Mutex<Option<Entry>>,
+    // バックグラウンド更新を二重に起動しないためのフラグ
+    refreshing: AtomicBool,
+}
+
+impl RuntimeCache {
+    /// `path` があれば保存済みのリストを読み込む
+    pub fn new(path: Option<PathBuf>, ttl: Duration) -> Self {
+        let entry = path.as_ref().and_then(|p| {
+            let bytes = std::fs::read(p).ok()?;
+            let saved: Saved = serde_json::from_slice(&bytes).ok()?;
+            Some(Entry {
+                time_stamp: saved.time_stamp,
+                content: Arc::new(saved.content),
+            })
+        });
+        Self {
+            path,
+            ttl,
+            entry: Mutex::new(entry),
+            refreshing: AtomicBool::new(false),
+        }
+    }
+
+    fn age(&self) -> Option<Duration> {
+        let entry = self.entry.lock().unwrap();
+        let elapsed = chrono::Utc::now().timestamp() - entry.as_ref()?.time_stamp;
+        Some(Duration::from_secs(elapsed.max(0) as u64))
+    }
+
+    fn is_fresh(&self) -> bool {
+        self.age().is_some_and(|age| age < self.ttl)
+    }
+
+    /// 期限の半分を過ぎたら裏で取り直す
+    pub fn needs_refresh(&self) -> bool {
+        self.age().is_none_or(|age| age >= self.ttl / 2)
+    }
+
+    fn current(&self) -> Option<Arc<Languages>> {
+        self.entry
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|e| e.content.clone())
+    }
+
+    /// 期限内ならキャッシュを、そうでなければ `fetch` で取り直して返す。
+    /// 取り直しに失敗しても古いリストがあればそれを返す
+    pub async fn get<F, Fut, E>(&self, fetch: F) -> Result<Arc<Languages>, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Languages, E>>,
+        E: std::fmt::Display,
+    {
+        if self.is_fresh()
+            && let Some(content) = self.current()
+        {
+            return Ok(content);
+        }
+        match self.refresh(fetch).await {
+            Ok(content) => Ok(content),
+            Err(e) => match self.current() {
+                Some(stale) => {
+                    println!("言語リストの更新に失敗したため古いリストを使います: {e}");
+                    Ok(stale)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// `fetch` で取り直してキャッシュとファイルを更新する
+    pub async fn refresh<F, Fut, E>(&self, fetch: F) -> Result<Arc<Languages>, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Languages, E>>,
+    {
+        let content = fetch().await?;
+        let time_stamp = chrono::Utc::now().timestamp();
+        self.save(&Saved {
+            time_stamp,
+            content: content.clone(),
+        });
+        let content = Arc::new(content);
+        *self.entry.lock().unwrap() = Some(Entry {
+            time_stamp,
+            content: content.clone(),
+        });
+        Ok(content)
+    }
+
+    fn save(&self, saved: &Saved) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let result = serde_json::to_vec(saved)
+            .map_err(std::io::Error::from)
+            .and_then(|bytes| std::fs::write(path, bytes));
+        if let Err(e) = result {
+            println!("言語リストの保存に失敗: {e}");
+        }
+    }
+
+    /// バックグラウンド更新を始めてよければ true（2回目以降は false）
+    pub fn start_refreshing(&self) -> bool {
+        !self.refreshing.swap(true, Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piston::Lang;
+
+    fn langs(version: &str) -> Languages {
+        Languages(vec![Lang {
+            language: "rust".to_string(),
+            version: version.to_string(),
+            aliases: vec!["rs".to_string()],
+        }])
+    }
+
+    #[tokio::test]
+    async fn fresh_cache_skips_fetch() {
+        let cache = RuntimeCache::new(None, Duration::from_secs(3600));
+        let first = cache
+            .get(|| async { Ok::<_, String>(langs("1.0.0")) })
+            .await
+            .unwrap();
+        assert_eq!(first.0[0].version, "1.0.0");
+
+        let second = cache
+            .get(|| async { Err::<Languages, _>("呼ばれないはず".to_string()) })
+            .await
+            .unwrap();
+        assert_eq!(second.0[0].version, "1.0.0");
+        assert!(!cache.needs_refresh());
+    }
+
+    #[tokio::test]
+    async fn stale_on_error() {
+        let cache = RuntimeCache::new(None, Duration::ZERO);
+        assert!(
+            cache
+                .get(|| async { Err::<Languages, _>("down".to_string()) })
+                .await
+                .is_err()
+        );
+
+        cache
+            .refresh(|| async { Ok::<_, String>(langs("1.0.0")) })
+            .await
+            .unwrap();
+        // TTL 0 なので毎回取り直そうとするが、失敗したら古いリストを返す
+        let stale = cache
+            .get(|| async { Err::<Languages, _>("down".to_string()) })
+            .await
+            .unwrap();
+        assert_eq!(stale.0[0].version, "1.0.0");
+
+        let updated = cache
+            .get(|| async { Ok::<_, String>(langs("2.0.0")) })
+            .await
+            .unwrap();
+        assert_eq!(updated.0[0].version, "2.0.0");
+    }
+
+    #[tokio::test]
+    async fn persists_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sub").join("runtimes.json");
+
+        let cache = RuntimeCache::new(Some(path.clone()), Duration::from_secs(3600));
+        cache
+            .refresh(|| async { Ok::<_, String>(langs("1.0.0")) })
+            .await
+            .unwrap();
+
+        let restarted = RuntimeCache::new(Some(path), Duration::from_secs(3600));
+        assert!(restarted.is_fresh());
+        let loaded = restarted
+            .get(|| async { Err::<Languages, _>("呼ばれないはず".to_string()) })
+            .await
+            .unwrap();
+        assert_eq!(loaded.get("rs").unwrap().version, "1.0.0");
+    }
+}
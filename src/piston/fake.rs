@@ -0,0 +1,42 @@
+// テスト用の Piston
+//
+// test_support::fake_http の HTTP サーバーを Piston の代わりに使うための道具。
+// GET /runtimes には起動時に渡したリストを、POST /execute にはハンドラの戻り値を返す。
+
+use std::time::Duration;
+
+use serde_json::{Value, json};
+
+use super::Config;
+use crate::test_support::fake_http::FakeHttp;
+
+/// `server` に接続する設定
+pub fn config(server: &FakeHttp) -> Config {
+    Config {
+        base_url: server.url().to_string(),
+        timeout: Duration::from_secs(5),
+        ..Config::default()
+    }
+}
+
+/// 受け取ったリクエストの言語・バージョンで、実行結果が `output` のレスポンスを作る
+pub fn output(req: &Value, output: &str) -> Value {
+    json!({
+        "language": req["language"],
+        "version": req["version"],
+        "compile": {
+            "stdout": "",
+            "stderr": "",
+            "code": 0,
+            "signal": null,
+            "output": "",
+        },
+        "run": {
+            "stdout": output,
+            "stderr": "",
+            "code": 0,
+            "signal": null,
+            "output": output,
+        },
+    })
+}
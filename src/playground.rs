@@ -0,0 +1,206 @@
+// Rust Playground 互換 API クライアント (/rust fmt, clippy, expand, asm 用)
+//
+// 接続先は環境変数で変更できる（手元で動かしている Playground を使う場合など）
+// - PLAYGROUND_URL: API のベース URL (既定: https://play.rust-lang.org)
+// - PLAYGROUND_TIMEOUT_SECS: リクエスト全体のタイムアウト秒数 (既定: 30)
+
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+const DEFAULT_URL: &str = "https://play.rust-lang.org";
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+pub const DEFAULT_EDITION: &str = "2021";
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub base_url: String,
+    pub timeout: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_URL.to_string(),
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// 環境変数の読み方を差し替えられるようにしたもの（テスト用）
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let default = Self::default();
+        Self {
+            base_url: lookup("PLAYGROUND_URL")
+                .map(|s| s.trim().trim_end_matches('/').to_string())
+                .filter(|s| !s.is_empty())
+                .unwrap_or(default.base_url),
+            timeout: lookup("PLAYGROUND_TIMEOUT_SECS")
+                .and_then(|s| s.trim().parse::<u64>().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(default.timeout),
+        }
+    }
+}
+
+/// Playground のツール
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Fmt,
+    Clippy,
+    Expand,
+    Asm,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 4] = [Tool::Fmt, Tool::Clippy, Tool::Expand, Tool::Asm];
+
+    /// サブコマンド名
+    pub fn name(self) -> &'static str {
+        match self {
+            Tool::Fmt => "fmt",
+            Tool::Clippy => "clippy",
+            Tool::Expand => "expand",
+            Tool::Asm => "asm",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.name() == name)
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Tool::Fmt => "rustfmt で整形します",
+            Tool::Clippy => "clippy の警告を表示します",
+            Tool::Expand => "マクロを展開したコードを表示します",
+            Tool::Asm => "アセンブリを表示します (release)",
+        }
+    }
+
+    fn path(self) -> &'static str {
+        match self {
+            Tool::Fmt => "format",
+            Tool::Clippy => "clippy",
+            Tool::Expand => "macro-expansion",
+            Tool::Asm => "compile",
+        }
+    }
+
+    /// リクエストの本文
+    fn body(self, code: &str, edition: &str) -> Value {
+        let mut body = json!({
+            "channel": "stable",
+            "edition": edition,
+            "code": code,
+        });
+        match self {
+            Tool::Fmt => {}
+            // main が無くても使えるようにライブラリとして扱う
+            Tool::Clippy => body["crateType"] = json!("lib"),
+            // マクロ展開は nightly のみ
+            Tool::Expand => body["channel"] = json!("nightly"),
+            Tool::Asm => {
+                let asm = json!({
+                    "mode": "release",
+                    "crateType": "lib",
+                    "tests": false,
+                    "backtrace": false,
+                    "target": "asm",
+                    "assemblyFlavor": "intel",
+                    "demangleAssembly": "demangle",
+                    "processAssembly": "filter",
+                });
+                for (key, value) in asm.as_object().unwrap() {
+                    body[key] = value.clone();
+                }
+            }
+        }
+        body
+    }
+}
+
+/// Playground の応答。fmt と asm は結果が `code` に入る
+#[derive(Debug, Clone, Deserialize)]
+pub struct Output {
+    pub success: bool,
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub stdout: String,
+    #[serde(default)]
+    pub stderr: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Playground {
+    config: Config,
+    client: reqwest::Client,
+}
+
+impl Playground {
+    pub fn new(config: Config) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .build()
+            .unwrap_or_default();
+        Self { config, client }
+    }
+
+    /// POST /<tool>
+    pub async fn run(
+        &self,
+        tool: Tool,
+        code: &str,
+        edition: &str,
+    ) -> Result<Output, reqwest::Error> {
+        self.client
+            .post(format!("{}/{}", self.config.base_url, tool.path()))
+            .json(&tool.body(code, edition))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+}
+
+static CLIENT: Lazy<Playground> = Lazy::new(|| Playground::new(Config::from_env()));
+
+/// 環境変数の設定で作った共有クライアント
+pub fn client() -> &'static Playground {
+    &CLIENT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_and_bodies() {
+        let c = Config::from_lookup(|_| None);
+        assert_eq!(c.base_url, DEFAULT_URL);
+        let c = Config::from_lookup(|key| match key {
+            "PLAYGROUND_URL" => Some("http://localhost:5000/".to_string()),
+            "PLAYGROUND_TIMEOUT_SECS" => Some("3".to_string()),
+            _ => None,
+        });
+        assert_eq!(c.base_url, "http://localhost:5000");
+        assert_eq!(c.timeout, Duration::from_secs(3));
+
+        assert_eq!(Tool::from_name("asm"), Some(Tool::Asm));
+        assert_eq!(Tool::from_name("run"), None);
+        let body = Tool::Asm.body("fn f() {}", "2021");
+        assert_eq!(body["target"], "asm");
+        assert_eq!(body["crateType"], "lib");
+        assert_eq!(body["edition"], "2021");
+        assert_eq!(Tool::Expand.body("", "2021")["channel"], "nightly");
+    }
+}
@@ -0,0 +1,88 @@
+// コード実行のバックエンド
+//
+// eval / rrepl はここの Runner 越しにコードを実行する。
+// リクエスト・応答はどちらも Piston の POST /execute と同じ形。
+// ただしリクエストの color (色付きの出力を頼む) だけは Piston にない項目。
+// Piston はコンパイラの引数も環境変数も渡せないので、Piston で color を頼まれたらエラーにする。
+// - piston: Piston API (既定)
+// - sandbox: Bot のホスト上のサンドボックスで実行する (runner/sandbox.rs)
+//
+// どちらを使うかは環境変数で選ぶ
+// - EVAL_RUNNER: piston / sandbox (既定: piston)
+
+use std::sync::Arc;
+
+use once_cell::sync::Lazy;
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use serenity::async_trait;
+
+use crate::piston::{self, Languages, Piston};
+
+pub mod sandbox;
+
+use sandbox::SandboxRunner;
+
+#[async_trait]
+pub trait Runner: Send + Sync {
+    /// 対応言語とバージョン
+    async fn languages(&self) -> Result<Arc<Languages>, String>;
+
+    /// Piston の POST /execute と同じ形のリクエストを実行し、同じ形の応答を返す
+    async fn execute_json(&self, req: Value) -> Result<Value, String>;
+
+    /// リクエストの color (色付きの出力) に対応しているか
+    fn colors(&self) -> bool {
+        false
+    }
+}
+
+/// color を頼まれたのに対応していないバックエンドならエラー
+pub fn check_color(runner: &dyn Runner, color: bool) -> Result<(), String> {
+    if color && !runner.colors() {
+        return Err(
+            "この実行環境 (Piston) では color は使えません（EVAL_RUNNER=sandbox か // deps: のときに使えます）"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// 型付きのリクエスト・応答で実行する
+pub async fn execute<Req: Serialize, Resp: DeserializeOwned>(
+    runner: &dyn Runner,
+    req: &Req,
+) -> Result<Resp, String> {
+    let req = serde_json::to_value(req).map_err(|e| e.to_string())?;
+    let resp = runner.execute_json(req).await?;
+    serde_json::from_value(resp).map_err(|e| format!("応答を読めませんでした: {e}"))
+}
+
+#[async_trait]
+impl Runner for Piston {
+    async fn languages(&self) -> Result<Arc<Languages>, String> {
+        Piston::languages(self)
+            .await
+            .map_err(|_| "言語リストの取得に失敗しました。".to_string())
+    }
+
+    async fn execute_json(&self, req: Value) -> Result<Value, String> {
+        self.execute(&req).await.map_err(|e| match e.status() {
+            // 制限値が Piston 側の上限を超えていると 400 が返る
+            Some(status) if status.is_client_error() => format!(
+                "実行に失敗しました。指定した制限値が大きすぎる可能性があります。({status})"
+            ),
+            _ => "実行に失敗しました。".to_string(),
+        })
+    }
+}
+
+static SANDBOX: Lazy<SandboxRunner> = Lazy::new(|| SandboxRunner::new(sandbox::Config::from_env()));
+
+/// EVAL_RUNNER で選んだバックエンド
+pub fn client() -> &'static dyn Runner {
+    match std::env::var("EVAL_RUNNER").ok().as_deref().map(str::trim) {
+        Some("sandbox") => &*SANDBOX,
+        _ => piston::client(),
+    }
+}
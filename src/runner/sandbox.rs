@@ -0,0 +1,912 @@
+// Bot のホスト上でコードを実行するバックエンド (EVAL_RUNNER=sandbox)
+//
+// 一時ディレクトリ (tempfile) を作業場所にして、bubblewrap か nsjail の中でコンパイル・実行する。
+// ネットワークは切り、見えるのはシステムのディレクトリとコンパイラ (読み取り専用) と作業場所だけ。
+// メモリ・CPU 時間・プロセス数・ファイルサイズは rlimit で制限する。
+// 応答は Piston と同じ形なので、eval / rrepl の表示はそのまま使える。
+//
+// 設定は環境変数で行う
+// - SANDBOX_TOOL: bwrap / nsjail / none (既定: 見つかった方。none は隔離しないのでテスト用)
+// - SANDBOX_COMPILE_TIMEOUT_SECS: コンパイルのタイムアウト秒数 (既定: 30)
+// - SANDBOX_RUN_TIMEOUT_SECS: 実行のタイムアウト秒数 (既定: 5)
+// - SANDBOX_COMPILE_MEMORY_MB: コンパイル時のメモリ上限 (既定: 4096)
+// - SANDBOX_RUN_MEMORY_MB: 実行時のメモリ上限 (既定: 512)
+// - SANDBOX_MAX_PROCS: プロセス数の上限 (既定: 64)
+//
+// リクエストの color (Piston にない項目) が true なら、コンパイラに色付きの診断を頼み、
+// 実行時にも色を強制する環境変数 (COLOR_ENV) を渡す。
+
+use std::{
+    path::{Component, Path, PathBuf},
+    process::Stdio,
+    sync::Arc,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use serenity::async_trait;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    process::{ChildStderr, ChildStdout, Command},
+    sync::OnceCell,
+};
+
+use super::Runner;
+use crate::piston::{Lang, Languages};
+
+const DEFAULT_COMPILE_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_RUN_TIMEOUT_SECS: u64 = 5;
+const DEFAULT_COMPILE_MEMORY_MB: u64 = 4096;
+const DEFAULT_RUN_MEMORY_MB: u64 = 512;
+const DEFAULT_MAX_PROCS: u64 = 64;
+// 書き出せるファイルの大きさ (MB)
+const MAX_FILE_MB: u64 = 16;
+// 出力の上限（これ以上は捨てる）
+const MAX_OUTPUT_BYTES: usize = 64 * 1024;
+// サンドボックスの中の作業場所
+const WORK_DIR: &str = "/work";
+const PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+// 色付きの出力を頼むときの環境変数
+pub const COLOR_ENV: &[(&str, &str)] = &[
+    ("CLICOLOR_FORCE", "1"),
+    ("FORCE_COLOR", "1"),
+    ("PYTHON_COLORS", "1"),
+    ("TERM", "xterm-256color"),
+];
+// 読み取り専用で見せるシステムのディレクトリ
+const SYSTEM_DIRS: &[&str] = &[
+    "/usr",
+    "/bin",
+    "/lib",
+    "/lib64",
+    "/etc/alternatives",
+    "/etc/ld.so.cache",
+];
+
+/// 隔離に使うツール
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Isolation {
+    // bwrap, nsjail の順に探す
+    Auto,
+    Bwrap,
+    Nsjail,
+    // 隔離しない (rlimit だけ)
+    None,
+}
+
+impl Isolation {
+    fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "auto" => Some(Self::Auto),
+            "bwrap" | "bubblewrap" => Some(Self::Bwrap),
+            "nsjail" => Some(Self::Nsjail),
+            "none" => Some(Self::None),
+            _ => None,
+        }
+    }
+
+    /// Auto なら使えるツールを探す
+    pub fn resolve(self) -> Result<Self, String> {
+        match self {
+            Self::Auto if which("bwrap").is_some() => Ok(Self::Bwrap),
+            Self::Auto if which("nsjail").is_some() => Ok(Self::Nsjail),
+            Self::Auto => Err(
+                "サンドボックス (bwrap / nsjail) が見つかりません。SANDBOX_TOOL を設定してください"
+                    .to_string(),
+            ),
+            other => Ok(other),
+        }
+    }
+}
+
+/// 1回のコンパイル・実行の制限
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub timeout: Duration,
+    pub memory_mb: u64,
+    pub max_procs: u64,
+}
+
+impl Limits {
+    /// リクエストで指定された値 (ミリ秒・バイト) があれば、上限を超えない範囲で使う
+    fn narrow(self, timeout_ms: Option<i64>, memory_bytes: Option<i64>) -> Self {
+        let positive = |v: Option<i64>| v.filter(|v| *v > 0).map(|v| v as u64);
+        Self {
+            timeout: positive(timeout_ms)
+                .map(Duration::from_millis)
+                .map_or(self.timeout, |t| t.min(self.timeout)),
+            memory_mb: positive(memory_bytes)
+                .map(|b| b.div_ceil(1024 * 1024))
+                .map_or(self.memory_mb, |m| m.min(self.memory_mb)),
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub isolation: Isolation,
+    pub compile: Limits,
+    pub run: Limits,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            isolation: Isolation::Auto,
+            compile: Limits {
+                timeout: Duration::from_secs(DEFAULT_COMPILE_TIMEOUT_SECS),
+                memory_mb: DEFAULT_COMPILE_MEMORY_MB,
+                max_procs: DEFAULT_MAX_PROCS,
+            },
+            run: Limits {
+                timeout: Duration::from_secs(DEFAULT_RUN_TIMEOUT_SECS),
+                memory_mb: DEFAULT_RUN_MEMORY_MB,
+                max_procs: DEFAULT_MAX_PROCS,
+            },
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// 環境変数の読み方を差し替えられるようにしたもの（テスト用）
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let default = Self::default();
+        let num = |key: &str, default: u64| {
+            lookup(key)
+                .and_then(|s| s.trim().parse::<u64>().ok())
+                .unwrap_or(default)
+        };
+        let max_procs = num("SANDBOX_MAX_PROCS", DEFAULT_MAX_PROCS);
+        Self {
+            isolation: lookup("SANDBOX_TOOL")
+                .and_then(|s| Isolation::parse(&s))
+                .unwrap_or(default.isolation),
+            compile: Limits {
+                timeout: Duration::from_secs(num(
+                    "SANDBOX_COMPILE_TIMEOUT_SECS",
+                    DEFAULT_COMPILE_TIMEOUT_SECS,
+                )),
+                memory_mb: num("SANDBOX_COMPILE_MEMORY_MB", DEFAULT_COMPILE_MEMORY_MB),
+                max_procs,
+            },
+            run: Limits {
+                timeout: Duration::from_secs(num(
+                    "SANDBOX_RUN_TIMEOUT_SECS",
+                    DEFAULT_RUN_TIMEOUT_SECS,
+                )),
+                memory_mb: num("SANDBOX_RUN_MEMORY_MB", DEFAULT_RUN_MEMORY_MB),
+                max_procs,
+            },
+        }
+    }
+}
+
+/// 対応言語。コマンドの {tool} は見つけたコンパイラ・インタプリタ、
+/// {src} は先頭のファイル、{sources} は同じ拡張子のファイル全部に置き換える
+struct Spec {
+    language: &'static str,
+    aliases: &'static [&'static str],
+    ext: &'static str,
+    compile: Option<&'static [&'static str]>,
+    // 色付きの診断を出させるコンパイラの引数
+    color: &'static [&'static str],
+    run: &'static [&'static str],
+}
+
+const SPECS: &[Spec] = &[
+    Spec {
+        language: "rust",
+        aliases: &["rs"],
+        ext: "rs",
+        compile: Some(&["{tool}", "--edition", "2021", "-O", "-o", "main", "{src}"]),
+        color: &["--color=always"],
+        run: &["./main"],
+    },
+    Spec {
+        language: "python",
+        aliases: &["py", "python3"],
+        ext: "py",
+        compile: None,
+        color: &[],
+        run: &["{tool}", "{src}"],
+    },
+    Spec {
+        language: "c",
+        aliases: &["gcc"],
+        ext: "c",
+        compile: Some(&["{tool}", "-O2", "-o", "main", "{sources}", "-lm"]),
+        color: &["-fdiagnostics-color=always"],
+        run: &["./main"],
+    },
+    Spec {
+        language: "c++",
+        aliases: &["cpp", "g++"],
+        ext: "cpp",
+        compile: Some(&["{tool}", "-O2", "-std=c++17", "-o", "main", "{sources}"]),
+        color: &["-fdiagnostics-color=always"],
+        run: &["./main"],
+    },
+];
+
+/// ホストで見つかった言語の処理系
+#[derive(Debug)]
+struct Toolchain {
+    spec: &'static Spec,
+    tool: PathBuf,
+    version: String,
+    // サンドボックスの中に見せるディレクトリ
+    binds: Vec<PathBuf>,
+}
+
+impl std::fmt::Debug for Spec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.language)
+    }
+}
+
+/// コマンドの標準出力（失敗したら None）
+pub async fn probe(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().await.ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// 言語の処理系を探す。rustup や pyenv の中継ではなく本体の場所を使う
+async fn detect(spec: &'static Spec) -> Option<Toolchain> {
+    let (tool, version, binds) = match spec.language {
+        "rust" => {
+            let sysroot = PathBuf::from(probe("rustc", &["--print", "sysroot"]).await?);
+            let version = probe("rustc", &["--version"]).await?;
+            let version = version.split_whitespace().nth(1)?.to_string();
+            (sysroot.join("bin/rustc"), version, vec![sysroot])
+        }
+        "python" => {
+            let script = "import sys; print(sys.executable); print(sys.base_prefix); print(sys.version.split()[0])";
+            let out = probe("python3", &["-c", script]).await?;
+            let mut lines = out.lines();
+            let (exe, prefix, version) = (lines.next()?, lines.next()?, lines.next()?);
+            (
+                PathBuf::from(exe),
+                version.to_string(),
+                vec![PathBuf::from(prefix)],
+            )
+        }
+        "c" | "c++" => {
+            let name = if spec.language == "c" { "gcc" } else { "g++" };
+            let version = probe(name, &["-dumpfullversion"]).await?;
+            (which(name)?, version, Vec::new())
+        }
+        _ => return None,
+    };
+    Some(Toolchain {
+        spec,
+        tool,
+        version,
+        binds,
+    })
+}
+
+/// PATH から実行ファイルを探す
+fn which(name: &str) -> Option<PathBuf> {
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(name))
+            .find(|p| p.is_file())
+    })
+}
+
+/// Piston の /execute と同じ形のリクエスト（使う項目だけ）
+#[derive(Debug, Deserialize)]
+struct Request {
+    language: String,
+    files: Vec<File>,
+    #[serde(default)]
+    stdin: Option<String>,
+    #[serde(default)]
+    args: Vec<String>,
+    // ミリ秒
+    #[serde(default)]
+    compile_timeout: Option<i64>,
+    #[serde(default)]
+    run_timeout: Option<i64>,
+    // バイト
+    #[serde(default)]
+    compile_memory_limit: Option<i64>,
+    #[serde(default)]
+    run_memory_limit: Option<i64>,
+    #[serde(default)]
+    color: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct File {
+    pub name: String,
+    pub content: String,
+}
+
+/// Piston の応答と同じ形のステージ
+#[derive(Debug, Default, Serialize)]
+pub struct Stage {
+    pub stdout: String,
+    pub stderr: String,
+    pub output: String,
+    pub code: Option<i32>,
+    pub signal: Option<String>,
+}
+
+impl Stage {
+    fn timed_out() -> Self {
+        Self {
+            signal: Some("SIGKILL".to_string()),
+            stderr: "タイムアウトしました\n".to_string(),
+            output: "タイムアウトしました\n".to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+pub struct SandboxRunner {
+    config: Config,
+    toolchains: OnceCell<Vec<Toolchain>>,
+}
+
+impl SandboxRunner {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            toolchains: OnceCell::new(),
+        }
+    }
+
+    /// 初回だけホストの処理系を調べる
+    async fn toolchains(&self) -> &[Toolchain] {
+        self.toolchains
+            .get_or_init(|| async {
+                let mut found = Vec::new();
+                for spec in SPECS {
+                    found.extend(detect(spec).await);
+                }
+                found
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl Runner for SandboxRunner {
+    async fn languages(&self) -> Result<Arc<Languages>, String> {
+        let langs = self
+            .toolchains()
+            .await
+            .iter()
+            .map(|t| Lang {
+                language: t.spec.language.to_string(),
+                version: t.version.clone(),
+                aliases: t.spec.aliases.iter().map(|a| a.to_string()).collect(),
+            })
+            .collect();
+        Ok(Arc::new(Languages(langs)))
+    }
+
+    fn colors(&self) -> bool {
+        true
+    }
+
+    async fn execute_json(&self, req: Value) -> Result<Value, String> {
+        let req: Request =
+            serde_json::from_value(req).map_err(|e| format!("リクエストが不正です: {e}"))?;
+        let toolchain = self
+            .toolchains()
+            .await
+            .iter()
+            .find(|t| {
+                t.spec.language == req.language || t.spec.aliases.contains(&req.language.as_str())
+            })
+            .ok_or_else(|| format!("{} はこの実行環境にありません", req.language))?;
+        let isolation = self.config.isolation.resolve()?;
+        let spec = toolchain.spec;
+
+        let dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+        write_files(dir.path(), &req.files).await?;
+        let src = req.files.first().map_or("", |f| f.name.as_str());
+        let sources: Vec<&str> = req
+            .files
+            .iter()
+            .map(|f| f.name.as_str())
+            .filter(|n| n.ends_with(&format!(".{}", spec.ext)))
+            .collect();
+        let expand = |template: &[&str]| -> Vec<String> {
+            template
+                .iter()
+                .flat_map(|arg| match *arg {
+                    "{tool}" => vec![toolchain.tool.to_string_lossy().into_owned()],
+                    "{src}" => vec![src.to_string()],
+                    "{sources}" => sources.iter().map(|s| s.to_string()).collect(),
+                    arg => vec![arg.to_string()],
+                })
+                .collect()
+        };
+        let env = if req.color { COLOR_ENV } else { &[] };
+
+        let compile = match spec.compile {
+            Some(template) => {
+                let limits = self
+                    .config
+                    .compile
+                    .narrow(req.compile_timeout, req.compile_memory_limit);
+                let mut argv = expand(template);
+                if req.color {
+                    argv.splice(1..1, spec.color.iter().map(|a| a.to_string()));
+                }
+                let cmd = jail(
+                    isolation,
+                    dir.path(),
+                    &toolchain.binds,
+                    &[],
+                    env,
+                    limits,
+                    &argv,
+                );
+                Some(run_command(cmd, None, limits.timeout).await?)
+            }
+            None => None,
+        };
+        let run = match &compile {
+            // コンパイルに失敗したら実行しない
+            Some(stage) if !stage.succeeded() => None,
+            _ => {
+                let limits = self
+                    .config
+                    .run
+                    .narrow(req.run_timeout, req.run_memory_limit);
+                let mut argv = expand(spec.run);
+                argv.extend(req.args.iter().cloned());
+                let cmd = jail(
+                    isolation,
+                    dir.path(),
+                    &toolchain.binds,
+                    &[],
+                    env,
+                    limits,
+                    &argv,
+                );
+                Some(run_command(cmd, req.stdin.as_deref(), limits.timeout).await?)
+            }
+        };
+        Ok(json!({
+            "language": spec.language,
+            "version": toolchain.version,
+            "compile": compile,
+            "run": run,
+        }))
+    }
+}
+
+/// ファイルを作業場所に書き出す。作業場所の外を指す名前は拒否する
+pub async fn write_files(root: &Path, files: &[File]) -> Result<(), String> {
+    for file in files {
+        let rel = Path::new(&file.name);
+        if !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(format!("使えないファイル名です: {}", file.name));
+        }
+        let path = root.join(rel);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        tokio::fs::write(&path, &file.content)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// rlimit を掛けてから `argv` を exec するシェルの引数
+fn with_rlimits(limits: Limits, argv: &[String]) -> Vec<String> {
+    let script = format!(
+        "ulimit -v {}; ulimit -t {}; ulimit -f {}; ulimit -u {}; exec \"$@\"",
+        limits.memory_mb * 1024,
+        limits.timeout.as_secs() + 1,
+        MAX_FILE_MB * 1024 * 2,
+        limits.max_procs,
+    );
+    let mut args = vec!["-c".to_string(), script, "sh".to_string()];
+    args.extend(argv.iter().cloned());
+    args
+}
+
+/// サンドボックスの中で `argv` を動かすコマンド。書き込めるのは作業場所 `work` と `writable` だけ。
+/// `binds` は読み取り専用で、`writable` は書き込めるようにホストと同じパスで見せる
+pub fn jail(
+    isolation: Isolation,
+    work: &Path,
+    binds: &[PathBuf],
+    writable: &[PathBuf],
+    env: &[(&str, &str)],
+    limits: Limits,
+    argv: &[String],
+) -> Command {
+    let work = work.to_string_lossy().into_owned();
+    let args: Vec<String> = match isolation {
+        Isolation::Bwrap => {
+            let mut args: Vec<String> = [
+                "--unshare-all",
+                "--die-with-parent",
+                "--new-session",
+                "--clearenv",
+                "--setenv",
+                "PATH",
+                PATH,
+                "--setenv",
+                "HOME",
+                WORK_DIR,
+                "--proc",
+                "/proc",
+                "--dev",
+                "/dev",
+                "--tmpfs",
+                "/tmp",
+            ]
+            .map(String::from)
+            .to_vec();
+            for (key, value) in env {
+                args.extend(["--setenv", key, value].map(String::from));
+            }
+            for dir in SYSTEM_DIRS
+                .iter()
+                .map(PathBuf::from)
+                .chain(binds.iter().cloned())
+            {
+                let dir = dir.to_string_lossy().into_owned();
+                args.extend(["--ro-bind-try".to_string(), dir.clone(), dir]);
+            }
+            for dir in writable {
+                let dir = dir.to_string_lossy().into_owned();
+                args.extend(["--bind".to_string(), dir.clone(), dir]);
+            }
+            args.extend(["--bind".to_string(), work.clone(), WORK_DIR.to_string()]);
+            args.extend(["--chdir", WORK_DIR, "--", "/bin/sh"].map(String::from));
+            args.extend(with_rlimits(limits, argv));
+            args
+        }
+        Isolation::Nsjail => {
+            let mut args: Vec<String> = ["-Mo", "--quiet", "--cwd", WORK_DIR, "-T", "/tmp"]
+                .map(String::from)
+                .to_vec();
+            args.extend(["-E".to_string(), format!("PATH={PATH}")]);
+            args.extend(["-E".to_string(), format!("HOME={WORK_DIR}")]);
+            for (key, value) in env {
+                args.extend(["-E".to_string(), format!("{key}={value}")]);
+            }
+            for dir in SYSTEM_DIRS
+                .iter()
+                .map(PathBuf::from)
+                .chain(binds.iter().cloned())
+            {
+                // nsjail は存在しないパスを指定すると失敗する
+                if dir.exists() {
+                    args.extend(["-R".to_string(), dir.to_string_lossy().into_owned()]);
+                }
+            }
+            for dir in writable {
+                args.extend(["-B".to_string(), dir.to_string_lossy().into_owned()]);
+            }
+            args.extend(["-B".to_string(), format!("{work}:{WORK_DIR}")]);
+            args.extend([
+                "--rlimit_as".to_string(),
+                limits.memory_mb.to_string(),
+                "--rlimit_cpu".to_string(),
+                (limits.timeout.as_secs() + 1).to_string(),
+                "--rlimit_fsize".to_string(),
+                MAX_FILE_MB.to_string(),
+                "--rlimit_nproc".to_string(),
+                limits.max_procs.to_string(),
+                "--time_limit".to_string(),
+                (limits.timeout.as_secs() + 1).to_string(),
+                "--".to_string(),
+            ]);
+            args.extend(argv.iter().cloned());
+            args
+        }
+        Isolation::None | Isolation::Auto => with_rlimits(limits, argv),
+    };
+    let program = match isolation {
+        Isolation::Bwrap => "bwrap",
+        Isolation::Nsjail => "nsjail",
+        Isolation::None | Isolation::Auto => "/bin/sh",
+    };
+    let mut cmd = Command::new(program);
+    cmd.args(args)
+        .current_dir(&work)
+        .env_clear()
+        .env("PATH", PATH)
+        .envs(env.iter().copied());
+    cmd
+}
+
+/// コマンドを実行して、出力と終了ステータスを Piston の形にまとめる
+pub async fn run_command(
+    mut cmd: Command,
+    stdin: Option<&str>,
+    timeout: Duration,
+) -> Result<Stage, String> {
+    cmd.stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| format!("起動に失敗しました: {e}"))?;
+    if let Some(mut pipe) = child.stdin.take() {
+        let input = stdin.unwrap_or("").as_bytes().to_vec();
+        // 読まないプログラムもあるので、書き込みの失敗は無視する
+        tokio::spawn(async move {
+            let _ = pipe.write_all(&input).await;
+        });
+    }
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+    let finish = async {
+        let (stdout, mut stderr, overflowed) = read_capped(stdout, stderr).await;
+        if overflowed {
+            // 上限まで読んだら残りは待たずに止める
+            let _ = child.kill().await;
+            stderr.extend_from_slice("\n出力が多すぎるので止めました\n".as_bytes());
+        }
+        child.wait().await.map(|status| (stdout, stderr, status))
+    };
+    let Ok(finished) = tokio::time::timeout(timeout, finish).await else {
+        return Ok(Stage::timed_out());
+    };
+    let (stdout, stderr, status) = finished.map_err(|e| e.to_string())?;
+    let (stdout, stderr) = (
+        String::from_utf8_lossy(&stdout).into_owned(),
+        String::from_utf8_lossy(&stderr).into_owned(),
+    );
+    #[cfg(unix)]
+    let signal = std::os::unix::process::ExitStatusExt::signal(&status).map(signal_name);
+    #[cfg(not(unix))]
+    let signal = None;
+    Ok(Stage {
+        output: format!("{stdout}{stderr}"),
+        stdout,
+        stderr,
+        code: status.code(),
+        signal,
+    })
+}
+
+/// 標準出力と標準エラーを同時に少しずつ読む。
+/// どちらかが MAX_OUTPUT_BYTES に達したらそこで読むのをやめ、true を返す
+async fn read_capped(
+    stdout: Option<ChildStdout>,
+    stderr: Option<ChildStderr>,
+) -> (Vec<u8>, Vec<u8>, bool) {
+    async fn read(pipe: Option<impl AsyncRead + Unpin>, buf: &mut [u8]) -> usize {
+        match pipe {
+            Some(mut pipe) => pipe.read(buf).await.unwrap_or(0),
+            None => 0,
+        }
+    }
+    let (mut stdout, mut stderr) = (stdout, stderr);
+    let (mut out, mut err) = (Vec::new(), Vec::new());
+    let (mut out_buf, mut err_buf) = ([0u8; 8192], [0u8; 8192]);
+    while stdout.is_some() || stderr.is_some() {
+        tokio::select! {
+            n = read(stdout.as_mut(), &mut out_buf), if stdout.is_some() => match n {
+                0 => stdout = None,
+                n => out.extend_from_slice(&out_buf[..n]),
+            },
+            n = read(stderr.as_mut(), &mut err_buf), if stderr.is_some() => match n {
+                0 => stderr = None,
+                n => err.extend_from_slice(&err_buf[..n]),
+            },
+        }
+        if out.len() >= MAX_OUTPUT_BYTES || err.len() >= MAX_OUTPUT_BYTES {
+            out.truncate(MAX_OUTPUT_BYTES);
+            err.truncate(MAX_OUTPUT_BYTES);
+            return (out, err, true);
+        }
+    }
+    (out, err, false)
+}
+
+/// Piston と同じ "SIGKILL" のような名前
+#[cfg(unix)]
+fn signal_name(signal: i32) -> String {
+    match signal {
+        6 => "SIGABRT".to_string(),
+        8 => "SIGFPE".to_string(),
+        9 => "SIGKILL".to_string(),
+        11 => "SIGSEGV".to_string(),
+        13 => "SIGPIPE".to_string(),
+        15 => "SIGTERM".to_string(),
+        24 => "SIGXCPU".to_string(),
+        25 => "SIGXFSZ".to_string(),
+        n => format!("SIG{n}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unjailed() -> SandboxRunner {
+        SandboxRunner::new(Config {
+            isolation: Isolation::None,
+            ..Config::default()
+        })
+    }
+
+    #[test]
+    fn config_and_limits() {
+        let c = Config::from_lookup(|key| match key {
+            "SANDBOX_TOOL" => Some("nsjail".to_string()),
+            "SANDBOX_RUN_MEMORY_MB" => Some("128".to_string()),
+            "SANDBOX_MAX_PROCS" => Some("8".to_string()),
+            _ => None,
+        });
+        assert_eq!(c.isolation, Isolation::Nsjail);
+        assert_eq!(c.run.memory_mb, 128);
+        assert_eq!(c.compile.max_procs, 8);
+        assert_eq!(Config::from_lookup(|_| None).isolation, Isolation::Auto);
+
+        // リクエストの指定は上限まで
+        let run = c.run.narrow(Some(1500), Some(64 * 1024 * 1024));
+        assert_eq!(
+            (run.timeout, run.memory_mb),
+            (Duration::from_millis(1500), 64)
+        );
+        let run = c.run.narrow(Some(-1), Some(1 << 40));
+        assert_eq!((run.timeout, run.memory_mb), (c.run.timeout, 128));
+    }
+
+    #[test]
+    fn jail_commands() {
+        let limits = Config::default().run;
+        let argv = ["./main".to_string(), "a".to_string()];
+        let args = |cmd: Command| -> Vec<String> {
+            cmd.as_std()
+                .get_args()
+                .map(|a| a.to_string_lossy().into_owned())
+                .collect()
+        };
+
+        let bwrap = jail(
+            Isolation::Bwrap,
+            Path::new("/tmp/x"),
+            &[PathBuf::from("/opt/py")],
+            &[PathBuf::from("/cache/target")],
+            COLOR_ENV,
+            limits,
+            &argv,
+        );
+        assert_eq!(bwrap.as_std().get_program(), "bwrap");
+        let a = args(bwrap);
+        assert!(a.contains(&"--unshare-all".to_string()));
+        assert!(a.windows(3).any(|w| w == ["--bind", "/tmp/x", WORK_DIR]));
+        assert!(
+            a.windows(3)
+                .any(|w| w == ["--ro-bind-try", "/opt/py", "/opt/py"])
+        );
+        assert!(
+            a.windows(3)
+                .any(|w| w == ["--bind", "/cache/target", "/cache/target"])
+        );
+        assert_eq!(a[a.len() - 2..], argv);
+        assert!(a.windows(3).any(|w| w == ["--setenv", "FORCE_COLOR", "1"]));
+
+        let a = args(jail(
+            Isolation::Nsjail,
+            Path::new("/tmp/x"),
+            &[],
+            &[PathBuf::from("/cache/target")],
+            &[],
+            limits,
+            &argv,
+        ));
+        assert!(a.windows(2).any(|w| w == ["--rlimit_as", "512"]));
+        assert!(a.windows(2).any(|w| w == ["-B", "/tmp/x:/work"]));
+        assert!(a.windows(2).any(|w| w == ["-B", "/cache/target"]));
+
+        let a = args(jail(
+            Isolation::None,
+            Path::new("/tmp/x"),
+            &[],
+            &[],
+            &[],
+            limits,
+            &argv,
+        ));
+        assert!(a[1].starts_with("ulimit -v 524288;"));
+    }
+
+    #[tokio::test]
+    async fn runs_python_and_c() {
+        let runner = unjailed();
+        let langs = runner.languages().await.unwrap();
+        if langs.get("py").is_some() {
+            let res = runner
+                .execute_json(json!({
+                    "language": "py",
+                    "files": [{"name": "main.py", "content": "import sys\nprint(input() + sys.argv[1])"}],
+                    "stdin": "hi",
+                    "args": ["!"],
+                }))
+                .await
+                .unwrap();
+            assert_eq!(res["language"], "python");
+            assert_eq!(res["run"]["stdout"], "hi!\n");
+            assert!(res["compile"].is_null());
+        }
+        if langs.get("c").is_some() {
+            let res = runner
+                .execute_json(json!({
+                    "language": "c",
+                    "files": [{"name": "main.c", "content": "int main(void) { return 3; }"}],
+                }))
+                .await
+                .unwrap();
+            assert_eq!(res["compile"]["code"], 0);
+            assert_eq!(res["run"]["code"], 3);
+
+            let res = runner
+                .execute_json(json!({
+                    "language": "c",
+                    "files": [{"name": "main.c", "content": "int main(void) { oops }"}],
+                    "color": true,
+                }))
+                .await
+                .unwrap();
+            assert_ne!(res["compile"]["code"], 0);
+            assert!(res["run"].is_null());
+            // 色付きの診断
+            assert!(res["compile"]["stderr"].as_str().unwrap().contains('\x1b'));
+        }
+
+        let err = runner
+            .execute_json(json!({"language": "cobol", "files": []}))
+            .await
+            .unwrap_err();
+        assert_eq!(err, "cobol はこの実行環境にありません");
+    }
+
+    #[tokio::test]
+    async fn runs_with_stdin_and_timeout() {
+        let stage = run_command(Command::new("cat"), Some("hello"), Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!((stage.stdout.as_str(), stage.code), ("hello", Some(0)));
+
+        let mut sleep = Command::new("sleep");
+        sleep.arg("5");
+        let stage = run_command(sleep, None, Duration::from_millis(100))
+            .await
+            .unwrap();
+        assert_eq!(stage.signal.as_deref(), Some("SIGKILL"));
+
+        // 出力が上限を超えたら、終わるのを待たずに止める
+        let stage = run_command(Command::new("yes"), None, Duration::from_secs(30))
+            .await
+            .unwrap();
+        assert_eq!(stage.stdout.len(), MAX_OUTPUT_BYTES);
+        // 止める前に読むのをやめたパイプで SIGPIPE になることもある
+        assert!(matches!(
+            stage.signal.as_deref(),
+            Some("SIGKILL" | "SIGPIPE")
+        ));
+        assert!(stage.stderr.ends_with("出力が多すぎるので止めました\n"));
+    }
+}
@@ -0,0 +1,3 @@
+// テストで使う道具（複数のモジュールで共有するもの）
+
+pub mod fake_http;
@@ -0,0 +1,117 @@
+// テスト用のプロセス内 HTTP サーバー
+//
+// 本物の API (Piston・Playground 互換 API・LLM API) を叩かずに、
+// リクエスト生成とレスポンス処理を確かめるためのもの。
+// GET は固定の JSON を返し、POST は本文をハンドラに渡してその戻り値を返す。
+// 受け取ったリクエストは記録しておき、requests() で確かめられる。
+// ハンドラが文字列を返したときは JSON にせずそのまま返す（LLM API のストリーミング応答用）。
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+};
+
+use serde_json::{Value, json};
+
+type Handler = dyn Fn(&Value) -> Value + Send + Sync;
+
+/// 受け取ったリクエスト
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub auth: Option<String>,
+    pub body: Value,
+}
+
+pub struct FakeHttp {
+    url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl FakeHttp {
+    /// `get` は GET の応答、`handler` は POST の本文から応答を作る
+    pub fn start(get: Value, handler: impl Fn(&Value) -> Value + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        let log = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // 1接続ずつ処理する（テスト用なので十分）
+                let _ = serve(stream, &get, handler.as_ref(), &log);
+            }
+        });
+
+        Self { url, requests }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn serve(
+    stream: TcpStream,
+    get: &Value,
+    handler: &Handler,
+    log: &Mutex<Vec<Request>>,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let path = parts.next().unwrap_or("").to_string();
+
+    let mut content_length = 0;
+    let mut auth = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            match name.trim().to_lowercase().as_str() {
+                "content-length" => content_length = value.trim().parse().unwrap_or(0),
+                "authorization" => auth = Some(value.trim().to_string()),
+                _ => {}
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+    let (status, response) = match (method.as_str(), path.as_str()) {
+        ("GET", _) => ("200 OK", get.clone()),
+        ("POST", _) => ("200 OK", handler(&body)),
+        _ => ("404 Not Found", json!({"message": "not found"})),
+    };
+    log.lock().unwrap().push(Request {
+        method,
+        path,
+        auth,
+        body,
+    });
+
+    let (content_type, response) = match response {
+        Value::String(s) => ("text/event-stream", s),
+        v => ("application/json", v.to_string()),
+    };
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+        response.len()
+    )?;
+    stream.flush()
+}